@@ -1,28 +1,172 @@
 use crate::api::*;
+use crate::blob::{decode_preview, Blob, BlobRef};
+use crate::cache::{Cache, CacheKind};
+use crate::car::{Car, Commit, Mst};
+use crate::client::{Retry, RetryNotifier, RetryingClient};
+use crate::config::APP_ID;
+use crate::diff::{diff_cars, diff_commit, diff_repos, DiffSource, RepoDiff};
+use crate::local::LocalRepo;
+use crate::plc::{PlcData, PlcLogEntry};
+use crate::snapshots::{self, Snapshot};
 use crate::types::AtUri;
+use crate::verify::{
+    check_blob, check_block, check_commit, check_inclusion, check_plc_log, CidCheck, CommitCheck,
+    InclusionCheck, PlcLogCheck,
+};
 use atrium_api::agent::{store::MemorySessionStore, AtpAgent};
 use atrium_api::did_doc::DidDocument;
 use atrium_api::types::string::{AtIdentifier, Did, Handle, Nsid, RecordKey};
+use atrium_api::xrpc::error::{Error as XrpcFailure, XrpcErrorKind};
 use atrium_api::xrpc::Result as AtResult;
 use atrium_api::*;
-use atrium_xrpc_client::reqwest::ReqwestClient;
+use futures::future::{abortable, AbortHandle};
+use http::StatusCode;
+use ipld_core::cid::Cid as IpldCid;
+use relm4::gtk::{gio, glib};
 use relm4::prelude::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// identifies a single URI lookup, so that progress and results can find their way back to the tab
+// that asked for them
+pub type RequestId = u64;
 
 #[derive(Debug)]
 pub enum AgentInput {
     GetDidDoc(AtIdentifier),
     GetPdsEndpointFor(AtIdentifier),
-    GetURI(AtUri),
+    GetURI(RequestId, AtUri),
+    // looks a URI up in a CAR export that's been opened, rather than online
+    GetLocalURI(RequestId, PathBuf, AtUri),
+    // looks a URI up in a snapshot of its repository instead of its PDS
+    GetURIAsOf(RequestId, AtUri, Snapshot),
+    // one version of a record, from the PDS while it's still current and from a snapshot after
+    GetRecordVersion(RequestId, AtUri, IpldCid),
+    // fetches the whole repository as a CAR file and saves it to the given path
+    DownloadRepo(RequestId, Did, PathBuf),
+    // brings a CAR file saved by DownloadRepo up to date with only what changed since its revision
+    RefreshRepo(RequestId, Did, PathBuf),
+    // the merkle search tree beneath a repository
+    GetMst(RequestId, Did),
+    GetLocalMst(RequestId, PathBuf),
+    // checked against the CAR export at the path instead, if the tab was opened from one
+    CheckCommit(RequestId, Did, Option<PathBuf>),
+    // fetches an inclusion proof for a record with sync.getRecord and checks it, or proves it from
+    // the CAR export at the path
+    ProveRecord(RequestId, AtUri, Option<PathBuf>),
+    // one page of the CIDs of every blob a repository holds, starting from the cursor
+    ListBlobs(RequestId, Did, Option<String>),
+    GetBlob(RequestId, AtIdentifier, BlobRef),
+    // what a PDS says about itself, and whether it's up, given its base URL
+    DescribeServer(RequestId, String),
+    // a handle's DID, for views that filter by repository
+    ResolveHandle(RequestId, Handle),
+    // what changed in a repository, from two exports or from a commit event
+    Diff(RequestId, DiffSource),
+    // reads a CAR export, so that tabs opened from it are answered from the file
+    OpenCar(PathBuf),
+    Opened(Arc<LocalRepo>),
+    // the tab that asked for this lookup has gone away
+    Cancel(RequestId),
+    // a lookup's task is done, told apart by the number spawn gave it
+    Finished(RequestId, u64),
 }
 #[derive(Debug)]
 pub enum AgentOutput {
     DidDoc(DidDocument),
     PdsEndpoint(String),
-    Repo(com::atproto::repo::describe_repo::OutputData),
-    Records(com::atproto::repo::list_records::OutputData),
-    Record(com::atproto::repo::get_record::OutputData),
+    Stage(RequestId, LookupStage),
+    // the result that came before this was read from the cache, at the given unix time
+    Cached(RequestId, i64),
+    // the result that came before this was read from a local CAR export
+    Local(RequestId, PathBuf),
+    // the snapshots kept of the repository being looked at, oldest first
+    Snapshots(RequestId, Vec<Snapshot>),
+    Repo(RequestId, com::atproto::repo::describe_repo::OutputData),
+    Records(RequestId, com::atproto::repo::list_records::OutputData),
+    Record(RequestId, com::atproto::repo::get_record::OutputData),
+    Mst(RequestId, Mst),
+    Server(RequestId, ServerOverview),
+    Diff(RequestId, RepoDiff),
+    PlcData(RequestId, PlcData),
+    PlcLog(RequestId, Vec<PlcLogEntry>),
+    // the audit log replayed independently of plc.directory, and compared with the DID document
+    PlcLogChecked(RequestId, PlcLogCheck),
+    // the record that came before this was checked against its CID using the raw block
+    CidChecked(RequestId, CidCheck),
+    // a lookup failed, and should be shown in the tab that requested it
+    Failed(RequestId, AgentError),
+    Downloaded(RequestId, RepoDownload),
+    // the refreshed file, and the records that changed since it was last saved
+    Refreshed(RequestId, RepoDownload, RepoDiff),
+    DownloadFailed(RequestId, AgentError),
+    CommitChecked(RequestId, CommitCheck),
+    CommitCheckFailed(RequestId, AgentError),
+    RecordProved(RequestId, InclusionCheck),
+    ProofFailed(RequestId, AgentError),
+    // a page of blob CIDs, and the cursor for the next one if there is more
+    Blobs(RequestId, Vec<IpldCid>, Option<String>),
+    BlobsFailed(RequestId, AgentError),
+    Blob(RequestId, Blob),
+    BlobFailed(RequestId, IpldCid, AgentError),
+    Resolved(RequestId, Handle, Did),
+    ResolveFailed(RequestId, Handle, AgentError),
+    // a CAR export has been opened, and its repository can be browsed at this URI
+    Opened(PathBuf, AtUri),
+    Finished(RequestId),
     Error(AgentError),
 }
+
+#[derive(Debug, Clone)]
+pub struct RepoDownload {
+    pub path: PathBuf,
+    // bytes
+    pub size: usize,
+    pub blocks: usize,
+    pub root: String,
+    pub rev: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerOverview {
+    pub url: String,
+    // each is the error as text if that request failed
+    pub description: Result<ServerDescription, String>,
+    pub health: Result<ServerHealth, String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum LookupStage {
+    ResolvingHandle(Handle),
+    FetchingDidDocument(Did),
+    ContactingPds(String),
+    Retrying(Retry),
+    FetchingRecord,
+    ListingRecords,
+    DescribingRepo,
+    DownloadingRepo,
+}
+
+impl std::fmt::Display for LookupStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LookupStage::ResolvingHandle(handle) => {
+                write!(f, "Resolving handle {}…", handle.as_str())
+            }
+            LookupStage::FetchingDidDocument(did) => {
+                write!(f, "Fetching DID document for {}…", did.as_str())
+            }
+            LookupStage::ContactingPds(endpoint) => write!(f, "Contacting PDS at {}…", endpoint),
+            LookupStage::Retrying(retry) => write!(f, "{}…", retry),
+            LookupStage::FetchingRecord => write!(f, "Fetching record…"),
+            LookupStage::ListingRecords => write!(f, "Listing records…"),
+            LookupStage::DescribingRepo => write!(f, "Describing repository…"),
+            LookupStage::DownloadingRepo => write!(f, "Downloading repository…"),
+        }
+    }
+}
 #[derive(Debug)]
 pub enum AgentCommand {}
 
@@ -33,18 +177,208 @@ pub enum AgentError {
     NoDidDoc(String),
     NoPdsEndpointFound(String),
     RecordNotFound(String),
+    // neither the PDS nor a snapshot has the version of a record that was asked for
+    VersionNotFound(String),
+    // a record came back, but not as something that can be shown: a bad URI, or a value that isn't an object
+    InvalidRecord(String),
     RecordsNotFound(String),
     RepoNotFound(String),
+    // the repository exists, but its PDS won't serve it: taken down, suspended or deactivated
+    RepoUnavailable(String),
+    BlobNotFound(String),
+    NotCached(String),
+    // the lookup reached the PDS, which turned it down for a reason other than "not found"
+    Rejected(String),
+    RateLimited(String),
+    ServerError(String),
+    Unreachable(String),
+    InvalidCar(String),
+    Io(String),
+}
+
+impl AgentError {
+    pub fn title(&self) -> &'static str {
+        match self {
+            AgentError::InvalidIdentifier(_) => "Unknown Handle",
+            AgentError::NoDidDoc(_) => "No DID Document",
+            AgentError::NoPdsEndpointFound(_) => "No PDS Found",
+            AgentError::RecordNotFound(_) => "Record Not Found",
+            AgentError::VersionNotFound(_) => "Version Not Available",
+            AgentError::InvalidRecord(_) => "Unreadable Record",
+            AgentError::RecordsNotFound(_) => "Collection Not Found",
+            AgentError::RepoNotFound(_) => "Repository Not Found",
+            AgentError::RepoUnavailable(_) => "Repository Unavailable",
+            AgentError::BlobNotFound(_) => "Blob Not Found",
+            AgentError::NotCached(_) => "Not Available Offline",
+            AgentError::Rejected(_) => "Request Rejected",
+            AgentError::RateLimited(_) => "Rate Limited",
+            AgentError::ServerError(_) => "Server Error",
+            AgentError::Unreachable(_) => "Server Unreachable",
+            AgentError::InvalidCar(_) => "Invalid Repository Export",
+            AgentError::Io(_) => "Couldn't Save File",
+        }
+    }
+}
+
+impl std::fmt::Display for AgentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgentError::InvalidIdentifier(id) => {
+                write!(f, "Couldn't resolve {} to a DID", id)
+            }
+            AgentError::NoDidDoc(context) => {
+                write!(f, "Couldn't fetch the DID document: {}", context)
+            }
+            AgentError::NoPdsEndpointFound(context) => {
+                write!(f, "Couldn't find the PDS hosting this account: {}", context)
+            }
+            AgentError::RecordNotFound(context) => {
+                write!(f, "The PDS couldn't return this record: {}", context)
+            }
+            AgentError::VersionNotFound(context) => write!(
+                f,
+                "Neither the PDS nor any snapshot has this version of the record: {}",
+                context
+            ),
+            AgentError::InvalidRecord(context) => {
+                write!(f, "The record can't be shown: {}", context)
+            }
+            AgentError::RecordsNotFound(context) => {
+                write!(f, "The PDS couldn't list this collection: {}", context)
+            }
+            AgentError::RepoNotFound(context) => {
+                write!(f, "The PDS couldn't describe this repository: {}", context)
+            }
+            AgentError::RepoUnavailable(context) => {
+                write!(f, "The PDS isn't serving this repository: {}", context)
+            }
+            AgentError::BlobNotFound(context) => {
+                write!(f, "The PDS couldn't return this blob: {}", context)
+            }
+            AgentError::Rejected(context) => {
+                write!(f, "The PDS rejected this request: {}", context)
+            }
+            AgentError::RateLimited(context) => {
+                write!(f, "The PDS is still rate limiting requests: {}", context)
+            }
+            AgentError::ServerError(context) => {
+                write!(f, "The PDS kept failing to answer: {}", context)
+            }
+            AgentError::Unreachable(context) => write!(f, "Couldn't reach the PDS: {}", context),
+            AgentError::InvalidCar(context) => {
+                write!(f, "The repository export couldn't be read: {}", context)
+            }
+            AgentError::Io(context) => write!(f, "{}", context),
+            AgentError::NotCached(uri) => {
+                write!(
+                    f,
+                    "{} hasn't been viewed before, and Branches is set to offline only",
+                    uri
+                )
+            }
+        }
+    }
+}
+
+impl AgentError {
+    /*
+    the client has already retried anything transient by the time an error gets here, so this only
+    has to tell an actual "not found" answer apart from everything else. `not_found` picks which
+    not-found variant the caller wants
+    */
+    fn from_xrpc<E>(err: XrpcFailure<E>, not_found: fn(String) -> AgentError) -> AgentError
+    where
+        E: std::fmt::Display,
+        XrpcFailure<E>: std::fmt::Display,
+    {
+        let context = err.to_string();
+        match err {
+            XrpcFailure::XrpcResponse(response) => {
+                let error = match &response.error {
+                    Some(XrpcErrorKind::Custom(error)) => Some(error.to_string()),
+                    Some(XrpcErrorKind::Undefined(body)) => body.error.clone(),
+                    None => None,
+                };
+                match error.as_deref().map(error_name) {
+                    Some(name) if name.ends_with("NotFound") => not_found(context),
+                    None if response.status == StatusCode::NOT_FOUND => not_found(context),
+                    Some(
+                        "RepoTakendown" | "RepoSuspended" | "RepoDeactivated" | "AccountTakedown"
+                        | "AccountDeactivated",
+                    ) => AgentError::RepoUnavailable(context),
+                    _ if response.status == StatusCode::TOO_MANY_REQUESTS => {
+                        AgentError::RateLimited(context)
+                    }
+                    _ if response.status.is_server_error() => AgentError::ServerError(context),
+                    _ => AgentError::Rejected(context),
+                }
+            }
+            XrpcFailure::HttpClient(_) => AgentError::Unreachable(context),
+            _ => AgentError::Rejected(context),
+        }
+    }
+}
+
+// atrium writes a lexicon error as its name, then the server's message after a colon
+fn error_name(error: &str) -> &str {
+    error.split(':').next().unwrap_or_default().trim()
 }
 
 /*
 idea: in the future, we could cache lookups by DID to prevent unnecessary lookups
 */
 pub struct AtprotoAgent {
-    agent: AtpAgent<MemorySessionStore, ReqwestClient>,
+    session: Session,
+    cache: Arc<Cache>,
+    // keeps the cache's settings handler alive
+    _settings: gio::Settings,
+    // lookups that are still running, so that they can be aborted along with their tab
+    lookups: HashMap<RequestId, (u64, AbortHandle)>,
+    // how many tasks have been spawned, which numbers each one
+    spawned: u64,
+    // repositories opened from CAR exports, by the path they were read from
+    local: HashMap<PathBuf, Arc<LocalRepo>>,
 }
 
-impl AtprotoAgent {
+/*
+a session points at a single PDS at a time, so every lookup gets a session of its own. this lets
+lookups run side by side, and dropping one (when it's aborted) drops any XRPC or DID document request
+it had in flight
+*/
+struct Session {
+    agent: AtpAgent<MemorySessionStore, RetryingClient>,
+    // for the requests that go around XRPC, like DID documents and plc.directory
+    notify: RetryNotifier,
+}
+
+impl Session {
+    fn new(notify: RetryNotifier) -> Self {
+        Self {
+            agent: AtpAgent::new(
+                RetryingClient::new("https://bsky.social", notify.clone()),
+                MemorySessionStore::default(),
+            ),
+            notify,
+        }
+    }
+
+    // resolves a handle, keeping "no such handle" apart from a server that didn't answer
+    async fn did_for(&self, repo: &AtIdentifier) -> Result<Did, AgentError> {
+        let handle = match repo {
+            AtIdentifier::Did(did) => return Ok(did.to_owned()),
+            AtIdentifier::Handle(handle) => handle,
+        };
+        self.did_from_handle(handle).await.map_err(|err| {
+            match AgentError::from_xrpc(err, AgentError::InvalidIdentifier) {
+                /* an unknown handle is answered with a 400 rather than a not-found */
+                AgentError::InvalidIdentifier(_) | AgentError::Rejected(_) => {
+                    AgentError::InvalidIdentifier(handle.as_str().to_string())
+                }
+                err => err,
+            }
+        })
+    }
+
     async fn did_from_handle(
         &self,
         handle: &Handle,
@@ -72,6 +406,8 @@ impl AtprotoAgent {
         repo: AtIdentifier,
         collection: Nsid,
         rkey: RecordKey,
+        // the PDS only answers if this is still the record's current version
+        cid: Option<IpldCid>,
     ) -> AtResult<com::atproto::repo::get_record::Output, com::atproto::repo::get_record::Error>
     {
         self.agent
@@ -84,7 +420,7 @@ impl AtprotoAgent {
                     repo,
                     collection,
                     rkey: String::from(rkey),
-                    cid: None,
+                    cid: cid.map(types::string::Cid::new),
                 }
                 .into(),
             )
@@ -128,19 +464,745 @@ impl AtprotoAgent {
             .describe_repo(com::atproto::repo::describe_repo::ParametersData { repo }.into())
             .await
     }
-    async fn set_pds_endpoint_for(&self, repo: &AtIdentifier) -> Result<(), AgentError> {
-        if let Ok(did) = match repo {
-            AtIdentifier::Did(did) => Ok(did.to_owned()),
-            AtIdentifier::Handle(handle) => self.did_from_handle(handle).await,
-        } {
-            match get_pds_endpoint_for(&did).await {
-                Ok(endpoint) => Ok(self.agent.configure_endpoint(endpoint)),
-                Err(err) => Err(AgentError::NoPdsEndpointFound(err.to_string())),
+    async fn set_pds_endpoint_for(
+        &self,
+        repo: &AtIdentifier,
+        cache: &Cache,
+        report: impl Fn(LookupStage),
+    ) -> Result<DidDocument, AgentError> {
+        /* everything after this needs the network */
+        if cache.offline_only() {
+            return Err(AgentError::NotCached(format!(
+                "the PDS of {}",
+                String::from(repo.to_owned())
+            )));
+        }
+        if let AtIdentifier::Handle(handle) = repo {
+            report(LookupStage::ResolvingHandle(handle.to_owned()));
+        }
+        let did = self.did_for(repo).await?;
+        report(LookupStage::FetchingDidDocument(did.to_owned()));
+        let did_doc = match get_did_doc_for(&did, &self.notify).await {
+            Ok(did_doc) => did_doc,
+            Err(err) => return Err(AgentError::NoPdsEndpointFound(err.to_string())),
+        };
+        cache.put(CacheKind::DidDoc, did.as_str(), &did_doc);
+        match did_doc.get_pds_endpoint() {
+            Some(endpoint) => {
+                report(LookupStage::ContactingPds(endpoint.clone()));
+                self.agent.configure_endpoint(endpoint);
+                Ok(did_doc)
             }
+            None => Err(AgentError::NoPdsEndpointFound(String::from(
+                "no PDS endpoint found",
+            ))),
+        }
+    }
+
+    /*
+    anything that has been looked up before is shown from the cache straight away. unless Branches is
+    offline only, the lookup then carries on over the network and replaces the cached copy; if that
+    fails, the cached copy stays up and the failure is only reported in passing
+    */
+    async fn lookup(
+        &self,
+        id: RequestId,
+        uri: AtUri,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        let report = |stage| {
+            output.emit(AgentOutput::Stage(id, stage));
+        };
+        let key = uri.to_string();
+        let cached_at = match (&uri.collection, &uri.rkey) {
+            (Some(_), Some(_)) => cache.get(CacheKind::Record, &key).map(|cached| {
+                output.emit(AgentOutput::Record(id, cached.value));
+                cached.cached_at
+            }),
+            (Some(_), None) => cache.get(CacheKind::Records, &key).map(|cached| {
+                output.emit(AgentOutput::Records(id, cached.value));
+                cached.cached_at
+            }),
+            (None, _) => cache.get(CacheKind::Repo, &key).map(|cached| {
+                output.emit(AgentOutput::Repo(id, cached.value));
+                cached.cached_at
+            }),
+        };
+        if let Some(cached_at) = cached_at {
+            output.emit(AgentOutput::Cached(id, cached_at));
+        }
+        if cache.offline_only() {
+            if cached_at.is_none() {
+                output.emit(AgentOutput::Failed(id, AgentError::NotCached(key)));
+            }
+            return;
+        }
+
+        let fail = |err| match cached_at {
+            None => output.emit(AgentOutput::Failed(id, err)),
+            Some(_) => output.emit(AgentOutput::Error(err)),
+        };
+        match self
+            .set_pds_endpoint_for(&uri.authority, cache, &report)
+            .await
+        {
+            Err(err) => fail(err),
+            Ok(did_doc) => {
+                /* a DID's snapshots were already listed before the lookup started */
+                if let AtIdentifier::Handle(_) = &uri.authority {
+                    emit_snapshots(id, &did_doc.id, output);
+                }
+                match (uri.authority, uri.collection, uri.rkey) {
+                    (repo, Some(collection), Some(rkey)) => {
+                        report(LookupStage::FetchingRecord);
+                        match self.get_record(repo, collection, rkey, None).await {
+                            Ok(record) => {
+                                cache.put(CacheKind::Record, &key, &record.data);
+                                output.emit(AgentOutput::Record(id, record.data))
+                            }
+                            Err(err) => {
+                                fail(AgentError::from_xrpc(err, AgentError::RecordNotFound))
+                            }
+                        };
+                    }
+                    (repo, Some(collection), None) => {
+                        report(LookupStage::ListingRecords);
+                        match self.list_records(repo, collection).await {
+                            Ok(records) => {
+                                cache.put(CacheKind::Records, &key, &records.data);
+                                output.emit(AgentOutput::Records(id, records.data))
+                            }
+                            Err(err) => {
+                                fail(AgentError::from_xrpc(err, AgentError::RecordsNotFound))
+                            }
+                        };
+                    }
+                    (repo, None, _) => {
+                        report(LookupStage::DescribingRepo);
+                        match self.describe_repo(repo).await {
+                            Ok(repo) => {
+                                cache.put(CacheKind::Repo, &key, &repo.data);
+                                let did = repo.data.did.clone();
+                                output.emit(AgentOutput::Repo(id, repo.data));
+                                /* the rotation keys aren't in the DID document, only in the PLC data */
+                                if did.method() == "did:plc" {
+                                    match get_plc_data_for(&did, &self.notify).await {
+                                        Ok(data) => output.emit(AgentOutput::PlcData(id, data)),
+                                        Err(err) => tracing::warn!(
+                                            "couldn't fetch PLC data for {}: {}",
+                                            did.as_str(),
+                                            err
+                                        ),
+                                    }
+                                    match get_plc_audit_log_for(&did, &self.notify).await {
+                                        Ok(log) => {
+                                            let check = check_plc_log(did.as_str(), &log, &did_doc);
+                                            output.emit(AgentOutput::PlcLog(id, log));
+                                            output.emit(AgentOutput::PlcLogChecked(id, check));
+                                        }
+                                        Err(err) => tracing::warn!(
+                                            "couldn't fetch the PLC audit log for {}: {}",
+                                            did.as_str(),
+                                            err
+                                        ),
+                                    }
+                                }
+                            }
+                            Err(err) => fail(AgentError::from_xrpc(err, AgentError::RepoNotFound)),
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    async fn get_repo(
+        &self,
+        did: Did,
+        // only the blocks added after this revision
+        since: Option<String>,
+        cache: &Cache,
+        report: impl Fn(LookupStage),
+    ) -> Result<Vec<u8>, AgentError> {
+        if cache.offline_only() {
+            return Err(AgentError::NotCached(format!(
+                "the repository of {}",
+                did.as_str()
+            )));
+        }
+        self.set_pds_endpoint_for(&AtIdentifier::Did(did.clone()), cache, &report)
+            .await?;
+        report(LookupStage::DownloadingRepo);
+        self.agent
+            .api
+            .com
+            .atproto
+            .sync
+            .get_repo(com::atproto::sync::get_repo::ParametersData { did, since }.into())
+            .await
+            .map_err(|err| AgentError::from_xrpc(err, AgentError::RepoNotFound))
+    }
+
+    /*
+    a version of a record is only as easy to get as the cache or the PDS make it while it's current.
+    once it has been replaced, the only copies left are in snapshots, which are searched newest first
+    */
+    async fn lookup_version(
+        &self,
+        id: RequestId,
+        uri: AtUri,
+        cid: IpldCid,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        let report = |stage| {
+            output.emit(AgentOutput::Stage(id, stage));
+        };
+        let (Some(collection), Some(rkey)) = (uri.collection.clone(), uri.rkey.clone()) else {
+            output.emit(AgentOutput::Failed(
+                id,
+                AgentError::RecordNotFound(uri.to_string()),
+            ));
+            return;
+        };
+        /*
+        versions are cached under their own key, since the plain URI is what lookups (and diffs of
+        commit events) take to be the current record
+        */
+        let key = format!("{}#{}", uri, cid);
+        let is_version = |record: &com::atproto::repo::get_record::OutputData| {
+            record.cid.as_ref().map(|cid| *cid.as_ref()) == Some(cid)
+        };
+        if let Some(cached) = [key.clone(), uri.to_string()].iter().find_map(|key| {
+            cache
+                .get::<com::atproto::repo::get_record::OutputData>(CacheKind::Record, key)
+                .filter(|cached| is_version(&cached.value))
+        }) {
+            output.emit(AgentOutput::Record(id, cached.value));
+            output.emit(AgentOutput::Cached(id, cached.cached_at));
+            return;
+        }
+
+        let mut did = match &uri.authority {
+            AtIdentifier::Did(did) => Some(did.as_str().to_string()),
+            AtIdentifier::Handle(_) => None,
+        };
+        let missing = if cache.offline_only() {
+            String::from("Branches is set to offline only")
         } else {
-            Err(AgentError::InvalidIdentifier(String::from(repo.to_owned())))
+            match self
+                .set_pds_endpoint_for(&uri.authority, cache, &report)
+                .await
+            {
+                Err(err) => err.to_string(),
+                Ok(did_doc) => {
+                    did = Some(did_doc.id);
+                    report(LookupStage::FetchingRecord);
+                    match self
+                        .get_record(
+                            uri.authority.clone(),
+                            collection.clone(),
+                            rkey.clone(),
+                            Some(cid),
+                        )
+                        .await
+                    {
+                        Ok(record) if is_version(&record.data) => {
+                            cache.put(CacheKind::Record, &key, &record.data);
+                            output.emit(AgentOutput::Record(id, record.data));
+                            return;
+                        }
+                        /* not every PDS pays attention to `cid`, some send whatever is current */
+                        Ok(record) => match record.data.cid {
+                            Some(current) => {
+                                format!("the PDS has moved on to {}", current.as_ref())
+                            }
+                            None => String::from("the PDS sent a record without a CID"),
+                        },
+                        Err(err) => {
+                            AgentError::from_xrpc(err, AgentError::RecordNotFound).to_string()
+                        }
+                    }
+                }
+            }
+        };
+
+        let Some(did) = did else {
+            output.emit(AgentOutput::Failed(
+                id,
+                AgentError::VersionNotFound(missing),
+            ));
+            return;
+        };
+        let record_key = format!("{}/{}", collection.as_str(), rkey.as_str());
+        let found = relm4::spawn_blocking(move || {
+            snapshots::list(&did)
+                .into_iter()
+                .rev()
+                .find_map(|snapshot| {
+                    LocalRepo::open(snapshot.path).ok().and_then(|repo| {
+                        repo.version(&record_key, &cid)
+                            .map(|record| (record, repo.path))
+                    })
+                })
+        })
+        .await;
+        match found {
+            Ok(Some((record, path))) => {
+                output.emit(AgentOutput::Record(id, record));
+                output.emit(AgentOutput::Local(id, path));
+            }
+            Ok(None) => output.emit(AgentOutput::Failed(
+                id,
+                AgentError::VersionNotFound(missing),
+            )),
+            Err(err) => output.emit(AgentOutput::Failed(id, AgentError::Io(err.to_string()))),
         }
     }
+
+    async fn download_repo(
+        &self,
+        id: RequestId,
+        did: Did,
+        path: PathBuf,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        let owner = did.as_str().to_string();
+        let bytes = match self.get_repo(did, None, cache, |_| ()).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                output.emit(AgentOutput::DownloadFailed(id, err));
+                return;
+            }
+        };
+        let summary = Car::from_bytes(&bytes).and_then(|car| {
+            let commit = car.commit()?;
+            Ok(RepoDownload {
+                path: path.clone(),
+                size: bytes.len(),
+                blocks: car.blocks.len(),
+                root: car.root()?.to_string(),
+                rev: commit.rev,
+            })
+        });
+        match summary {
+            Ok(summary) => match std::fs::write(&path, &bytes) {
+                Ok(()) => {
+                    keep_snapshot(&owner, &summary.rev, &bytes);
+                    output.emit(AgentOutput::Downloaded(id, summary))
+                }
+                Err(err) => output.emit(AgentOutput::DownloadFailed(
+                    id,
+                    AgentError::Io(format!("Couldn't write {}: {}", path.display(), err)),
+                )),
+            },
+            Err(err) => output.emit(AgentOutput::DownloadFailed(
+                id,
+                AgentError::InvalidCar(err.to_string()),
+            )),
+        }
+    }
+
+    /*
+    reads the saved export, asks for the blocks that arrived after its revision and merges them in. the
+    file is only overwritten once the merged copy has been checked to be a whole repository, so a
+    refresh that goes wrong leaves the old export as it was
+    */
+    async fn refresh_repo(
+        &self,
+        id: RequestId,
+        did: Did,
+        path: PathBuf,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        // reading, parsing, merging and diffing a whole repository all block, so none runs here
+        let read = {
+            let did = did.clone();
+            let path = path.clone();
+            relm4::spawn_blocking(move || read_export(&did, &path)).await
+        };
+        let (old_bytes, old_commit, old) = match read {
+            Ok(Ok(old)) => old,
+            Ok(Err(err)) => {
+                output.emit(AgentOutput::DownloadFailed(id, err));
+                return;
+            }
+            Err(err) => {
+                output.emit(AgentOutput::DownloadFailed(
+                    id,
+                    AgentError::Io(err.to_string()),
+                ));
+                return;
+            }
+        };
+        let bytes = match self
+            .get_repo(did, Some(old_commit.rev.clone()), cache, |_| ())
+            .await
+        {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                output.emit(AgentOutput::DownloadFailed(id, err));
+                return;
+            }
+        };
+        let refreshed = relm4::spawn_blocking(move || {
+            merge_export(&path, &old_bytes, &old_commit, &old, &bytes)
+        })
+        .await;
+        match refreshed {
+            Ok(Ok((download, changes))) => {
+                output.emit(AgentOutput::Refreshed(id, download, changes))
+            }
+            Ok(Err(err)) => output.emit(AgentOutput::DownloadFailed(id, err)),
+            Err(err) => output.emit(AgentOutput::DownloadFailed(
+                id,
+                AgentError::Io(err.to_string()),
+            )),
+        }
+    }
+
+    // checks the signature on the repository's latest commit against the DID's signing key
+    async fn check_commit(
+        &self,
+        id: RequestId,
+        did: Did,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        match self.latest_commit(did, cache).await {
+            Ok(check) => output.emit(AgentOutput::CommitChecked(id, check)),
+            Err(err) => output.emit(AgentOutput::CommitCheckFailed(id, err)),
+        }
+    }
+
+    async fn latest_commit(&self, did: Did, cache: &Cache) -> Result<CommitCheck, AgentError> {
+        let did_doc = self
+            .set_pds_endpoint_for(&AtIdentifier::Did(did.clone()), cache, |_| ())
+            .await?;
+        let latest = self
+            .agent
+            .api
+            .com
+            .atproto
+            .sync
+            .get_latest_commit(
+                com::atproto::sync::get_latest_commit::ParametersData { did: did.clone() }.into(),
+            )
+            .await
+            .map_err(|err| AgentError::from_xrpc(err, AgentError::RepoNotFound))?;
+        let blocks = self
+            .agent
+            .api
+            .com
+            .atproto
+            .sync
+            .get_blocks(
+                com::atproto::sync::get_blocks::ParametersData {
+                    did,
+                    cids: vec![latest.data.cid.clone()],
+                }
+                .into(),
+            )
+            .await
+            .map_err(|err| AgentError::from_xrpc(err, AgentError::RepoNotFound))?;
+        let cid = *latest.data.cid.as_ref();
+        let car =
+            Car::from_bytes(&blocks).map_err(|err| AgentError::InvalidCar(err.to_string()))?;
+        let block = car
+            .block(&cid)
+            .map_err(|err| AgentError::InvalidCar(err.to_string()))?;
+        if let check @ (CidCheck::Mismatch(_) | CidCheck::Unverifiable(_)) =
+            check_block(&cid, block)
+        {
+            return Err(AgentError::InvalidCar(format!("commit block: {}", check)));
+        }
+        let commit = car
+            .decode(&cid)
+            .map_err(|err| AgentError::InvalidCar(err.to_string()))?;
+        Ok(check_commit(cid, &commit, &did_doc))
+    }
+
+    async fn prove_record(
+        &self,
+        id: RequestId,
+        uri: AtUri,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        match self.inclusion_proof(uri, cache).await {
+            Ok(check) => output.emit(AgentOutput::RecordProved(id, check)),
+            Err(err) => output.emit(AgentOutput::ProofFailed(id, err)),
+        }
+    }
+
+    async fn inclusion_proof(
+        &self,
+        uri: AtUri,
+        cache: &Cache,
+    ) -> Result<InclusionCheck, AgentError> {
+        let (Some(collection), Some(rkey)) = (&uri.collection, &uri.rkey) else {
+            return Err(AgentError::RecordNotFound(uri.to_string()));
+        };
+        let did_doc = self
+            .set_pds_endpoint_for(&uri.authority, cache, |_| ())
+            .await?;
+        let did = did_doc
+            .id
+            .parse::<Did>()
+            .map_err(|_| AgentError::InvalidIdentifier(did_doc.id.clone()))?;
+        let proof = self
+            .agent
+            .api
+            .com
+            .atproto
+            .sync
+            .get_record(
+                com::atproto::sync::get_record::ParametersData {
+                    collection: collection.clone(),
+                    did,
+                    rkey: String::from(rkey.clone()),
+                }
+                .into(),
+            )
+            .await
+            .map_err(|err| AgentError::from_xrpc(err, AgentError::RecordNotFound))?;
+        let car = Car::from_bytes(&proof).map_err(|err| AgentError::InvalidCar(err.to_string()))?;
+        Ok(check_inclusion(
+            &car,
+            &did_doc,
+            &format!("{}/{}", collection.as_str(), rkey.as_str()),
+        ))
+    }
+
+    // the repository's tree, from a fresh export since there is nowhere else to get it from
+    async fn mst(
+        &self,
+        id: RequestId,
+        did: Did,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        let report = |stage| {
+            output.emit(AgentOutput::Stage(id, stage));
+        };
+        let mst = self
+            .get_repo(did, None, cache, report)
+            .await
+            .and_then(|bytes| {
+                Car::from_bytes(&bytes)
+                    .and_then(|car| car.mst())
+                    .map_err(|err| AgentError::InvalidCar(err.to_string()))
+            });
+        match mst {
+            Ok(mst) => output.emit(AgentOutput::Mst(id, mst)),
+            Err(err) => output.emit(AgentOutput::Failed(id, err)),
+        }
+    }
+
+    async fn list_blobs(
+        &self,
+        did: Did,
+        cursor: Option<String>,
+        cache: &Cache,
+    ) -> Result<(Vec<IpldCid>, Option<String>), AgentError> {
+        if cache.offline_only() {
+            return Err(AgentError::NotCached(format!(
+                "the blobs of {}",
+                did.as_str()
+            )));
+        }
+        self.set_pds_endpoint_for(&AtIdentifier::Did(did.clone()), cache, |_| ())
+            .await?;
+        let page = self
+            .agent
+            .api
+            .com
+            .atproto
+            .sync
+            .list_blobs(
+                com::atproto::sync::list_blobs::ParametersData {
+                    cursor,
+                    did,
+                    limit: None,
+                    since: None,
+                }
+                .into(),
+            )
+            .await
+            .map_err(|err| AgentError::from_xrpc(err, AgentError::RepoNotFound))?;
+        Ok((
+            page.data.cids.iter().map(|cid| *cid.as_ref()).collect(),
+            page.data.cursor,
+        ))
+    }
+
+    /* blobs are never in a CAR export, so even a repository opened from a file fetches them from its PDS */
+    async fn get_blob(
+        &self,
+        repo: AtIdentifier,
+        reference: BlobRef,
+        cache: &Cache,
+    ) -> Result<Blob, AgentError> {
+        let cid = reference.cid.to_string();
+        /* a handle has to be resolved before the cache can be asked, so only DIDs skip the network */
+        if let AtIdentifier::Did(did) = &repo {
+            if let Some(cached) = cache.blob(did.as_str(), &cid) {
+                return open_blob(reference, cached.value).await;
+            }
+        }
+        if cache.offline_only() {
+            return Err(AgentError::NotCached(cid));
+        }
+        let did_doc = self.set_pds_endpoint_for(&repo, cache, |_| ()).await?;
+        let did = did_doc
+            .id
+            .parse::<Did>()
+            .map_err(|_| AgentError::InvalidIdentifier(did_doc.id.clone()))?;
+        if let Some(cached) = cache.blob(did.as_str(), &cid) {
+            return open_blob(reference, cached.value).await;
+        }
+        let owner = did.as_str().to_string();
+        let bytes = self
+            .agent
+            .api
+            .com
+            .atproto
+            .sync
+            .get_blob(
+                com::atproto::sync::get_blob::ParametersData {
+                    cid: atrium_api::types::string::Cid::new(reference.cid),
+                    did,
+                }
+                .into(),
+            )
+            .await
+            .map_err(|err| AgentError::from_xrpc(err, AgentError::BlobNotFound))?;
+        let blob = open_blob(reference, bytes).await?;
+        /* only bytes that are what the CID says are worth keeping */
+        if let CidCheck::Verified = blob.check.cid {
+            cache.put_blob(&owner, &cid, &blob.bytes);
+        }
+        Ok(blob)
+    }
+}
+
+// hashing and decoding a large blob both take a while, so neither happens on the runtime
+async fn open_blob(reference: BlobRef, bytes: Vec<u8>) -> Result<Blob, AgentError> {
+    relm4::spawn_blocking(move || {
+        let bytes = glib::Bytes::from_owned(bytes);
+        Blob {
+            check: check_blob(&reference, &bytes),
+            preview: decode_preview(&reference, &bytes),
+            reference,
+            bytes,
+        }
+    })
+    .await
+    .map_err(|err| AgentError::Io(err.to_string()))
+}
+
+impl AtprotoAgent {
+    // runs a lookup off the main thread, keeping hold of it so that it can be cancelled
+    fn spawn(
+        &mut self,
+        id: RequestId,
+        sender: &AsyncComponentSender<Self>,
+        task: impl Future<Output = ()> + Send + 'static,
+    ) {
+        let input = sender.input_sender().clone();
+        self.spawned += 1;
+        let number = self.spawned;
+        let (task, handle) = abortable(async move {
+            task.await;
+            input.emit(AgentInput::Finished(id, number));
+        });
+        relm4::spawn(task);
+        if let Some((_, previous)) = self.lookups.insert(id, (number, handle)) {
+            previous.abort();
+        }
+    }
+}
+
+// a saved export, checked to be of the repository it's about to be refreshed from
+fn read_export(did: &Did, path: &Path) -> Result<(Vec<u8>, Commit, Car), AgentError> {
+    let bytes = std::fs::read(path)
+        .map_err(|err| AgentError::Io(format!("Couldn't read {}: {}", path.display(), err)))?;
+    let car = Car::from_bytes(&bytes).map_err(|err| AgentError::InvalidCar(err.to_string()))?;
+    let commit = car
+        .commit()
+        .map_err(|err| AgentError::InvalidCar(err.to_string()))?;
+    if commit.did != did.as_str() {
+        return Err(AgentError::InvalidCar(format!(
+            "{} is an export of {}, not {}",
+            path.display(),
+            commit.did,
+            did.as_str()
+        )));
+    }
+    Ok((bytes, commit, car))
+}
+
+// lays what getRepo returned since the old revision over the saved export, and saves the result
+fn merge_export(
+    path: &Path,
+    old_bytes: &[u8],
+    old_commit: &Commit,
+    old: &Car,
+    bytes: &[u8],
+) -> Result<(RepoDownload, RepoDiff), AgentError> {
+    let invalid = |err: crate::car::CarError| AgentError::InvalidCar(err.to_string());
+    let mut merged = Car::from_bytes(old_bytes).map_err(invalid)?;
+    merged
+        .merge(Car::from_bytes(bytes).map_err(invalid)?)
+        .map_err(invalid)?;
+    let merged_bytes = merged.to_bytes().map_err(invalid)?;
+    let changes = diff_repos(old, &merged)?;
+    let download = RepoDownload {
+        path: path.to_path_buf(),
+        size: merged_bytes.len(),
+        blocks: merged.blocks.len(),
+        root: merged.root().map_err(invalid)?.to_string(),
+        rev: changes.to.clone(),
+    };
+    std::fs::write(path, &merged_bytes)
+        .map_err(|err| AgentError::Io(format!("Couldn't write {}: {}", path.display(), err)))?;
+    keep_snapshot(&old_commit.did, &download.rev, &merged_bytes);
+    Ok((download, changes))
+}
+
+fn keep_snapshot(did: &str, rev: &str, bytes: &[u8]) {
+    if let Err(err) = snapshots::save(did, rev, bytes) {
+        tracing::warn!("couldn't keep a snapshot of {} at {}: {}", did, rev, err);
+    }
+}
+
+fn emit_snapshots(id: RequestId, did: &str, output: &relm4::Sender<AgentOutput>) {
+    let snapshots = snapshots::list(did);
+    if !snapshots.is_empty() {
+        output.emit(AgentOutput::Snapshots(id, snapshots));
+    }
+}
+
+// an export that's already been opened, or else read from disk just for this lookup
+async fn local_repo(
+    opened: Option<Arc<LocalRepo>>,
+    path: PathBuf,
+) -> Result<Arc<LocalRepo>, AgentError> {
+    if let Some(repo) = opened {
+        return Ok(repo);
+    }
+    match relm4::spawn_blocking(move || LocalRepo::open(path)).await {
+        Ok(repo) => repo.map(Arc::new),
+        Err(err) => Err(AgentError::InvalidCar(err.to_string())),
+    }
+}
+
+pub fn notifier(id: RequestId, output: &relm4::Sender<AgentOutput>) -> RetryNotifier {
+    let output = output.clone();
+    Arc::new(move |retry| output.emit(AgentOutput::Stage(id, LookupStage::Retrying(retry))))
 }
 
 impl AsyncComponent for AtprotoAgent {
@@ -158,11 +1220,15 @@ impl AsyncComponent for AtprotoAgent {
         _root: Self::Root,
         _sender: AsyncComponentSender<Self>,
     ) -> AsyncComponentParts<Self> {
-        let agent = AtpAgent::new(
-            ReqwestClient::new("https://bsky.social"),
-            MemorySessionStore::default(),
-        );
-        let model = Self { agent };
+        let settings = gio::Settings::new(APP_ID);
+        let model = Self {
+            session: Session::new(Arc::new(|_: Retry| ())),
+            cache: Cache::from_settings(&settings),
+            _settings: settings,
+            lookups: HashMap::new(),
+            spawned: 0,
+            local: HashMap::new(),
+        };
 
         AsyncComponentParts { model, widgets: () }
     }
@@ -175,71 +1241,287 @@ impl AsyncComponent for AtprotoAgent {
     ) {
         match message {
             AgentInput::GetDidDoc(id) => {
-                let identifier = id.clone();
-                if let Ok(did) = match id {
-                    AtIdentifier::Did(did) => Ok(did),
-                    AtIdentifier::Handle(handle) => self.did_from_handle(&handle).await,
-                } {
-                    match get_did_doc_for(&did).await {
-                        Ok(did_doc) => sender.output(AgentOutput::DidDoc(did_doc)),
+                match self.session.did_for(&id).await {
+                    Ok(did) => match get_did_doc_for(&did, &self.session.notify).await {
+                        Ok(did_doc) => {
+                            self.cache.put(CacheKind::DidDoc, did.as_str(), &did_doc);
+                            sender.output(AgentOutput::DidDoc(did_doc))
+                        }
                         Err(err) => {
                             sender.output(AgentOutput::Error(AgentError::NoDidDoc(err.to_string())))
                         }
-                    };
-                } else {
-                    sender.output(AgentOutput::Error(AgentError::InvalidIdentifier(
-                        String::from(identifier),
-                    )));
+                    },
+                    Err(err) => sender.output(AgentOutput::Error(err)),
                 };
             }
             AgentInput::GetPdsEndpointFor(id) => {
-                let identifier = id.clone();
-                if let Ok(did) = match id {
-                    AtIdentifier::Did(did) => Ok(did),
-                    AtIdentifier::Handle(handle) => self.did_from_handle(&handle).await,
-                } {
-                    match get_pds_endpoint_for(&did).await {
+                match self.session.did_for(&id).await {
+                    Ok(did) => match get_pds_endpoint_for(&did, &self.session.notify).await {
                         Ok(endpoint) => sender.output(AgentOutput::PdsEndpoint(endpoint)),
                         Err(err) => sender.output(AgentOutput::Error(
                             AgentError::NoPdsEndpointFound(err.to_string()),
                         )),
-                    };
-                } else {
-                    sender.output(AgentOutput::Error(AgentError::InvalidIdentifier(
-                        String::from(identifier),
-                    )));
+                    },
+                    Err(err) => sender.output(AgentOutput::Error(err)),
                 };
             }
-            AgentInput::GetURI(uri) => {
-                if let Err(err) = self.set_pds_endpoint_for(&uri.authority).await {
-                    sender.output(AgentOutput::Error(err));
-                } else {
-                    match (uri.authority, uri.collection, uri.rkey) {
-                        (repo, Some(collection), Some(rkey)) => {
-                            match self.get_record(repo, collection, rkey).await {
-                                Ok(record) => sender.output(AgentOutput::Record(record.data)),
-                                Err(err) => sender.output(AgentOutput::Error(
-                                    AgentError::RecordNotFound(err.to_string()),
-                                )),
-                            };
-                        }
-                        (repo, Some(collection), None) => {
-                            match self.list_records(repo, collection).await {
-                                Ok(records) => sender.output(AgentOutput::Records(records.data)),
-                                Err(err) => sender.output(AgentOutput::Error(
-                                    AgentError::RecordsNotFound(err.to_string()),
-                                )),
-                            };
-                        }
-                        (repo, None, _) => {
-                            match self.describe_repo(repo).await {
-                                Ok(repo) => sender.output(AgentOutput::Repo(repo.data)),
-                                Err(err) => sender.output(AgentOutput::Error(
-                                    AgentError::RepoNotFound(err.to_string()),
-                                )),
-                            };
-                        }
+            AgentInput::GetURI(id, uri) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                if let AtIdentifier::Did(did) = &uri.authority {
+                    emit_snapshots(id, did.as_str(), &output);
+                }
+                self.spawn(id, &sender, async move {
+                    Session::new(notifier(id, &output))
+                        .lookup(id, uri, &cache, &output)
+                        .await;
+                });
+            }
+            AgentInput::GetLocalURI(id, path, uri) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                let opened = self.local.get(&path).cloned();
+                self.spawn(id, &sender, async move {
+                    match local_repo(opened, path).await {
+                        Ok(repo) => repo.lookup(id, uri, &cache, &output),
+                        Err(err) => output.emit(AgentOutput::Failed(id, err)),
                     }
+                });
+            }
+            AgentInput::GetURIAsOf(id, uri, snapshot) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    emit_snapshots(id, &snapshot.did, &output);
+                    match local_repo(None, snapshot.path).await {
+                        Ok(repo) => repo.lookup(id, uri, &cache, &output),
+                        Err(err) => output.emit(AgentOutput::Failed(id, err)),
+                    }
+                });
+            }
+            AgentInput::GetRecordVersion(id, uri, cid) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    Session::new(notifier(id, &output))
+                        .lookup_version(id, uri, cid, &cache, &output)
+                        .await;
+                });
+            }
+            AgentInput::DescribeServer(id, url) => {
+                let output = sender.output_sender().clone();
+                let offline_only = self.cache.offline_only();
+                self.spawn(id, &sender, async move {
+                    if offline_only {
+                        output.emit(AgentOutput::Failed(id, AgentError::NotCached(url)));
+                        return;
+                    }
+                    /* the errors are boxed without Send, so they're turned into text before joining */
+                    let (description, health) = futures::join!(
+                        async { describe_server(&url).await.map_err(|err| err.to_string()) },
+                        async { server_health(&url).await.map_err(|err| err.to_string()) }
+                    );
+                    let overview = ServerOverview {
+                        description,
+                        health,
+                        url,
+                    };
+                    /* a server that answers neither is as good as not there */
+                    match (&overview.description, &overview.health) {
+                        (Err(err), Err(_)) => output.emit(AgentOutput::Failed(
+                            id,
+                            AgentError::Unreachable(format!("{}: {}", overview.url, err)),
+                        )),
+                        _ => output.emit(AgentOutput::Server(id, overview)),
+                    }
+                });
+            }
+            AgentInput::ResolveHandle(id, handle) => {
+                let output = sender.output_sender().clone();
+                let offline_only = self.cache.offline_only();
+                self.spawn(id, &sender, async move {
+                    if offline_only {
+                        output.emit(AgentOutput::ResolveFailed(
+                            id,
+                            handle.clone(),
+                            AgentError::NotCached(handle.as_str().to_string()),
+                        ));
+                        return;
+                    }
+                    let identifier = AtIdentifier::Handle(handle.clone());
+                    match Session::new(notifier(id, &output))
+                        .did_for(&identifier)
+                        .await
+                    {
+                        Ok(did) => output.emit(AgentOutput::Resolved(id, handle, did)),
+                        Err(err) => output.emit(AgentOutput::ResolveFailed(id, handle, err)),
+                    }
+                });
+            }
+            AgentInput::DownloadRepo(id, did, path) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    Session::new(notifier(id, &output))
+                        .download_repo(id, did, path, &cache, &output)
+                        .await;
+                });
+            }
+            AgentInput::RefreshRepo(id, did, path) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    Session::new(notifier(id, &output))
+                        .refresh_repo(id, did, path, &cache, &output)
+                        .await;
+                });
+            }
+            AgentInput::GetMst(id, did) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    Session::new(notifier(id, &output))
+                        .mst(id, did, &cache, &output)
+                        .await;
+                });
+            }
+            AgentInput::GetLocalMst(id, path) => {
+                let output = sender.output_sender().clone();
+                let opened = self.local.get(&path).cloned();
+                self.spawn(id, &sender, async move {
+                    match local_repo(opened, path).await {
+                        Ok(repo) => repo.mst(id, &output),
+                        Err(err) => output.emit(AgentOutput::Failed(id, err)),
+                    }
+                });
+            }
+            AgentInput::CheckCommit(id, did, None) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    Session::new(notifier(id, &output))
+                        .check_commit(id, did, &cache, &output)
+                        .await;
+                });
+            }
+            AgentInput::CheckCommit(id, _, Some(path)) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                let opened = self.local.get(&path).cloned();
+                self.spawn(id, &sender, async move {
+                    match local_repo(opened, path).await {
+                        Ok(repo) => repo.check_commit(id, &cache, &output).await,
+                        Err(err) => output.emit(AgentOutput::CommitCheckFailed(id, err)),
+                    }
+                });
+            }
+            AgentInput::ProveRecord(id, uri, None) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    Session::new(notifier(id, &output))
+                        .prove_record(id, uri, &cache, &output)
+                        .await;
+                });
+            }
+            AgentInput::ProveRecord(id, uri, Some(path)) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                let opened = self.local.get(&path).cloned();
+                self.spawn(id, &sender, async move {
+                    match local_repo(opened, path).await {
+                        Ok(repo) => repo.prove_record(id, uri, &cache, &output).await,
+                        Err(err) => output.emit(AgentOutput::ProofFailed(id, err)),
+                    }
+                });
+            }
+            AgentInput::ListBlobs(id, did, cursor) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    match Session::new(notifier(id, &output))
+                        .list_blobs(did, cursor, &cache)
+                        .await
+                    {
+                        Ok((cids, cursor)) => output.emit(AgentOutput::Blobs(id, cids, cursor)),
+                        Err(err) => output.emit(AgentOutput::BlobsFailed(id, err)),
+                    }
+                });
+            }
+            AgentInput::GetBlob(id, repo, reference) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    let cid = reference.cid;
+                    match Session::new(notifier(id, &output))
+                        .get_blob(repo, reference, &cache)
+                        .await
+                    {
+                        Ok(blob) => output.emit(AgentOutput::Blob(id, blob)),
+                        Err(err) => output.emit(AgentOutput::BlobFailed(id, cid, err)),
+                    }
+                });
+            }
+            AgentInput::Diff(id, source) => {
+                let output = sender.output_sender().clone();
+                let cache = self.cache.clone();
+                self.spawn(id, &sender, async move {
+                    let diff = relm4::spawn_blocking(move || match source {
+                        DiffSource::Cars(first, second) => diff_cars(&first, &second),
+                        DiffSource::Commit(commit) => diff_commit(&commit, &cache),
+                    })
+                    .await;
+                    match diff {
+                        Ok(Ok(diff)) => output.emit(AgentOutput::Diff(id, diff)),
+                        Ok(Err(err)) => output.emit(AgentOutput::Failed(id, err)),
+                        Err(err) => output.emit(AgentOutput::Failed(
+                            id,
+                            AgentError::InvalidCar(err.to_string()),
+                        )),
+                    }
+                });
+            }
+            AgentInput::OpenCar(path) => {
+                let input = sender.input_sender().clone();
+                let output = sender.output_sender().clone();
+                relm4::spawn_blocking(move || match LocalRepo::open(path) {
+                    Ok(repo) => input.emit(AgentInput::Opened(Arc::new(repo))),
+                    Err(err) => output.emit(AgentOutput::Error(err)),
+                });
+            }
+            AgentInput::Opened(repo) => match repo.did().parse::<Did>() {
+                Ok(did) => {
+                    let path = repo.path.clone();
+                    self.local.insert(path.clone(), repo);
+                    sender.output(AgentOutput::Opened(
+                        path,
+                        AtUri {
+                            authority: AtIdentifier::Did(did),
+                            collection: None,
+                            rkey: None,
+                        },
+                    ))
+                }
+                Err(err) => sender.output(AgentOutput::Error(AgentError::InvalidCar(format!(
+                    "invalid DID in commit: {}",
+                    err
+                )))),
+            },
+            AgentInput::Cancel(id) => {
+                if let Some((_, lookup)) = self.lookups.remove(&id) {
+                    lookup.abort();
+                }
+            }
+            AgentInput::Finished(id, number) => {
+                /* a task can finish after another lookup has taken over its id, whose handle (and
+                tab) have to be left alone */
+                if self
+                    .lookups
+                    .get(&id)
+                    .is_some_and(|(current, _)| *current == number)
+                {
+                    self.lookups.remove(&id);
+                    sender.output(AgentOutput::Finished(id));
                 }
             }
         }
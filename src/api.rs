@@ -1,6 +1,9 @@
+use crate::client::{self, RetryNotifier};
+use crate::plc::{PlcData, PlcLogEntry};
 use atrium_api::did_doc::DidDocument;
 use atrium_api::types::string::{AtIdentifier, Did, Handle};
 use reqwest::get;
+use serde::Deserialize;
 use serde_json;
 use std::error::Error;
 
@@ -24,13 +27,25 @@ a Box<dyn Error>
 for consumers: you should be prepared to handle any of these errors, and be prepared to update code that uses this
 function to handle a common, non-Boxed error type when it happens
 */
-pub async fn get_did_doc_for(did: &Did) -> Result<DidDocument, Box<dyn Error>> {
+pub async fn get_did_doc_for(
+    did: &Did,
+    notify: &RetryNotifier,
+) -> Result<DidDocument, Box<dyn Error>> {
     let uri = match did.method() {
         "did:plc" => format!("https://plc.directory/{}", did.as_str()),
         "did:web" => format!("https://{}/.well-known/did.json", did.as_str()),
         method => return Err(format!("unrecognized DID method: {}", method).into()),
     };
-    Ok(serde_json::from_str(&get(uri).await?.text().await?)?)
+    Ok(serde_json::from_str(&get_text(&uri, notify).await?)?)
+}
+
+// the body of a GET that succeeded, with transient failures retried like XRPC requests are
+async fn get_text(uri: &str, notify: &RetryNotifier) -> Result<String, Box<dyn Error>> {
+    let response = client::get(uri, notify)
+        .await
+        .map_err(|err| err as Box<dyn Error>)?
+        .error_for_status()?;
+    Ok(response.text().await?)
 }
 
 /*
@@ -44,11 +59,98 @@ of returning a Box<dyn Error> with four separate sources of errors, plus one ext
 
 for more information, see `get_did_doc_for()`
 */
-pub async fn get_pds_endpoint_for(did: &Did) -> Result<String, Box<dyn Error>> {
-    let did_doc = get_did_doc_for(did).await?;
+pub async fn get_pds_endpoint_for(
+    did: &Did,
+    notify: &RetryNotifier,
+) -> Result<String, Box<dyn Error>> {
+    let did_doc = get_did_doc_for(did, notify).await?;
     Ok(did_doc.get_pds_endpoint().ok_or("no PDS endpoint found")?)
 }
 
+// the state plc.directory has for a did:plc, rotation keys included
+pub async fn get_plc_data_for(
+    did: &Did,
+    notify: &RetryNotifier,
+) -> Result<PlcData, Box<dyn Error>> {
+    if did.method() != "did:plc" {
+        return Err(format!("{} isn't a did:plc", did.as_str()).into());
+    }
+    let uri = format!("https://plc.directory/{}/data", did.as_str());
+    Ok(serde_json::from_str(&get_text(&uri, notify).await?)?)
+}
+
+// every operation plc.directory has seen for a did:plc, oldest first, nullified ones included
+pub async fn get_plc_audit_log_for(
+    did: &Did,
+    notify: &RetryNotifier,
+) -> Result<Vec<PlcLogEntry>, Box<dyn Error>> {
+    if did.method() != "did:plc" {
+        return Err(format!("{} isn't a did:plc", did.as_str()).into());
+    }
+    let uri = format!("https://plc.directory/{}/log/audit", did.as_str());
+    Ok(serde_json::from_str(&get_text(&uri, notify).await?)?)
+}
+
+// what a PDS says about itself in com.atproto.server.describeServer
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerDescription {
+    pub did: String,
+    // the handle suffixes accounts can sign up with
+    #[serde(default)]
+    pub available_user_domains: Vec<String>,
+    #[serde(default)]
+    pub invite_code_required: bool,
+    #[serde(default)]
+    pub phone_verification_required: bool,
+    #[serde(default)]
+    pub links: ServerLinks,
+    #[serde(default)]
+    pub contact: ServerContact,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerLinks {
+    pub privacy_policy: Option<String>,
+    pub terms_of_service: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServerContact {
+    pub email: Option<String>,
+}
+
+// the reference PDS answers /xrpc/_health with its version, other servers may not say
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerHealth {
+    pub version: Option<String>,
+}
+
+// a serviceEndpoint or a typed host as the base URL XRPC paths go after
+pub fn server_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    if endpoint.starts_with("https://") || endpoint.starts_with("http://") {
+        endpoint.to_string()
+    } else {
+        format!("https://{}", endpoint)
+    }
+}
+
+pub async fn describe_server(url: &str) -> Result<ServerDescription, Box<dyn Error>> {
+    let response = get(format!("{}/xrpc/com.atproto.server.describeServer", url))
+        .await?
+        .error_for_status()?;
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
+
+pub async fn server_health(url: &str) -> Result<ServerHealth, Box<dyn Error>> {
+    let response = get(format!("{}/xrpc/_health", url))
+        .await?
+        .error_for_status()?;
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
+
 pub async fn did_doc_from_plc_directory(did: &Did) -> Result<Option<String>, Box<dyn Error>> {
     let did_doc: DidDocument = serde_json::from_str(
         &get(format!("https://plc.directory/{}", did.as_str()))
@@ -4,7 +4,7 @@ use relm4::component::{
 };
 use relm4::factory::AsyncFactoryVecDeque;
 use relm4::{
-    actions::{RelmAction, RelmActionGroup},
+    actions::{AccelsPlus, RelmAction, RelmActionGroup},
     adw,
     factory::FactoryVecDeque,
     gtk::{self, prelude::EntryBufferExtManual},
@@ -14,8 +14,8 @@ use relm4::{
 };
 
 use gtk::prelude::{
-    ApplicationExt, ApplicationWindowExt, ButtonExt, EntryExt, GtkWindowExt, OrientableExt,
-    SettingsExt, WidgetExt,
+    ApplicationExt, ApplicationWindowExt, ButtonExt, EntryExt, GtkWindowExt, ListModelExtManual,
+    OrientableExt, SettingsExt, WidgetExt,
 };
 use gtk::{gio, glib};
 
@@ -24,18 +24,34 @@ use atrium_api::types::string::{AtIdentifier, Did, Handle};
 use atrium_api::xrpc::Result as AtResult;
 use atrium_api::*;
 
-use crate::agent::{AgentInput, AgentOutput, AtprotoAgent};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::agent::{AgentInput, AgentOutput, AtprotoAgent, RequestId};
+use crate::api::server_url;
+use crate::blob::BlobRef;
 use crate::config::{APP_ID, PROFILE};
+use crate::diff::DiffSource;
+use crate::firehose::FirehoseSource;
 use crate::modals::about::AboutDialog;
-use crate::recordview::GetRecordView;
+use crate::modals::preferences::PreferencesDialog;
+use crate::tab::{Tab, TabInput, TabOutput, TabTarget};
 use crate::types::*;
 
 pub(super) struct App {
     about_dialog: Controller<AboutDialog>,
+    preferences_dialog: Controller<PreferencesDialog>,
     entry: gtk::EntryBuffer,
-    views: AsyncFactoryVecDeque<GetRecordView>,
+    views: AsyncFactoryVecDeque<Tab>,
     created_widgets: u8,
     atp_client: AsyncController<AtprotoAgent>,
+    // which tab is waiting on which lookup
+    requests: HashMap<RequestId, DynamicIndex>,
+    next_request: RequestId,
+    // set while the factory itself is closing a page, see `connect_close_page` below
+    closing_tab: Rc<Cell<bool>>,
 }
 
 // #[derive(Debug)]
@@ -53,9 +69,23 @@ pub enum AppMsg {
     // MoveUp(DynamicIndex),
     // MoveDown(DynamicIndex),
     Retrieve,
-    TabForRecord(com::atproto::repo::get_record::OutputData),
-    TabForRecords(com::atproto::repo::list_records::OutputData),
-    TabForRepo(com::atproto::repo::describe_repo::OutputData),
+    Open(TabTarget),
+    ChooseCar,
+    OpenCar(PathBuf),
+    ChooseRecording,
+    ChooseCarsToCompare,
+    ForTab(RequestId, TabInput),
+    LookupFinished(RequestId),
+    Retry(DynamicIndex, TabTarget),
+    DownloadRepo(DynamicIndex, Did, PathBuf),
+    RefreshRepo(DynamicIndex, Did, PathBuf),
+    CheckCommit(DynamicIndex, Did, Option<PathBuf>),
+    ProveRecord(DynamicIndex, AtUri, Option<PathBuf>),
+    ListBlobs(DynamicIndex, Did, Option<String>),
+    FetchBlob(DynamicIndex, AtIdentifier, BlobRef),
+    ResolveHandle(DynamicIndex, Handle),
+    CloseTab(i32),
+    Toast(String),
     NotImplemented,
     Quit,
 }
@@ -77,6 +107,11 @@ pub enum AppCommand {
 }
 
 relm4::new_action_group!(pub(super) WindowActionGroup, "win");
+relm4::new_stateless_action!(OpenAction, WindowActionGroup, "open");
+relm4::new_stateless_action!(CompareAction, WindowActionGroup, "compare");
+relm4::new_stateless_action!(FirehoseAction, WindowActionGroup, "firehose");
+relm4::new_stateless_action!(JetstreamAction, WindowActionGroup, "jetstream");
+relm4::new_stateless_action!(ReplayAction, WindowActionGroup, "replay");
 relm4::new_stateless_action!(PreferencesAction, WindowActionGroup, "preferences");
 relm4::new_stateless_action!(pub(super) ShortcutsAction, WindowActionGroup, "show-help-overlay");
 relm4::new_stateless_action!(AboutAction, WindowActionGroup, "about");
@@ -91,6 +126,13 @@ impl AsyncComponent for App {
 
     menu! {
         primary_menu: {
+            section! {
+                "_Open Repository Export…" => OpenAction,
+                "_Compare Repository Exports…" => CompareAction,
+                "Watch _Firehose" => FirehoseAction,
+                "Watch _Jetstream" => JetstreamAction,
+                "_Replay Firehose Recording…" => ReplayAction,
+            },
             section! {
                 "_Preferences" => PreferencesAction,
                 "_Keyboard" => ShortcutsAction,
@@ -139,7 +181,7 @@ impl AsyncComponent for App {
                                 set_icon_from_icon_name: (gtk::EntryIconPosition::Primary, Some("edit-find-symbolic")),
                                 set_width_request: 300,
                                 set_hexpand: true,
-                                set_tooltip_text: Some("Enter a handle, did, or at:// URI"),
+                                set_tooltip_text: Some("Enter a handle, did, at:// URI, or the https:// address of a PDS"),
                                 set_buffer: &model.entry,
                                 connect_activate => AppMsg::Retrieve,
                             },
@@ -153,10 +195,14 @@ impl AsyncComponent for App {
                             set_menu_model: Some(&primary_menu),
                         }
                     },
-                    #[local_ref]
-                    tab_view -> adw::TabView {
-                        set_margin_all: 5,
+                    #[name(toast_overlay)]
+                    adw::ToastOverlay {
                         set_vexpand: true,
+                        #[local_ref]
+                        tab_view -> adw::TabView {
+                            set_margin_all: 5,
+                            set_vexpand: true,
+                        },
                     },
                     gtk::Label {
                         set_label: "Browse public AT Protocol data",
@@ -177,6 +223,10 @@ impl AsyncComponent for App {
             .transient_for(&root)
             .launch(())
             .detach();
+        let preferences_dialog = PreferencesDialog::builder()
+            .transient_for(&root)
+            .launch(())
+            .detach();
 
         // let counters = FactoryVecDeque::builder()
         //     .launch(adw::TabView::default())
@@ -188,28 +238,55 @@ impl AsyncComponent for App {
         let views = AsyncFactoryVecDeque::builder()
             .launch(adw::TabView::default())
             .forward(sender.input_sender(), |output| match output {
-                _ => AppMsg::NotImplemented,
+                TabOutput::Retry(index, target) => AppMsg::Retry(index, target),
+                TabOutput::Open(target) => AppMsg::Open(target),
+                TabOutput::DownloadRepo(index, did, path) => AppMsg::DownloadRepo(index, did, path),
+                TabOutput::RefreshRepo(index, did, path) => AppMsg::RefreshRepo(index, did, path),
+                TabOutput::CheckCommit(index, did, file) => AppMsg::CheckCommit(index, did, file),
+                TabOutput::ProveRecord(index, uri, file) => AppMsg::ProveRecord(index, uri, file),
+                TabOutput::ListBlobs(index, did, cursor) => AppMsg::ListBlobs(index, did, cursor),
+                TabOutput::FetchBlob(index, repo, blob) => AppMsg::FetchBlob(index, repo, blob),
+                TabOutput::ResolveHandle(index, handle) => AppMsg::ResolveHandle(index, handle),
             });
         let model = Self {
             about_dialog,
+            preferences_dialog,
             entry: gtk::EntryBuffer::default(),
             created_widgets: 0,
             views,
+            requests: HashMap::new(),
+            next_request: 0,
+            closing_tab: Rc::new(Cell::new(false)),
             atp_client: AtprotoAgent::builder().launch(()).forward(
                 sender.input_sender(),
                 |output| match output {
-                    AgentOutput::Record(record) => {
-                        println!("record: {:?}", record);
-                        AppMsg::TabForRecord(record)
+                    AgentOutput::Stage(id, stage) => AppMsg::ForTab(id, TabInput::Stage(stage)),
+                    AgentOutput::Cached(id, cached_at) => {
+                        AppMsg::ForTab(id, TabInput::Cached(cached_at))
+                    }
+                    AgentOutput::Local(id, path) => AppMsg::ForTab(id, TabInput::Local(path)),
+                    AgentOutput::Snapshots(id, snapshots) => {
+                        AppMsg::ForTab(id, TabInput::Snapshots(snapshots))
+                    }
+                    AgentOutput::Opened(path, uri) => AppMsg::Open(TabTarget::Local(path, uri)),
+                    AgentOutput::Mst(id, mst) => AppMsg::ForTab(id, TabInput::Mst(mst)),
+                    AgentOutput::Server(id, overview) => {
+                        AppMsg::ForTab(id, TabInput::Server(overview))
+                    }
+                    AgentOutput::Diff(id, diff) => AppMsg::ForTab(id, TabInput::Diff(diff)),
+                    AgentOutput::PlcData(id, data) => AppMsg::ForTab(id, TabInput::PlcData(data)),
+                    AgentOutput::PlcLog(id, log) => AppMsg::ForTab(id, TabInput::PlcLog(log)),
+                    AgentOutput::PlcLogChecked(id, check) => {
+                        AppMsg::ForTab(id, TabInput::PlcLogChecked(check))
                     }
-                    AgentOutput::Records(records) => {
-                        println!("records: {:?}", records);
-                        AppMsg::TabForRecords(records)
+                    AgentOutput::CidChecked(id, check) => {
+                        AppMsg::ForTab(id, TabInput::CidChecked(check))
                     }
-                    AgentOutput::Repo(repo) => {
-                        println!("repo: {:?}", repo);
-                        AppMsg::TabForRepo(repo)
+                    AgentOutput::Record(id, record) => AppMsg::ForTab(id, TabInput::Record(record)),
+                    AgentOutput::Records(id, records) => {
+                        AppMsg::ForTab(id, TabInput::Records(records))
                     }
+                    AgentOutput::Repo(id, repo) => AppMsg::ForTab(id, TabInput::Repo(repo)),
                     AgentOutput::DidDoc(did_doc) => {
                         println!("did_doc: {:?}", did_doc);
                         AppMsg::NotImplemented
@@ -218,16 +295,70 @@ impl AsyncComponent for App {
                         println!("pds_endpoint: {:?}", pds_endpoint);
                         AppMsg::NotImplemented
                     }
-                    AgentOutput::Error(err) => {
-                        println!("error: {:?}", err);
-                        AppMsg::NotImplemented
+                    AgentOutput::Failed(id, err) => AppMsg::ForTab(id, TabInput::Failed(err)),
+                    AgentOutput::Downloaded(id, download) => {
+                        AppMsg::ForTab(id, TabInput::Downloaded(download))
+                    }
+                    AgentOutput::Refreshed(id, download, changes) => {
+                        AppMsg::ForTab(id, TabInput::Refreshed(download, changes))
+                    }
+                    AgentOutput::DownloadFailed(id, err) => {
+                        AppMsg::ForTab(id, TabInput::DownloadFailed(err))
+                    }
+                    AgentOutput::CommitChecked(id, check) => {
+                        AppMsg::ForTab(id, TabInput::CommitChecked(check))
+                    }
+                    AgentOutput::CommitCheckFailed(id, err) => {
+                        AppMsg::ForTab(id, TabInput::CommitCheckFailed(err))
+                    }
+                    AgentOutput::RecordProved(id, proof) => {
+                        AppMsg::ForTab(id, TabInput::RecordProved(proof))
+                    }
+                    AgentOutput::ProofFailed(id, err) => {
+                        AppMsg::ForTab(id, TabInput::ProofFailed(err))
+                    }
+                    AgentOutput::Blobs(id, cids, cursor) => {
+                        AppMsg::ForTab(id, TabInput::Blobs(cids, cursor))
+                    }
+                    AgentOutput::BlobsFailed(id, err) => {
+                        AppMsg::ForTab(id, TabInput::BlobsFailed(err))
                     }
+                    AgentOutput::Blob(id, blob) => AppMsg::ForTab(id, TabInput::Blob(blob)),
+                    AgentOutput::BlobFailed(id, cid, err) => {
+                        AppMsg::ForTab(id, TabInput::BlobFailed(cid, err))
+                    }
+                    AgentOutput::Resolved(id, handle, did) => {
+                        AppMsg::ForTab(id, TabInput::Resolved(handle, did))
+                    }
+                    AgentOutput::ResolveFailed(id, handle, err) => {
+                        AppMsg::ForTab(id, TabInput::ResolveFailed(handle, err))
+                    }
+                    AgentOutput::Finished(id) => AppMsg::LookupFinished(id),
+                    AgentOutput::Error(err) => AppMsg::Toast(err.to_string()),
                 },
             ),
         };
 
         let tab_view = model.views.widget();
 
+        /*
+        pages closed from the tab bar or overview have to go through the factory, otherwise it loses
+        track of which page belongs to which tab. we decline the close here, and let the factory close
+        the page for real once the tab has been removed (and its lookups cancelled)
+        */
+        {
+            let sender = sender.clone();
+            let closing_tab = model.closing_tab.clone();
+            tab_view.connect_close_page(move |view, page| {
+                if closing_tab.get() {
+                    return glib::Propagation::Proceed;
+                }
+                view.close_page_finish(page, false);
+                sender.input(AppMsg::CloseTab(view.page_position(page)));
+                glib::Propagation::Stop
+            });
+        }
+
         let widgets = view_output!();
 
         let mut actions = RelmActionGroup::<WindowActionGroup>::new();
@@ -246,9 +377,68 @@ impl AsyncComponent for App {
             })
         };
 
+        let preferences_action = {
+            let sender = model.preferences_dialog.sender().clone();
+            RelmAction::<PreferencesAction>::new_stateless(move |_| {
+                sender.send(()).unwrap();
+            })
+        };
+
+        let open_action = {
+            let sender = sender.clone();
+            RelmAction::<OpenAction>::new_stateless(move |_| {
+                sender.input(AppMsg::ChooseCar);
+            })
+        };
+
+        let compare_action = {
+            let sender = sender.clone();
+            RelmAction::<CompareAction>::new_stateless(move |_| {
+                sender.input(AppMsg::ChooseCarsToCompare);
+            })
+        };
+
+        let firehose_action = {
+            let sender = sender.clone();
+            RelmAction::<FirehoseAction>::new_stateless(move |_| {
+                let url = gio::Settings::new(APP_ID)
+                    .string("firehose-url")
+                    .to_string();
+                sender.input(AppMsg::Open(TabTarget::Firehose(FirehoseSource::Live(url))));
+            })
+        };
+
+        let jetstream_action = {
+            let sender = sender.clone();
+            RelmAction::<JetstreamAction>::new_stateless(move |_| {
+                let url = gio::Settings::new(APP_ID)
+                    .string("jetstream-url")
+                    .to_string();
+                sender.input(AppMsg::Open(TabTarget::Firehose(
+                    FirehoseSource::Jetstream(url),
+                )));
+            })
+        };
+
+        let replay_action = {
+            let sender = sender.clone();
+            RelmAction::<ReplayAction>::new_stateless(move |_| {
+                sender.input(AppMsg::ChooseRecording);
+            })
+        };
+
+        actions.add_action(open_action);
+        actions.add_action(compare_action);
+        actions.add_action(firehose_action);
+        actions.add_action(jetstream_action);
+        actions.add_action(replay_action);
         actions.add_action(shortcuts_action);
+        actions.add_action(preferences_action);
         actions.add_action(about_action);
         actions.register_for_widget(&widgets.main_window);
+        main_application().set_accelerators_for_action::<OpenAction>(&["<Control>o"]);
+        main_application().set_accelerators_for_action::<FirehoseAction>(&["<Control><Shift>f"]);
+        main_application().set_accelerators_for_action::<JetstreamAction>(&["<Control><Shift>j"]);
 
         widgets.load_window_size();
 
@@ -265,30 +455,174 @@ impl AsyncComponent for App {
         let mut counters_guard = self.views.guard();
 
         match message {
-            AppMsg::Retrieve => {
-                if let Ok(uri) = self.entry.text().to_string().parse::<AtUri>() {
-                    self.atp_client.emit(AgentInput::GetURI(uri));
-                } else {
-                    println!("not a valid URI"); // TODO create a toast to represent this error
+            /* a web address can only be a server, handles, DIDs and at:// URIs never start like one */
+            AppMsg::Retrieve
+                if self.entry.text().starts_with("https://")
+                    || self.entry.text().starts_with("http://") =>
+            {
+                sender.input(AppMsg::Open(TabTarget::Server(server_url(
+                    &self.entry.text(),
+                ))))
+            }
+            AppMsg::Retrieve => match parse_versioned(&self.entry.text()) {
+                Ok((uri, None)) => sender.input(AppMsg::Open(TabTarget::Uri(uri))),
+                Ok((uri, Some(cid))) => sender.input(AppMsg::Open(TabTarget::Version(uri, cid))),
+                Err(err) => widgets
+                    .toast_overlay
+                    .add_toast(adw::Toast::new(&err.to_string())),
+            },
+            AppMsg::Open(target) => {
+                let index = counters_guard.push_back(target.clone());
+                self.created_widgets = self.created_widgets.wrapping_add(1);
+                let id = self.next_request;
+                self.next_request += 1;
+                if let Some(lookup) = lookup_for(id, target) {
+                    self.requests.insert(id, index);
+                    self.atp_client.emit(lookup);
                 }
             }
+            AppMsg::ChooseCar => {
+                let filter = gtk::FileFilter::new();
+                filter.set_name(Some("Repository exports"));
+                filter.add_pattern("*.car");
+                let filters = gio::ListStore::new::<gtk::FileFilter>();
+                filters.append(&filter);
+                let dialog = gtk::FileDialog::builder()
+                    .title("Open Repository Export")
+                    .filters(&filters)
+                    .modal(true)
+                    .build();
+                let sender = sender.clone();
+                dialog.open(Some(root), None::<&gio::Cancellable>, move |result| {
+                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                        sender.input(AppMsg::OpenCar(path));
+                    }
+                });
+            }
+            AppMsg::OpenCar(path) => self.atp_client.emit(AgentInput::OpenCar(path)),
+            AppMsg::ChooseCarsToCompare => {
+                let filter = gtk::FileFilter::new();
+                filter.set_name(Some("Repository exports"));
+                filter.add_pattern("*.car");
+                let filters = gio::ListStore::new::<gtk::FileFilter>();
+                filters.append(&filter);
+                let dialog = gtk::FileDialog::builder()
+                    .title("Compare Two Repository Exports")
+                    .filters(&filters)
+                    .modal(true)
+                    .build();
+                let sender = sender.clone();
+                dialog.open_multiple(Some(root), None::<&gio::Cancellable>, move |result| {
+                    let Ok(files) = result else {
+                        return;
+                    };
+                    let paths: Vec<PathBuf> = files
+                        .iter::<gio::File>()
+                        .filter_map(|file| file.ok().and_then(|file| file.path()))
+                        .collect();
+                    match <[PathBuf; 2]>::try_from(paths) {
+                        Ok([first, second]) => sender.input(AppMsg::Open(TabTarget::Diff(
+                            DiffSource::Cars(first, second),
+                        ))),
+                        Err(_) => sender.input(AppMsg::Toast(String::from(
+                            "Choose two exports of the same repository to compare",
+                        ))),
+                    }
+                });
+            }
+            AppMsg::ChooseRecording => {
+                let filter = gtk::FileFilter::new();
+                filter.set_name(Some("Firehose recordings"));
+                filter.add_pattern("*.firehose");
+                let filters = gio::ListStore::new::<gtk::FileFilter>();
+                filters.append(&filter);
+                let dialog = gtk::FileDialog::builder()
+                    .title("Replay Firehose Recording")
+                    .filters(&filters)
+                    .modal(true)
+                    .build();
+                let sender = sender.clone();
+                dialog.open(Some(root), None::<&gio::Cancellable>, move |result| {
+                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                        sender.input(AppMsg::Open(TabTarget::Firehose(FirehoseSource::Replay(
+                            path,
+                        ))));
+                    }
+                });
+            }
             AppMsg::DisplayOverview => {
                 widgets.tab_overview.set_open(true);
             }
-            AppMsg::TabForRecord(record) => {
-                counters_guard.push_back(record);
-                self.created_widgets = self.created_widgets.wrapping_add(1);
+            AppMsg::ForTab(id, input) => {
+                if let Some(index) = self.requests.get(&id) {
+                    counters_guard.send(index.current_index(), input);
+                }
+            }
+            AppMsg::LookupFinished(id) => {
+                self.requests.remove(&id);
+            }
+            AppMsg::Retry(index, target) => {
+                cancel_lookups_for(&mut self.requests, &self.atp_client, index.current_index());
+                let id = self.next_request;
+                self.next_request += 1;
+                if let Some(lookup) = lookup_for(id, target) {
+                    self.requests.insert(id, index);
+                    self.atp_client.emit(lookup);
+                }
+            }
+            AppMsg::DownloadRepo(index, did, path) => {
+                let id = self.next_request;
+                self.next_request += 1;
+                self.requests.insert(id, index);
+                self.atp_client
+                    .emit(AgentInput::DownloadRepo(id, did, path));
+            }
+            AppMsg::RefreshRepo(index, did, path) => {
+                let id = self.next_request;
+                self.next_request += 1;
+                self.requests.insert(id, index);
+                self.atp_client.emit(AgentInput::RefreshRepo(id, did, path));
+            }
+            AppMsg::CheckCommit(index, did, file) => {
+                let id = self.next_request;
+                self.next_request += 1;
+                self.requests.insert(id, index);
+                self.atp_client.emit(AgentInput::CheckCommit(id, did, file));
             }
-            AppMsg::TabForRecords(records) => {
-                dbg!("we're not worrying about this for now");
-                // counters_guard.push_back(records);
-                // self.created_widgets = self.created_widgets.wrapping_add(1);
+            AppMsg::ProveRecord(index, uri, file) => {
+                let id = self.next_request;
+                self.next_request += 1;
+                self.requests.insert(id, index);
+                self.atp_client.emit(AgentInput::ProveRecord(id, uri, file));
             }
-            AppMsg::TabForRepo(repo) => {
-                dbg!("we're not worrying about this for now");
-                // counters_guard.push_back(repo);
-                // self.created_widgets = self.created_widgets.wrapping_add(1);
+            AppMsg::ListBlobs(index, did, cursor) => {
+                let id = self.next_request;
+                self.next_request += 1;
+                self.requests.insert(id, index);
+                self.atp_client.emit(AgentInput::ListBlobs(id, did, cursor));
             }
+            AppMsg::FetchBlob(index, repo, blob) => {
+                let id = self.next_request;
+                self.next_request += 1;
+                self.requests.insert(id, index);
+                self.atp_client.emit(AgentInput::GetBlob(id, repo, blob));
+            }
+            AppMsg::ResolveHandle(index, handle) => {
+                let id = self.next_request;
+                self.next_request += 1;
+                self.requests.insert(id, index);
+                self.atp_client.emit(AgentInput::ResolveHandle(id, handle));
+            }
+            AppMsg::CloseTab(position) => {
+                let position = position as usize;
+                cancel_lookups_for(&mut self.requests, &self.atp_client, position);
+                self.closing_tab.set(true);
+                counters_guard.remove(position);
+                // dropping the guard is what actually closes the page
+                drop(counters_guard);
+                self.closing_tab.set(false);
+            }
+            AppMsg::Toast(text) => widgets.toast_overlay.add_toast(adw::Toast::new(&text)),
             AppMsg::NotImplemented => println!("not implemented"),
             AppMsg::Quit => main_application().quit(),
         }
@@ -299,6 +633,37 @@ impl AsyncComponent for App {
     }
 }
 
+// what the agent has to do to fill a tab, if anything
+fn lookup_for(id: RequestId, target: TabTarget) -> Option<AgentInput> {
+    match target {
+        TabTarget::Uri(uri) => Some(AgentInput::GetURI(id, uri)),
+        TabTarget::AsOf(uri, snapshot) => Some(AgentInput::GetURIAsOf(id, uri, snapshot)),
+        TabTarget::Version(uri, cid) => Some(AgentInput::GetRecordVersion(id, uri, cid)),
+        TabTarget::Local(path, uri) => Some(AgentInput::GetLocalURI(id, path, uri)),
+        TabTarget::Mst(did) => Some(AgentInput::GetMst(id, did)),
+        TabTarget::LocalMst(path, _) => Some(AgentInput::GetLocalMst(id, path)),
+        TabTarget::Server(url) => Some(AgentInput::DescribeServer(id, url)),
+        TabTarget::Diff(source) => Some(AgentInput::Diff(id, source)),
+        TabTarget::Firehose(_) => None,
+    }
+}
+
+fn cancel_lookups_for(
+    requests: &mut HashMap<RequestId, DynamicIndex>,
+    agent: &AsyncController<AtprotoAgent>,
+    position: usize,
+) {
+    let stale: Vec<RequestId> = requests
+        .iter()
+        .filter(|(_, index)| index.current_index() == position)
+        .map(|(id, _)| *id)
+        .collect();
+    for id in stale {
+        requests.remove(&id);
+        agent.emit(AgentInput::Cancel(id));
+    }
+}
+
 impl AppWidgets {
     fn save_window_size(&self) -> Result<(), glib::BoolError> {
         let settings = gio::Settings::new(APP_ID);
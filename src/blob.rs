@@ -0,0 +1,108 @@
+use crate::verify::BlobCheck;
+use ipld_core::cid::Cid;
+use relm4::gtk::{gdk, gdk_pixbuf, gio, glib};
+
+/*
+blobs are the media records point at (avatars, images, video). they aren't part of the repository
+itself: a record only holds a reference to one, and the bytes are fetched from the PDS separately.
+references are written as {"$type": "blob", "ref": {"$link": cid}, "mimeType": …, "size": …}, or in
+the legacy form {"cid": cid, "mimeType": …} in records old enough to predate it
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlobRef {
+    pub cid: Cid,
+    // listBlobs only gives CIDs, so neither of these are known for blobs found that way
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+}
+
+impl BlobRef {
+    pub fn from_cid(cid: Cid) -> Self {
+        Self {
+            cid,
+            mime_type: None,
+            size: None,
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.mime_type
+            .as_deref()
+            .is_some_and(|mime_type| mime_type.starts_with("image/"))
+    }
+
+    pub fn describe(&self) -> String {
+        match (&self.mime_type, self.size) {
+            (Some(mime_type), Some(size)) => {
+                format!("{}, {}", mime_type, glib::format_size(size))
+            }
+            (Some(mime_type), None) => mime_type.clone(),
+            (None, _) => String::from("Unknown type"),
+        }
+    }
+
+    fn from_json(map: &serde_json::Map<String, serde_json::Value>) -> Option<Self> {
+        let mime_type = map.get("mimeType")?.as_str()?.to_string();
+        let cid = match map.get("$type").and_then(|t| t.as_str()) {
+            Some("blob") => map.get("ref")?.get("$link")?.as_str()?,
+            _ => map.get("cid")?.as_str()?,
+        };
+        Some(Self {
+            cid: cid.parse().ok()?,
+            mime_type: Some(mime_type),
+            size: map.get("size").and_then(|size| size.as_u64()),
+        })
+    }
+}
+
+// every blob a record references, wherever in the record it is, in the order they appear
+pub fn blob_refs(value: &serde_json::Value) -> Vec<BlobRef> {
+    let mut refs = vec![];
+    collect_blob_refs(value, &mut refs);
+    refs
+}
+
+fn collect_blob_refs(value: &serde_json::Value, refs: &mut Vec<BlobRef>) {
+    match value {
+        serde_json::Value::Object(map) => match BlobRef::from_json(map) {
+            Some(blob) if !refs.contains(&blob) => refs.push(blob),
+            Some(_) => (),
+            None => map
+                .values()
+                .for_each(|value| collect_blob_refs(value, refs)),
+        },
+        serde_json::Value::Array(values) => values
+            .iter()
+            .for_each(|value| collect_blob_refs(value, refs)),
+        _ => (),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Blob {
+    pub reference: BlobRef,
+    // what was actually downloaded
+    pub bytes: glib::Bytes,
+    pub check: BlobCheck,
+    pub preview: Result<gdk::Texture, String>,
+}
+
+/*
+called off the main thread, since decoding a large image can take a while. GTK reads PNG, JPEG and
+TIFF itself; anything else (WebP, mostly) goes through whichever gdk-pixbuf loaders are installed
+*/
+pub fn decode_preview(reference: &BlobRef, bytes: &glib::Bytes) -> Result<gdk::Texture, String> {
+    if reference.mime_type.is_some() && !reference.is_image() {
+        return Err(format!("No preview for {}", reference.describe()));
+    }
+    gdk::Texture::from_bytes(bytes).or_else(|_| {
+        let stream = gio::MemoryInputStream::from_bytes(bytes);
+        gdk_pixbuf::Pixbuf::from_stream(&stream, None::<&gio::Cancellable>)
+            .map(|pixbuf| gdk::Texture::for_pixbuf(&pixbuf))
+            .map_err(|err| match reference.mime_type {
+                Some(_) => format!("Couldn't decode the image: {}", err),
+                None => String::from("No preview, this isn't an image Branches can read"),
+            })
+    })
+}
@@ -0,0 +1,174 @@
+use crate::agent::AgentError;
+use crate::blob::{Blob, BlobRef};
+use relm4::adw::prelude::*;
+use relm4::gtk::prelude::*;
+use relm4::gtk::{gio, glib};
+use relm4::{adw, gtk};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/*
+a row for one blob, shared between the record and repository views. blobs can be large (video in
+particular), so nothing is downloaded until the row is first expanded; `fetch` is how the row asks
+whichever view it is in to go and get it
+*/
+#[derive(Debug, Clone)]
+pub struct BlobRow {
+    pub reference: BlobRef,
+    row: adw::ExpanderRow,
+    status: adw::ActionRow,
+    save: gtk::Button,
+    check: adw::ActionRow,
+    check_badge: gtk::Label,
+    picture: gtk::Picture,
+    // kept so that exactly what was checked is what gets saved
+    bytes: Rc<RefCell<Option<glib::Bytes>>>,
+}
+
+impl BlobRow {
+    pub fn new(reference: BlobRef, fetch: impl Fn(BlobRef) + 'static) -> Self {
+        let row = adw::ExpanderRow::new();
+        row.set_title(&reference.cid.to_string());
+        row.set_subtitle(&glib::markup_escape_text(&reference.describe()));
+        row.add_css_class("property");
+
+        let status = adw::ActionRow::new();
+        status.set_title("Not downloaded yet");
+        let save = gtk::Button::from_icon_name("document-save-symbolic");
+        save.set_tooltip_text(Some("Save the blob as it was downloaded"));
+        save.set_valign(gtk::Align::Center);
+        save.add_css_class("flat");
+        save.set_sensitive(false);
+        status.add_suffix(&save);
+        row.add_row(&status);
+        let check = adw::ActionRow::new();
+        check.set_title("Integrity");
+        check.set_subtitle_selectable(true);
+        check.set_visible(false);
+        let check_badge = gtk::Label::new(None);
+        check_badge.set_valign(gtk::Align::Center);
+        check.add_suffix(&check_badge);
+        row.add_row(&check);
+        let picture = gtk::Picture::new();
+        picture.set_can_shrink(true);
+        picture.set_content_fit(gtk::ContentFit::ScaleDown);
+        picture.set_height_request(240);
+        picture.set_margin_top(10);
+        picture.set_margin_bottom(10);
+        picture.set_visible(false);
+        row.add_row(&picture);
+
+        let requested = Cell::new(false);
+        let blob = reference.clone();
+        let downloading = status.clone();
+        row.connect_expanded_notify(move |row| {
+            if row.is_expanded() && !requested.replace(true) {
+                downloading.set_title("Downloading…");
+                fetch(blob.clone());
+            }
+        });
+        let bytes: Rc<RefCell<Option<glib::Bytes>>> = Rc::default();
+        {
+            let (bytes, status) = (bytes.clone(), status.clone());
+            let name = format!(
+                "{}.{}",
+                reference.cid,
+                reference
+                    .mime_type
+                    .as_deref()
+                    .and_then(|mime_type| mime_type.split('/').nth(1))
+                    .unwrap_or("bin")
+            );
+            save.connect_clicked(move |button| {
+                let Some(contents) = bytes.borrow().clone() else {
+                    return;
+                };
+                let dialog = gtk::FileDialog::builder()
+                    .title("Save Blob")
+                    .initial_name(name.as_str())
+                    .modal(true)
+                    .build();
+                let status = status.clone();
+                dialog.save(
+                    button.root().and_downcast_ref::<gtk::Window>(),
+                    None::<&gio::Cancellable>,
+                    move |result| {
+                        /* dismissing the dialog shows up as an error too, and isn't worth reporting */
+                        let Some(file) = result.ok() else {
+                            return;
+                        };
+                        let path = file.path().unwrap_or_default();
+                        file.replace_contents_async(
+                            contents,
+                            None,
+                            false,
+                            gio::FileCreateFlags::REPLACE_DESTINATION,
+                            None::<&gio::Cancellable>,
+                            move |result| match result {
+                                Ok(_) => status.set_subtitle(&glib::markup_escape_text(
+                                    &format!("Saved to {}", path.display()),
+                                )),
+                                Err((_, err)) => status.set_subtitle(&glib::markup_escape_text(
+                                    &format!("Couldn't save the blob: {}", err),
+                                )),
+                            },
+                        );
+                    },
+                );
+            });
+        }
+        Self {
+            reference,
+            row,
+            status,
+            save,
+            check,
+            check_badge,
+            picture,
+            bytes,
+        }
+    }
+
+    pub fn widget(&self) -> &adw::ExpanderRow {
+        &self.row
+    }
+
+    pub fn show(&self, blob: &Blob) {
+        self.status.set_title(&format!(
+            "Downloaded {}",
+            glib::format_size(blob.bytes.len() as u64)
+        ));
+        self.bytes.replace(Some(blob.bytes.clone()));
+        self.save.set_sensitive(true);
+        self.check
+            .set_subtitle(&glib::markup_escape_text(&blob.check.to_string()));
+        self.check_badge.set_label(blob.check.badge());
+        self.check_badge
+            .set_css_classes(&["caption-heading", blob.check.css_class()]);
+        self.check.set_visible(true);
+        /* a blob found through listBlobs has no ref to say what it is, so say what it turned out to be */
+        if self.reference.mime_type.is_none() {
+            if let Some(mime_type) = &blob.check.mime_type {
+                self.row.set_subtitle(&glib::markup_escape_text(mime_type));
+            }
+        }
+        match &blob.preview {
+            Ok(texture) => {
+                self.picture.set_paintable(Some(texture));
+                self.picture.set_visible(true);
+                self.status.set_subtitle(&format!(
+                    "{} × {} pixels",
+                    texture.width(),
+                    texture.height()
+                ));
+            }
+            Err(reason) => self.status.set_subtitle(&glib::markup_escape_text(reason)),
+        }
+    }
+
+    pub fn failed(&self, error: &AgentError) {
+        self.status.set_title(error.title());
+        self.status
+            .set_subtitle(&glib::markup_escape_text(&error.to_string()));
+    }
+}
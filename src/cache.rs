@@ -0,0 +1,466 @@
+use relm4::gtk::gio::{self, prelude::*};
+use relm4::gtk::glib;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/*
+everything Branches has fetched is kept twice: in memory while it's still among the most recent few
+hundred entries, and on disk under $XDG_CACHE_HOME/branches so that it survives restarts and can be
+browsed offline.
+
+on disk, each kind of data gets its own directory, and each entry is named after the sha256 of its
+key (URIs and DIDs aren't safe or short enough to be file names). JSON entries are wrapped in an
+`Envelope` that remembers when they were cached, blobs get a short header that does the same. a
+file's modification time is bumped whenever it is read, which is what eviction uses to find the
+least recently used entries
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheKind {
+    Record,
+    Records,
+    Repo,
+    DidDoc,
+    Blob,
+}
+
+impl CacheKind {
+    fn directory(&self) -> &'static str {
+        match self {
+            CacheKind::Record => "records",
+            CacheKind::Records => "listings",
+            CacheKind::Repo => "repos",
+            CacheKind::DidDoc => "did-docs",
+            CacheKind::Blob => "blobs",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Cached<T> {
+    // seconds since the unix epoch
+    pub cached_at: i64,
+    pub value: T,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    cached_at: i64,
+    value: T,
+}
+
+// how many JSON entries are kept in memory before the oldest are dropped back to disk only
+const MEMORY_ENTRIES: usize = 512;
+
+#[derive(Default)]
+struct Memory {
+    entries: HashMap<(CacheKind, String), Cached<serde_json::Value>>,
+    // keys in the order they were first remembered, oldest first
+    order: VecDeque<(CacheKind, String)>,
+}
+
+impl Memory {
+    fn insert(&mut self, key: (CacheKind, String), cached: Cached<serde_json::Value>) {
+        if self.entries.insert(key.clone(), cached).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > MEMORY_ENTRIES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+// a blob on disk starts with this, then the big-endian time it was cached at
+const BLOB_MAGIC: &[u8; 8] = b"brblob01";
+const BLOB_HEADER: usize = BLOB_MAGIC.len() + 8;
+
+// how long the size limit has to stay put before the cache is walked to enforce it
+const LIMIT_SETTLE: Duration = Duration::from_millis(500);
+
+pub struct Cache {
+    memory: Mutex<Memory>,
+    offline_only: AtomicBool,
+    disk: Arc<Disk>,
+}
+
+// what eviction needs, shared with the blocking task that does it
+struct Disk {
+    root: PathBuf,
+    // bytes
+    size_limit: AtomicU64,
+    // bytes on disk as of the last eviction, plus whatever has been written since
+    used: Mutex<Option<u64>>,
+    // an eviction is already walking the cache
+    evicting: AtomicBool,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn megabytes(settings: &gio::Settings) -> u64 {
+    settings.int("cache-size-limit").max(0) as u64 * 1024 * 1024
+}
+
+impl Cache {
+    /*
+    the cache is shared with lookups running off the main thread, so the settings it cares about are
+    copied into atomics and kept up to date from here, rather than read from GSettings on the spot
+    */
+    pub fn from_settings(settings: &gio::Settings) -> Arc<Self> {
+        let cache = Arc::new(Self::new(
+            glib::user_cache_dir().join("branches"),
+            megabytes(settings),
+        ));
+        cache
+            .offline_only
+            .store(settings.boolean("offline-only"), Ordering::Relaxed);
+        let weak = Arc::downgrade(&cache);
+        let pending: Rc<RefCell<Option<glib::SourceId>>> = Rc::default();
+        settings.connect_changed(None, move |settings, key| {
+            if let Some(cache) = weak.upgrade() {
+                match key {
+                    "offline-only" => cache
+                        .offline_only
+                        .store(settings.boolean("offline-only"), Ordering::Relaxed),
+                    "cache-size-limit" => {
+                        cache
+                            .disk
+                            .size_limit
+                            .store(megabytes(settings), Ordering::Relaxed);
+                        // the limit changes with every step of a drag, only the last one evicts
+                        if let Some(source) = pending.take() {
+                            source.remove();
+                        }
+                        let (weak, fired) = (weak.clone(), pending.clone());
+                        let source = glib::timeout_add_local_once(LIMIT_SETTLE, move || {
+                            fired.take();
+                            if let Some(cache) = weak.upgrade() {
+                                cache.evict_in_background();
+                            }
+                        });
+                        pending.replace(Some(source));
+                    }
+                    _ => (),
+                }
+            }
+        });
+        cache
+    }
+
+    fn new(root: PathBuf, size_limit: u64) -> Self {
+        Self {
+            memory: Mutex::new(Memory::default()),
+            offline_only: AtomicBool::new(false),
+            disk: Arc::new(Disk {
+                root,
+                size_limit: AtomicU64::new(size_limit),
+                used: Mutex::new(None),
+                evicting: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    // a cache of its own under the temporary directory, already counted so writes don't evict
+    #[cfg(test)]
+    pub fn temporary(name: &str, size_limit: u64) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("branches-cache-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        let cache = Self::new(root, size_limit);
+        cache.disk.evict();
+        cache
+    }
+
+    pub fn offline_only(&self) -> bool {
+        self.offline_only.load(Ordering::Relaxed)
+    }
+
+    fn path_for(&self, kind: CacheKind, key: &str) -> PathBuf {
+        let digest = Sha256::digest(key.as_bytes());
+        let name: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        self.disk.root.join(kind.directory()).join(name)
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, kind: CacheKind, key: &str) -> Option<Cached<T>> {
+        // held only to copy the entry out, or every read would wait on this one's disk
+        let remembered = self
+            .memory
+            .lock()
+            .unwrap()
+            .entries
+            .get(&(kind, key.to_owned()))
+            .map(|cached| (cached.cached_at, cached.value.clone()));
+        if let Some((cached_at, value)) = remembered {
+            if let Ok(value) = serde_json::from_value(value) {
+                return Some(Cached { cached_at, value });
+            }
+        }
+        let path = self.path_for(kind, key);
+        let envelope: Envelope<serde_json::Value> =
+            serde_json::from_slice(&fs::read(&path).ok()?).ok()?;
+        touch(&path);
+        let value = serde_json::from_value(envelope.value.clone()).ok()?;
+        self.memory.lock().unwrap().insert(
+            (kind, key.to_owned()),
+            Cached {
+                cached_at: envelope.cached_at,
+                value: envelope.value,
+            },
+        );
+        Some(Cached {
+            cached_at: envelope.cached_at,
+            value,
+        })
+    }
+
+    pub fn put<T: Serialize>(&self, kind: CacheKind, key: &str, value: &T) {
+        let Ok(value) = serde_json::to_value(value) else {
+            return;
+        };
+        let envelope = Envelope {
+            cached_at: now(),
+            value,
+        };
+        match self.write(
+            kind,
+            key,
+            &serde_json::to_vec(&envelope).unwrap_or_default(),
+        ) {
+            Ok(true) => self.evict_in_background(),
+            Ok(false) => (),
+            Err(err) => tracing::warn!("couldn't write {} to the cache: {}", key, err),
+        }
+        self.memory.lock().unwrap().insert(
+            (kind, key.to_owned()),
+            Cached {
+                cached_at: envelope.cached_at,
+                value: envelope.value,
+            },
+        );
+    }
+
+    // blobs can be large, so they skip the in-memory layer entirely
+    pub fn blob(&self, did: &str, cid: &str) -> Option<Cached<Vec<u8>>> {
+        let path = self.path_for(CacheKind::Blob, &format!("{}/{}", did, cid));
+        let mut bytes = fs::read(&path).ok()?;
+        /* the file's own times can't say when it was cached, reading it bumps them */
+        if bytes.len() < BLOB_HEADER || !bytes.starts_with(BLOB_MAGIC) {
+            return None;
+        }
+        let cached_at = i64::from_be_bytes(bytes[BLOB_MAGIC.len()..BLOB_HEADER].try_into().ok()?);
+        touch(&path);
+        bytes.drain(..BLOB_HEADER);
+        Some(Cached {
+            cached_at,
+            value: bytes,
+        })
+    }
+
+    pub fn put_blob(&self, did: &str, cid: &str, bytes: &[u8]) {
+        let key = format!("{}/{}", did, cid);
+        let mut file = Vec::with_capacity(BLOB_HEADER + bytes.len());
+        file.extend_from_slice(BLOB_MAGIC);
+        file.extend_from_slice(&now().to_be_bytes());
+        file.extend_from_slice(bytes);
+        match self.write(CacheKind::Blob, &key, &file) {
+            Ok(true) => self.evict_in_background(),
+            Ok(false) => (),
+            Err(err) => tracing::warn!("couldn't write blob {} to the cache: {}", key, err),
+        }
+    }
+
+    // whether the cache may have gone over its size limit with this write
+    fn write(&self, kind: CacheKind, key: &str, bytes: &[u8]) -> io::Result<bool> {
+        let path = self.path_for(kind, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let replaced = fs::metadata(&path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        fs::write(&path, bytes)?;
+        /* the whole cache is only scanned again once this write may have taken it over the limit */
+        let over = match self.disk.used.lock().unwrap().as_mut() {
+            Some(used) => {
+                *used = used.saturating_sub(replaced) + bytes.len() as u64;
+                *used > self.disk.size_limit.load(Ordering::Relaxed)
+            }
+            None => true,
+        };
+        Ok(over)
+    }
+
+    // walking the whole cache takes a while, so it's kept off the main thread and the runtime
+    fn evict_in_background(&self) {
+        if self.disk.evicting.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let disk = self.disk.clone();
+        relm4::spawn_blocking(move || {
+            disk.evict();
+            disk.evicting.store(false, Ordering::Relaxed);
+        });
+    }
+}
+
+impl Disk {
+    // removes the least recently used entries until the cache fits under its size limit again
+    fn evict(&self) {
+        let limit = self.size_limit.load(Ordering::Relaxed);
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+        for kind in [
+            CacheKind::Record,
+            CacheKind::Records,
+            CacheKind::Repo,
+            CacheKind::DidDoc,
+            CacheKind::Blob,
+        ] {
+            let Ok(dir) = fs::read_dir(self.root.join(kind.directory())) else {
+                continue;
+            };
+            for entry in dir.flatten() {
+                if let Ok(metadata) = entry.metadata() {
+                    let used = metadata.modified().unwrap_or(UNIX_EPOCH);
+                    entries.push((used, metadata.len(), entry.path()));
+                }
+            }
+        }
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(used, _, _)| *used);
+        for (_, size, path) in entries {
+            if total <= limit {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+        *self.used.lock().unwrap() = Some(total);
+    }
+}
+
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+pub fn format_cached_at(cached_at: i64) -> String {
+    glib::DateTime::from_unix_local(cached_at)
+        .and_then(|time| time.format("%x %X"))
+        .map(|time| time.to_string())
+        .unwrap_or_else(|_| String::from("an unknown time"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // makes an entry look like it was last used this long ago
+    fn last_used(cache: &Cache, kind: CacheKind, key: &str, seconds_ago: u64) {
+        let file = fs::File::options()
+            .write(true)
+            .open(cache.path_for(kind, key))
+            .unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(seconds_ago))
+            .unwrap();
+    }
+
+    fn used(cache: &Cache) -> Option<u64> {
+        *cache.disk.used.lock().unwrap()
+    }
+
+    #[test]
+    fn entries_round_trip_through_memory_and_disk() {
+        let cache = Cache::temporary("round-trip", u64::MAX);
+        let value = serde_json::json!({ "text": "hello", "langs": ["en"] });
+        cache.put(CacheKind::Record, "at://did:plc:alice/post/1", &value);
+
+        let remembered =
+            cache.get::<serde_json::Value>(CacheKind::Record, "at://did:plc:alice/post/1");
+        assert_eq!(remembered.unwrap().value, value);
+
+        *cache.memory.lock().unwrap() = Memory::default();
+        let read = cache
+            .get::<serde_json::Value>(CacheKind::Record, "at://did:plc:alice/post/1")
+            .unwrap();
+        assert_eq!(read.value, value);
+        assert!((now() - read.cached_at).abs() < 60);
+
+        assert!(cache
+            .get::<serde_json::Value>(CacheKind::Repo, "at://did:plc:alice/post/1")
+            .is_none());
+        assert!(cache
+            .get::<Vec<u64>>(CacheKind::Record, "at://did:plc:alice/post/1")
+            .is_none());
+        let _ = fs::remove_dir_all(&cache.disk.root);
+    }
+
+    #[test]
+    fn blobs_keep_the_time_they_were_cached() {
+        let cache = Cache::temporary("blobs", u64::MAX);
+        cache.put_blob("did:plc:alice", "bafkrei", b"not really a png");
+        last_used(&cache, CacheKind::Blob, "did:plc:alice/bafkrei", 86400);
+
+        let blob = cache.blob("did:plc:alice", "bafkrei").unwrap();
+        assert_eq!(blob.value, b"not really a png");
+        assert!((now() - blob.cached_at).abs() < 60);
+
+        /* files from before the header was written are misses, not garbage */
+        fs::write(cache.path_for(CacheKind::Blob, "did:plc:alice/old"), b"png").unwrap();
+        assert!(cache.blob("did:plc:alice", "old").is_none());
+        let _ = fs::remove_dir_all(&cache.disk.root);
+    }
+
+    #[test]
+    fn writes_are_counted_against_the_limit() {
+        let cache = Cache::temporary("accounting", 100);
+        assert_eq!(used(&cache), Some(0));
+        assert!(!cache.write(CacheKind::Record, "a", &[0; 40]).unwrap());
+        assert_eq!(used(&cache), Some(40));
+        // rewriting an entry only counts the difference
+        assert!(!cache.write(CacheKind::Record, "a", &[0; 60]).unwrap());
+        assert_eq!(used(&cache), Some(60));
+        assert!(cache.write(CacheKind::Blob, "b", &[0; 50]).unwrap());
+        assert_eq!(used(&cache), Some(110));
+        let _ = fs::remove_dir_all(&cache.disk.root);
+    }
+
+    #[test]
+    fn eviction_drops_the_least_recently_used_first() {
+        let cache = Cache::temporary("eviction", 100);
+        for key in ["a", "b", "c"] {
+            cache.write(CacheKind::Record, key, &[0; 40]).unwrap();
+        }
+        last_used(&cache, CacheKind::Record, "a", 300);
+        last_used(&cache, CacheKind::Record, "b", 100);
+        last_used(&cache, CacheKind::Record, "c", 200);
+        let exists = |key| cache.path_for(CacheKind::Record, key).exists();
+
+        cache.disk.evict();
+        assert_eq!((exists("a"), exists("b"), exists("c")), (false, true, true));
+        assert_eq!(used(&cache), Some(80));
+
+        cache.disk.size_limit.store(40, Ordering::Relaxed);
+        cache.disk.evict();
+        assert_eq!((exists("b"), exists("c")), (true, false));
+        assert_eq!(used(&cache), Some(40));
+        let _ = fs::remove_dir_all(&cache.disk.root);
+    }
+}
@@ -0,0 +1,627 @@
+use crate::verify::{check_block, CidCheck};
+use ipld_core::cid::Cid;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+/*
+a minimal reader for CAR (content addressable archive) files, which is what com.atproto.sync.getRepo
+and friends return. a CAR is a varint-prefixed DAG-CBOR header naming the root CIDs, followed by
+varint-prefixed sections that each hold a CID and the block it addresses.
+
+see https://ipld.io/specs/transport/car/carv1/ and https://atproto.com/specs/repository
+*/
+
+#[derive(Debug, Clone)]
+pub enum CarError {
+    Truncated,
+    InvalidHeader(String),
+    InvalidCid(String),
+    NoRoot,
+    MissingBlock(Cid),
+    InvalidBlock(Cid, String),
+}
+
+impl std::fmt::Display for CarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CarError::Truncated => write!(f, "the CAR file ends part way through a section"),
+            CarError::InvalidHeader(context) => write!(f, "invalid CAR header: {}", context),
+            CarError::InvalidCid(context) => write!(f, "invalid CID in CAR file: {}", context),
+            CarError::NoRoot => write!(f, "the CAR file doesn't name a root"),
+            CarError::MissingBlock(cid) => write!(f, "block {} is missing from the CAR file", cid),
+            CarError::InvalidBlock(cid, context) => {
+                write!(f, "block {} couldn't be decoded: {}", cid, context)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CarHeader {
+    version: u64,
+    roots: Vec<Cid>,
+}
+
+// the signed commit object at the root of every repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Commit {
+    pub did: String,
+    pub version: u64,
+    // root of the repository's merkle search tree
+    pub data: Cid,
+    pub rev: String,
+    pub prev: Option<Cid>,
+    #[serde(with = "serde_bytes")]
+    pub sig: Vec<u8>,
+}
+
+/*
+a node in the repository's merkle search tree. keys are "collection/rkey" paths, and each entry only
+stores the part of its key that differs from the entry before it: `p` bytes are shared with the
+previous key and `k` holds the rest. `l` is the subtree sorting before the first entry, and an entry's
+`t` is the subtree sorting between it and the next entry
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MstNode {
+    #[serde(rename = "l")]
+    pub left: Option<Cid>,
+    #[serde(rename = "e")]
+    pub entries: Vec<MstEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MstEntry {
+    #[serde(rename = "p")]
+    pub prefix_len: usize,
+    #[serde(rename = "k", with = "serde_bytes")]
+    pub key_suffix: Vec<u8>,
+    // the record
+    #[serde(rename = "v")]
+    pub value: Cid,
+    #[serde(rename = "t")]
+    pub tree: Option<Cid>,
+}
+
+/*
+the shape of a repository's tree as read from a CAR file, for showing rather than for lookups. nodes
+are kept in a flat list and refer to each other by index; a subtree whose block isn't in the file
+(as in the partial CARs that sync.getRecord returns) is kept as its CID
+*/
+#[derive(Debug, Clone)]
+pub struct Mst {
+    pub did: String,
+    pub rev: String,
+    pub commit: Cid,
+    pub root: MstSubtree,
+    pub nodes: Vec<MstTreeNode>,
+}
+
+#[derive(Debug, Clone)]
+pub enum MstSubtree {
+    Node(usize),
+    Missing(Cid),
+}
+
+#[derive(Debug, Clone)]
+pub struct MstTreeNode {
+    pub cid: Cid,
+    pub layer: u32,
+    pub left: Option<MstSubtree>,
+    pub entries: Vec<MstTreeEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MstTreeEntry {
+    // the full "collection/rkey" key
+    pub key: String,
+    pub prefix_len: usize,
+    pub value: Cid,
+    pub right: Option<MstSubtree>,
+}
+
+impl Mst {
+    pub fn record_count(&self) -> usize {
+        self.nodes.iter().map(|node| node.entries.len()).sum()
+    }
+
+    // every record under a node, its subtrees included
+    pub fn records_under(&self, subtree: &MstSubtree) -> usize {
+        let MstSubtree::Node(index) = subtree else {
+            return 0;
+        };
+        let node = &self.nodes[*index];
+        node.entries.len()
+            + node
+                .left
+                .iter()
+                .map(|left| self.records_under(left))
+                .sum::<usize>()
+            + node
+                .entries
+                .iter()
+                .filter_map(|entry| entry.right.as_ref())
+                .map(|right| self.records_under(right))
+                .sum::<usize>()
+    }
+}
+
+// a key's layer is the number of leading zero bits in its sha256, counted two at a time
+pub fn layer_for_key(key: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in Sha256::digest(key) {
+        zeros += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    zeros / 2
+}
+
+#[derive(Debug)]
+pub struct Car {
+    pub roots: Vec<Cid>,
+    pub blocks: HashMap<Cid, Vec<u8>>,
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> Result<u64, CarError> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*position).ok_or(CarError::Truncated)?;
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(CarError::InvalidHeader(String::from("varint is too long")));
+        }
+    }
+}
+
+fn write_varint(mut value: u64, bytes: &mut Vec<u8>) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_section<'a>(bytes: &'a [u8], position: &mut usize) -> Result<&'a [u8], CarError> {
+    let length = read_varint(bytes, position)? as usize;
+    let end = position.checked_add(length).ok_or(CarError::Truncated)?;
+    let section = bytes.get(*position..end).ok_or(CarError::Truncated)?;
+    *position = end;
+    Ok(section)
+}
+
+/*
+a sha256 has 256 bits, so no key's layer is above 128 and no tree built from them is more than 129
+nodes deep. nor can a tree reach the same node twice, since that node's keys would then appear
+twice. the walks below hold every file to both, so a crafted one can't send them round in circles
+or overflow the stack
+*/
+const MAX_DEPTH: usize = 129;
+
+fn enter(visited: &mut HashSet<Cid>, node: &Cid, depth: usize) -> Result<(), CarError> {
+    if depth > MAX_DEPTH {
+        return Err(CarError::InvalidBlock(
+            *node,
+            String::from("the tree is deeper than any key's layer allows"),
+        ));
+    }
+    if !visited.insert(*node) {
+        return Err(CarError::InvalidBlock(
+            *node,
+            String::from("the tree reaches this node more than once"),
+        ));
+    }
+    Ok(())
+}
+
+impl Car {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CarError> {
+        let mut position = 0;
+        let header: CarHeader = serde_ipld_dagcbor::from_slice(read_section(bytes, &mut position)?)
+            .map_err(|err| CarError::InvalidHeader(err.to_string()))?;
+        if header.version != 1 {
+            return Err(CarError::InvalidHeader(format!(
+                "unsupported version {}",
+                header.version
+            )));
+        }
+
+        let mut blocks = HashMap::new();
+        while position < bytes.len() {
+            let section = read_section(bytes, &mut position)?;
+            let mut cursor = Cursor::new(section);
+            let cid = Cid::read_bytes(&mut cursor)
+                .map_err(|err| CarError::InvalidCid(err.to_string()))?;
+            let data = &section[cursor.position() as usize..];
+            /* nothing later has to wonder whether a block is what its CID says */
+            match check_block(&cid, data) {
+                CidCheck::Verified => (),
+                CidCheck::Mismatch(actual) => {
+                    return Err(CarError::InvalidBlock(
+                        cid,
+                        format!("its contents hash to {}", actual),
+                    ))
+                }
+                CidCheck::Unverifiable(context) => {
+                    return Err(CarError::InvalidBlock(cid, context))
+                }
+            }
+            blocks.insert(cid, data.to_vec());
+        }
+
+        Ok(Self {
+            roots: header.roots,
+            blocks,
+        })
+    }
+
+    // back to a CAR file, commit first and the other blocks in CID order
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CarError> {
+        let header = serde_ipld_dagcbor::to_vec(&CarHeader {
+            version: 1,
+            roots: self.roots.clone(),
+        })
+        .map_err(|err| CarError::InvalidHeader(err.to_string()))?;
+        let mut bytes = Vec::new();
+        write_varint(header.len() as u64, &mut bytes);
+        bytes.extend_from_slice(&header);
+
+        let root = self.root()?;
+        let mut cids: Vec<&Cid> = self.blocks.keys().filter(|cid| *cid != root).collect();
+        cids.sort();
+        for cid in std::iter::once(root).chain(cids) {
+            let cid_bytes = cid.to_bytes();
+            let block = self.block(cid)?;
+            write_varint((cid_bytes.len() + block.len()) as u64, &mut bytes);
+            bytes.extend_from_slice(&cid_bytes);
+            bytes.extend_from_slice(block);
+        }
+        Ok(bytes)
+    }
+
+    /*
+    lays a CAR from getRepo's `since` on top of this one: its blocks are added and its commit becomes
+    the root. blocks the new commit no longer reaches, such as replaced tree nodes and deleted records,
+    are dropped, and a block it reaches that neither file has is an error, since the result wouldn't be
+    a complete repository
+    */
+    pub fn merge(&mut self, newer: Car) -> Result<(), CarError> {
+        self.blocks.extend(newer.blocks);
+        self.roots = newer.roots;
+        let mut reachable = HashSet::from([*self.root()?]);
+        self.reach(&self.commit()?.data, 0, &mut HashSet::new(), &mut reachable)?;
+        self.blocks.retain(|cid, _| reachable.contains(cid));
+        Ok(())
+    }
+
+    fn reach(
+        &self,
+        node: &Cid,
+        depth: usize,
+        visited: &mut HashSet<Cid>,
+        reachable: &mut HashSet<Cid>,
+    ) -> Result<(), CarError> {
+        enter(visited, node, depth)?;
+        reachable.insert(*node);
+        let node: MstNode = self.decode(node)?;
+        for subtree in node
+            .left
+            .iter()
+            .chain(node.entries.iter().filter_map(|entry| entry.tree.as_ref()))
+        {
+            self.reach(subtree, depth + 1, visited, reachable)?;
+        }
+        for entry in &node.entries {
+            self.block(&entry.value)?;
+            reachable.insert(entry.value);
+        }
+        Ok(())
+    }
+
+    pub fn root(&self) -> Result<&Cid, CarError> {
+        self.roots.first().ok_or(CarError::NoRoot)
+    }
+
+    pub fn block(&self, cid: &Cid) -> Result<&[u8], CarError> {
+        self.blocks
+            .get(cid)
+            .map(|block| block.as_slice())
+            .ok_or(CarError::MissingBlock(*cid))
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, cid: &Cid) -> Result<T, CarError> {
+        serde_ipld_dagcbor::from_slice(self.block(cid)?)
+            .map_err(|err| CarError::InvalidBlock(*cid, err.to_string()))
+    }
+
+    pub fn commit(&self) -> Result<Commit, CarError> {
+        self.decode(self.root()?)
+    }
+
+    // every record in the repository, in key order, as ("collection/rkey", record CID)
+    pub fn records(&self) -> Result<Vec<(String, Cid)>, CarError> {
+        let mut records = Vec::new();
+        self.walk(&self.commit()?.data, 0, &mut HashSet::new(), &mut records)?;
+        Ok(records)
+    }
+
+    pub fn mst(&self) -> Result<Mst, CarError> {
+        let commit = self.commit()?;
+        let mut nodes = Vec::new();
+        let root = self.subtree(&commit.data, None, 0, &mut HashSet::new(), &mut nodes)?;
+        Ok(Mst {
+            did: commit.did,
+            rev: commit.rev,
+            commit: *self.root()?,
+            root,
+            nodes,
+        })
+    }
+
+    fn subtree(
+        &self,
+        cid: &Cid,
+        parent_layer: Option<u32>,
+        depth: usize,
+        visited: &mut HashSet<Cid>,
+        nodes: &mut Vec<MstTreeNode>,
+    ) -> Result<MstSubtree, CarError> {
+        if !self.blocks.contains_key(cid) {
+            return Ok(MstSubtree::Missing(*cid));
+        }
+        enter(visited, cid, depth)?;
+        let node: MstNode = self.decode(cid)?;
+        let index = nodes.len();
+        nodes.push(MstTreeNode {
+            cid: *cid,
+            layer: 0,
+            left: None,
+            entries: Vec::new(),
+        });
+
+        let mut key: Vec<u8> = Vec::new();
+        let mut entries = Vec::new();
+        for entry in &node.entries {
+            if entry.prefix_len > key.len() {
+                return Err(CarError::InvalidBlock(
+                    *cid,
+                    String::from("key prefix is longer than the previous key"),
+                ));
+            }
+            key.truncate(entry.prefix_len);
+            key.extend_from_slice(&entry.key_suffix);
+            entries.push((key.clone(), entry));
+        }
+        /* an empty node (only ever the root of an empty repo) has no keys to work its layer out from */
+        let layer = entries
+            .first()
+            .map(|(key, _)| layer_for_key(key))
+            .or(parent_layer.map(|layer| layer.saturating_sub(1)))
+            .unwrap_or(0);
+
+        let left = match &node.left {
+            Some(left) => Some(self.subtree(left, Some(layer), depth + 1, visited, nodes)?),
+            None => None,
+        };
+        let mut tree_entries = Vec::new();
+        for (key, entry) in entries {
+            let right = match &entry.tree {
+                Some(tree) => Some(self.subtree(tree, Some(layer), depth + 1, visited, nodes)?),
+                None => None,
+            };
+            tree_entries.push(MstTreeEntry {
+                key: String::from_utf8_lossy(&key).into_owned(),
+                prefix_len: entry.prefix_len,
+                value: entry.value,
+                right,
+            });
+        }
+        nodes[index] = MstTreeNode {
+            cid: *cid,
+            layer,
+            left,
+            entries: tree_entries,
+        };
+        Ok(MstSubtree::Node(index))
+    }
+
+    /*
+    follows the tree from the commit down to `key`, the way a proof is checked: every node on the way
+    has to be in the file. returns the record's CID (None if the tree shows there's no such record)
+    along with the nodes that were passed through
+    */
+    pub fn find(&self, key: &str) -> Result<(Option<Cid>, Vec<Cid>), CarError> {
+        let mut path = Vec::new();
+        let mut visited = HashSet::new();
+        let mut next = Some(self.commit()?.data);
+        while let Some(cid) = next.take() {
+            enter(&mut visited, &cid, path.len())?;
+            path.push(cid);
+            let node: MstNode = self.decode(&cid)?;
+            let mut subtree = node.left;
+            let mut previous: Vec<u8> = Vec::new();
+            for entry in node.entries {
+                if entry.prefix_len > previous.len() {
+                    return Err(CarError::InvalidBlock(
+                        cid,
+                        String::from("key prefix is longer than the previous key"),
+                    ));
+                }
+                previous.truncate(entry.prefix_len);
+                previous.extend_from_slice(&entry.key_suffix);
+                match previous.as_slice().cmp(key.as_bytes()) {
+                    std::cmp::Ordering::Equal => return Ok((Some(entry.value), path)),
+                    std::cmp::Ordering::Greater => break,
+                    std::cmp::Ordering::Less => subtree = entry.tree,
+                }
+            }
+            next = subtree;
+        }
+        Ok((None, path))
+    }
+
+    fn walk(
+        &self,
+        node: &Cid,
+        depth: usize,
+        visited: &mut HashSet<Cid>,
+        records: &mut Vec<(String, Cid)>,
+    ) -> Result<(), CarError> {
+        enter(visited, node, depth)?;
+        let node: MstNode = self.decode(node)?;
+        if let Some(left) = &node.left {
+            self.walk(left, depth + 1, visited, records)?;
+        }
+        let mut key: Vec<u8> = Vec::new();
+        for entry in node.entries {
+            if entry.prefix_len > key.len() {
+                return Err(CarError::InvalidBlock(
+                    entry.value,
+                    String::from("key prefix is longer than the previous key"),
+                ));
+            }
+            key.truncate(entry.prefix_len);
+            key.extend_from_slice(&entry.key_suffix);
+            records.push((String::from_utf8_lossy(&key).into_owned(), entry.value));
+            if let Some(tree) = &entry.tree {
+                self.walk(tree, depth + 1, visited, records)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, entry, Repo};
+
+    /*
+    app.bsky.feed.post/a is on layer 2 and the other two keys on layer 0, so the root holds the first
+    and a subtree to its right holds the rest, the second of which shares "app.bsky." with the first
+    */
+    fn repository() -> (Car, [Cid; 3]) {
+        let mut repo = Repo::default();
+        let records = [repo.record("one"), repo.record("two"), repo.record("three")];
+        let leaf = repo.node(
+            None,
+            vec![
+                entry(0, "app.bsky.feed.post/b", records[1], None),
+                entry(9, "graph.follow/c", records[2], None),
+            ],
+        );
+        let root = repo.node(
+            None,
+            vec![entry(0, "app.bsky.feed.post/a", records[0], Some(leaf))],
+        );
+        (repo.commit(&fixtures::key(1), root), records)
+    }
+
+    #[test]
+    fn key_layers_match_the_spec_examples() {
+        for (key, layer) in [
+            ("", 0),
+            ("asdf", 0),
+            ("blue", 1),
+            ("2653ae71", 0),
+            ("88bfafc7", 2),
+            ("2a92d355", 4),
+            ("884976f5", 6),
+            ("app.bsky.feed.post/454397e440ec", 4),
+            ("app.bsky.feed.post/9adeb165882c", 8),
+        ] {
+            assert_eq!(layer_for_key(key.as_bytes()), layer, "{}", key);
+        }
+    }
+
+    #[test]
+    fn keys_are_rebuilt_from_their_prefixes() {
+        let (car, records) = repository();
+        assert_eq!(
+            car.records().unwrap(),
+            vec![
+                (String::from("app.bsky.feed.post/a"), records[0]),
+                (String::from("app.bsky.feed.post/b"), records[1]),
+                (String::from("app.bsky.graph.follow/c"), records[2]),
+            ]
+        );
+        let mst = car.mst().unwrap();
+        assert_eq!(mst.record_count(), 3);
+        assert_eq!(mst.records_under(&mst.root), 3);
+        let MstSubtree::Node(root) = mst.root else {
+            panic!("the root is in the file");
+        };
+        assert_eq!(mst.nodes[root].layer, 2);
+    }
+
+    #[test]
+    fn find_follows_the_tree_to_a_key_or_its_absence() {
+        let (car, records) = repository();
+        let root = car.commit().unwrap().data;
+        let (found, path) = car.find("app.bsky.feed.post/a").unwrap();
+        assert_eq!((found, path.len()), (Some(records[0]), 1));
+        let (found, path) = car.find("app.bsky.graph.follow/c").unwrap();
+        assert_eq!((found, path.len()), (Some(records[2]), 2));
+        assert_eq!(path[0], root);
+        assert_eq!(
+            car.find("app.bsky.feed.post/0").unwrap(),
+            (None, vec![root])
+        );
+        assert_eq!(car.find("app.bsky.feed.post/c").unwrap().0, None);
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let (car, _) = repository();
+        let read = Car::from_bytes(&car.to_bytes().unwrap()).unwrap();
+        assert_eq!(read.roots, car.roots);
+        assert_eq!(read.records().unwrap(), car.records().unwrap());
+    }
+
+    #[test]
+    fn tampered_block_is_refused_on_load() {
+        let (car, _) = repository();
+        let mut bytes = car.to_bytes().unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            Car::from_bytes(&bytes),
+            Err(CarError::InvalidBlock(..))
+        ));
+    }
+
+    #[test]
+    fn oversized_sections_are_truncated_not_overflowed() {
+        let (car, _) = repository();
+        let bytes = car.to_bytes().unwrap();
+        assert!(matches!(
+            Car::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CarError::Truncated)
+        ));
+        let mut huge = vec![0xff; 9];
+        huge.push(0x01);
+        assert!(matches!(Car::from_bytes(&huge), Err(CarError::Truncated)));
+    }
+
+    #[test]
+    fn a_node_reached_twice_is_refused() {
+        let mut repo = Repo::default();
+        let value = repo.record("one");
+        let leaf = repo.node(None, vec![entry(0, "app.bsky.feed.post/b", value, None)]);
+        let root = repo.node(
+            Some(leaf),
+            vec![entry(0, "app.bsky.feed.post/a", value, Some(leaf))],
+        );
+        let car = repo.commit(&fixtures::key(1), root);
+        assert!(matches!(car.records(), Err(CarError::InvalidBlock(..))));
+        assert!(matches!(car.mst(), Err(CarError::InvalidBlock(..))));
+    }
+}
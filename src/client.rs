@@ -0,0 +1,244 @@
+use atrium_api::xrpc::{HttpClient, XrpcClient};
+use http::{HeaderMap, Request, Response, StatusCode};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/*
+an XRPC client that retries transient failures, so that a PDS having a bad moment doesn't look the
+same as a record that doesn't exist.
+
+network errors, 429s and the 5xx statuses that usually mean "try again" are retried with exponential
+backoff. when the server says how long to wait, either through `Retry-After` (seconds) or atproto's
+`ratelimit-reset` (a unix timestamp), that wins over the backoff. every retry is reported through
+`RetryNotifier`, which is how a tab gets to say "rate limited, retrying in N s"
+*/
+
+const MAX_ATTEMPTS: u32 = 5;
+// nobody wants to stare at a tab for longer than this, whatever the server says
+const MAX_WAIT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub enum RetryReason {
+    RateLimited,
+    ServerError(StatusCode),
+    Unreachable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Retry {
+    pub reason: RetryReason,
+    pub wait: Duration,
+    // the attempt that is about to be made, starting from 2
+    pub attempt: u32,
+}
+
+impl std::fmt::Display for Retry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            RetryReason::RateLimited => write!(f, "Rate limited")?,
+            RetryReason::ServerError(status) => write!(f, "Server returned {}", status)?,
+            RetryReason::Unreachable => write!(f, "Couldn't reach the server")?,
+        };
+        write!(
+            f,
+            ", retrying in {} s (attempt {} of {})",
+            self.wait.as_secs().max(1),
+            self.attempt,
+            MAX_ATTEMPTS
+        )
+    }
+}
+
+pub type RetryNotifier = Arc<dyn Fn(Retry) + Send + Sync>;
+
+pub struct RetryingClient {
+    base_uri: String,
+    client: reqwest::Client,
+    notify: RetryNotifier,
+}
+
+impl RetryingClient {
+    pub fn new(base_uri: impl AsRef<str>, notify: RetryNotifier) -> Self {
+        Self {
+            base_uri: base_uri.as_ref().to_string(),
+            client: reqwest::Client::new(),
+            notify,
+        }
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn wait_before(attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+    let header = |name: &str| {
+        headers
+            .and_then(|headers| headers.get(name))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let wait = if let Some(seconds) = header("retry-after") {
+        Duration::from_secs(seconds)
+    } else if let Some(reset) = header("ratelimit-reset") {
+        Duration::from_secs(reset.saturating_sub(now))
+    } else {
+        Duration::from_secs(1 << (attempt - 1))
+    };
+    wait.min(MAX_WAIT)
+}
+
+pub type SendError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/*
+sends a request until the answer is something other than a transient failure, or the attempts run
+out, in which case the last answer is returned as it is. `request` builds the request again for
+every attempt
+*/
+pub async fn send_with_retries(
+    client: &reqwest::Client,
+    request: impl Fn() -> Result<reqwest::Request, SendError>,
+    notify: &RetryNotifier,
+) -> Result<reqwest::Response, SendError> {
+    let mut attempt = 1;
+    loop {
+        let (reason, headers) = match client.execute(request()?).await {
+            Ok(response) if is_transient(response.status()) && attempt < MAX_ATTEMPTS => {
+                let reason = match response.status() {
+                    StatusCode::TOO_MANY_REQUESTS => RetryReason::RateLimited,
+                    status => RetryReason::ServerError(status),
+                };
+                (reason, Some(response.headers().clone()))
+            }
+            Ok(response) => return Ok(response),
+            Err(err) if attempt < MAX_ATTEMPTS && (err.is_connect() || err.is_timeout()) => {
+                (RetryReason::Unreachable, None)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let wait = wait_before(attempt, headers.as_ref());
+        attempt += 1;
+        notify(Retry {
+            reason,
+            wait,
+            attempt,
+        });
+        tokio::time::sleep(wait).await;
+    }
+}
+
+// a GET from outside of XRPC, like a DID document or plc.directory, retried the same way
+pub async fn get(url: &str, notify: &RetryNotifier) -> Result<reqwest::Response, SendError> {
+    let client = reqwest::Client::new();
+    send_with_retries(&client, || Ok(client.get(url).build()?), notify).await
+}
+
+impl HttpClient for RetryingClient {
+    async fn send_http(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>, SendError> {
+        let (parts, body) = request.into_parts();
+        let request = || -> Result<reqwest::Request, SendError> {
+            let mut builder = Request::builder()
+                .method(parts.method.clone())
+                .uri(parts.uri.clone());
+            for (name, value) in parts.headers.iter() {
+                builder = builder.header(name, value);
+            }
+            Ok(builder.body(body.clone())?.try_into()?)
+        };
+        let response = send_with_retries(&self.client, request, &self.notify).await?;
+        let mut builder = Response::builder().status(response.status());
+        for (name, value) in response.headers() {
+            builder = builder.header(name, value);
+        }
+        Ok(builder.body(response.bytes().await?.to_vec())?)
+    }
+}
+
+impl XrpcClient for RetryingClient {
+    fn base_uri(&self) -> String {
+        self.base_uri.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn only_try_again_statuses_are_transient() {
+        assert!(is_transient(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_transient(StatusCode::BAD_GATEWAY));
+        assert!(is_transient(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_transient(StatusCode::BAD_REQUEST));
+        assert!(!is_transient(StatusCode::NOT_FOUND));
+        assert!(!is_transient(StatusCode::NOT_IMPLEMENTED));
+    }
+
+    #[test]
+    fn backoff_doubles_without_headers() {
+        assert_eq!(wait_before(1, None), Duration::from_secs(1));
+        assert_eq!(wait_before(3, None), Duration::from_secs(4));
+        assert_eq!(
+            wait_before(4, Some(&HeaderMap::new())),
+            Duration::from_secs(8)
+        );
+    }
+
+    #[test]
+    fn retry_after_wins_over_backoff() {
+        let wait = wait_before(4, Some(&headers("retry-after", " 2 ")));
+        assert_eq!(wait, Duration::from_secs(2));
+
+        // only the seconds form is read, an HTTP date falls back to backing off
+        let date = headers("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(wait_before(2, Some(&date)), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn ratelimit_reset_counts_down_to_the_timestamp() {
+        let wait = wait_before(
+            1,
+            Some(&headers("ratelimit-reset", &(now() + 30).to_string())),
+        );
+        assert!(wait <= Duration::from_secs(30) && wait >= Duration::from_secs(29));
+
+        // a reset that has already passed means going again straight away
+        let past = headers("ratelimit-reset", &(now() - 60).to_string());
+        assert_eq!(wait_before(3, Some(&past)), Duration::ZERO);
+    }
+
+    #[test]
+    fn waits_are_clamped() {
+        let long = headers("retry-after", "86400");
+        assert_eq!(wait_before(1, Some(&long)), MAX_WAIT);
+        let far = headers("ratelimit-reset", &(now() + 86400).to_string());
+        assert_eq!(wait_before(1, Some(&far)), MAX_WAIT);
+        assert_eq!(wait_before(MAX_ATTEMPTS + 20, None), MAX_WAIT);
+    }
+}
@@ -0,0 +1,424 @@
+use crate::agent::AgentError;
+use crate::cache::{Cache, CacheKind};
+use crate::car::Car;
+use crate::firehose::CommitEvent;
+use crate::types::AtUri;
+use atrium_api::com::atproto::repo::get_record;
+use atrium_api::types::Unknown;
+use ipld_core::cid::Cid;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/*
+what changed in a repository between two revisions, record by record. a diff comes either from two
+exports of the whole repository, where both sides of every change are known, or from a #commit event,
+which only carries the new side. the old side of a commit's changes is filled in from the cache, when
+what Branches last saw of the record is the version the commit replaced
+*/
+
+#[derive(Debug, Clone)]
+pub enum DiffSource {
+    // two exports of the same repository, in either order
+    Cars(PathBuf, PathBuf),
+    Commit(Box<CommitEvent>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "Created",
+            ChangeKind::Updated => "Updated",
+            ChangeKind::Deleted => "Deleted",
+        }
+    }
+
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "success",
+            ChangeKind::Updated => "accent",
+            ChangeKind::Deleted => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordVersion {
+    // None if the commit didn't say, as relays older than `prev` on ops don't
+    pub cid: Option<Cid>,
+    // None if the value isn't available
+    pub value: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordChange {
+    // "collection/rkey"
+    pub key: String,
+    pub kind: ChangeKind,
+    pub old: Option<RecordVersion>,
+    pub new: Option<RecordVersion>,
+}
+
+impl RecordChange {
+    // the top-level fields that differ, when both sides are known
+    pub fn changed_fields(&self) -> Vec<String> {
+        let (Some(serde_json::Value::Object(old)), Some(serde_json::Value::Object(new))) = (
+            self.old.as_ref().and_then(|old| old.value.as_ref()),
+            self.new.as_ref().and_then(|new| new.value.as_ref()),
+        ) else {
+            return vec![];
+        };
+        let mut fields: Vec<String> = old
+            .keys()
+            .chain(new.keys())
+            .filter(|field| old.get(*field) != new.get(*field))
+            .cloned()
+            .collect();
+        fields.sort();
+        fields.dedup();
+        fields
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RepoDiff {
+    pub did: String,
+    // the revision the changes were made on top of, if known
+    pub from: Option<String>,
+    pub to: String,
+    // in key order
+    pub changes: Vec<RecordChange>,
+}
+
+impl RepoDiff {
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .count()
+    }
+}
+
+fn version(car: &Car, cid: &Cid) -> RecordVersion {
+    RecordVersion {
+        cid: Some(*cid),
+        value: car
+            .decode::<Unknown>(cid)
+            .ok()
+            .and_then(|value| serde_json::to_value(value).ok()),
+    }
+}
+
+fn open_car(path: &Path) -> Result<Car, AgentError> {
+    let bytes = std::fs::read(path)
+        .map_err(|err| AgentError::Io(format!("Couldn't read {}: {}", path.display(), err)))?;
+    Car::from_bytes(&bytes).map_err(|err| AgentError::InvalidCar(err.to_string()))
+}
+
+// the older export (by revision) is taken as the "before", whichever order they were given in
+pub fn diff_cars(first: &Path, second: &Path) -> Result<RepoDiff, AgentError> {
+    let invalid = |err: crate::car::CarError| AgentError::InvalidCar(err.to_string());
+    let (first, second) = (open_car(first)?, open_car(second)?);
+    let (first_commit, second_commit) = (
+        first.commit().map_err(invalid)?,
+        second.commit().map_err(invalid)?,
+    );
+    if first_commit.did != second_commit.did {
+        return Err(AgentError::InvalidCar(format!(
+            "the exports are of different repositories, {} and {}",
+            first_commit.did, second_commit.did
+        )));
+    }
+    if first_commit.rev <= second_commit.rev {
+        diff_repos(&first, &second)
+    } else {
+        diff_repos(&second, &first)
+    }
+}
+
+// every record that differs between two copies of a repository
+pub fn diff_repos(old: &Car, new: &Car) -> Result<RepoDiff, AgentError> {
+    let invalid = |err: crate::car::CarError| AgentError::InvalidCar(err.to_string());
+    let (old_commit, new_commit) = (
+        old.commit().map_err(invalid)?,
+        new.commit().map_err(invalid)?,
+    );
+    let before: BTreeMap<String, Cid> = old.records().map_err(invalid)?.into_iter().collect();
+    let after: BTreeMap<String, Cid> = new.records().map_err(invalid)?.into_iter().collect();
+    let mut changes = vec![];
+    for (key, cid) in &before {
+        match after.get(key) {
+            None => changes.push(RecordChange {
+                key: key.clone(),
+                kind: ChangeKind::Deleted,
+                old: Some(version(old, cid)),
+                new: None,
+            }),
+            Some(new_cid) if new_cid != cid => changes.push(RecordChange {
+                key: key.clone(),
+                kind: ChangeKind::Updated,
+                old: Some(version(old, cid)),
+                new: Some(version(new, new_cid)),
+            }),
+            Some(_) => (),
+        }
+    }
+    for (key, cid) in &after {
+        if !before.contains_key(key) {
+            changes.push(RecordChange {
+                key: key.clone(),
+                kind: ChangeKind::Created,
+                old: None,
+                new: Some(version(new, cid)),
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(RepoDiff {
+        did: new_commit.did,
+        from: Some(old_commit.rev),
+        to: new_commit.rev,
+        changes,
+    })
+}
+
+pub fn diff_commit(commit: &CommitEvent, cache: &Cache) -> Result<RepoDiff, AgentError> {
+    let blocks =
+        Car::from_bytes(&commit.blocks).map_err(|err| AgentError::InvalidCar(err.to_string()))?;
+    let changes = commit
+        .ops
+        .iter()
+        .map(|op| {
+            let kind = match op.action.as_str() {
+                "create" => ChangeKind::Created,
+                "delete" => ChangeKind::Deleted,
+                _ => ChangeKind::Updated,
+            };
+            let old = (kind != ChangeKind::Created).then(|| {
+                let cached = format!("at://{}/{}", commit.repo, op.path)
+                    .parse::<AtUri>()
+                    .ok()
+                    .and_then(|uri| {
+                        cache.get::<get_record::OutputData>(CacheKind::Record, &uri.to_string())
+                    })
+                    .map(|cached| cached.value);
+                RecordVersion {
+                    cid: op.prev,
+                    value: cached
+                        .filter(|cached| {
+                            cached.cid.as_ref().map(|cid| *cid.as_ref()) == op.prev
+                                && op.prev.is_some()
+                        })
+                        .and_then(|cached| serde_json::to_value(cached.value).ok()),
+                }
+            });
+            RecordChange {
+                key: op.path.clone(),
+                kind,
+                old,
+                new: op.cid.map(|cid| version(&blocks, &cid)),
+            }
+        })
+        .collect();
+    Ok(RepoDiff {
+        did: commit.repo.clone(),
+        from: commit.since.clone(),
+        to: commit.rev.clone(),
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::car::Commit;
+    use crate::firehose::RepoOp;
+    use crate::fixtures::{self, entry, Repo};
+    use crate::verify::cid_for;
+    use serde_json::json;
+
+    // a repository holding a record for each (key, text), which have to be in key order
+    fn repository(records: &[(&str, &str)]) -> Car {
+        let mut repo = Repo::default();
+        let entries = records
+            .iter()
+            .map(|(key, text)| entry(0, key, repo.record(text), None))
+            .collect();
+        let data = repo.node(None, entries);
+        repo.commit(&fixtures::key(1), data)
+    }
+
+    // the same repository under a changed commit, whose signature no longer matches
+    fn amended(mut car: Car, amend: impl FnOnce(&mut Commit)) -> Car {
+        let mut commit = car.commit().unwrap();
+        amend(&mut commit);
+        let bytes = serde_ipld_dagcbor::to_vec(&commit).unwrap();
+        let root = cid_for(&bytes);
+        car.blocks.insert(root, bytes);
+        car.roots = vec![root];
+        car
+    }
+
+    fn record_cid(text: &str) -> Cid {
+        Repo::default().record(text)
+    }
+
+    fn written(car: &Car, name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("branches-diff-{}-{}.car", std::process::id(), name));
+        std::fs::write(&path, car.to_bytes().unwrap()).unwrap();
+        path
+    }
+
+    fn before() -> Car {
+        repository(&[
+            ("app.bsky.feed.post/a", "one"),
+            ("app.bsky.feed.post/b", "two"),
+            ("app.bsky.feed.post/c", "three"),
+        ])
+    }
+
+    fn after() -> Car {
+        let car = repository(&[
+            ("app.bsky.feed.post/a", "one"),
+            ("app.bsky.feed.post/b", "deux"),
+            ("app.bsky.feed.post/d", "four"),
+        ]);
+        amended(car, |commit| commit.rev = String::from("3l3qo2vutsw2c"))
+    }
+
+    #[test]
+    fn exports_differ_by_created_updated_and_deleted_records() {
+        let diff = diff_repos(&before(), &after()).unwrap();
+        let changes: Vec<(&str, ChangeKind)> = diff
+            .changes
+            .iter()
+            .map(|change| (change.key.as_str(), change.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("app.bsky.feed.post/b", ChangeKind::Updated),
+                ("app.bsky.feed.post/c", ChangeKind::Deleted),
+                ("app.bsky.feed.post/d", ChangeKind::Created),
+            ]
+        );
+        assert_eq!(
+            (diff.from.as_deref(), diff.to.as_str()),
+            (Some("3l3qo2vutsw2b"), "3l3qo2vutsw2c")
+        );
+
+        let updated = &diff.changes[0];
+        assert_eq!(
+            updated.old.as_ref().unwrap().value,
+            Some(json!({ "text": "two" }))
+        );
+        assert_eq!(
+            updated.new.as_ref().unwrap().value,
+            Some(json!({ "text": "deux" }))
+        );
+        assert_eq!(updated.changed_fields(), vec!["text"]);
+        assert!(diff.changes[1].new.is_none());
+        assert!(diff.changes[2].old.is_none());
+    }
+
+    #[test]
+    fn exports_are_put_in_revision_order() {
+        let (old, new) = (written(&before(), "old"), written(&after(), "new"));
+        let diff = diff_cars(&new, &old).unwrap();
+        assert_eq!(diff.to, "3l3qo2vutsw2c");
+        assert!(diff.changes.iter().any(
+            |change| change.key == "app.bsky.feed.post/d" && change.kind == ChangeKind::Created
+        ));
+
+        let mallory = amended(before(), |commit| {
+            commit.did = String::from("did:plc:mallory")
+        });
+        let mallory = written(&mallory, "mallory");
+        assert!(matches!(
+            diff_cars(&old, &mallory),
+            Err(AgentError::InvalidCar(_))
+        ));
+    }
+
+    #[test]
+    fn commits_only_take_the_old_side_from_the_version_they_replaced() {
+        let cache = Cache::temporary("diff-commit", u64::MAX);
+        for (path, text) in [
+            ("app.bsky.feed.post/b", "two"),
+            ("app.bsky.feed.post/c", "three"),
+            ("app.bsky.feed.post/d", "four"),
+        ] {
+            let uri = format!("at://did:plc:alice/{}", path);
+            let record = get_record::OutputData {
+                cid: Some(atrium_api::types::string::Cid::new(record_cid(text))),
+                uri: uri.clone(),
+                value: serde_json::from_value(json!({ "text": text })).unwrap(),
+            };
+            let key = uri.parse::<AtUri>().unwrap().to_string();
+            cache.put(CacheKind::Record, &key, &record);
+        }
+
+        let blocks = repository(&[
+            ("app.bsky.feed.post/b", "deux"),
+            ("app.bsky.feed.post/e", "five"),
+        ]);
+        let op = |action: &str, path: &str, cid: Option<&str>, prev: Option<&str>| RepoOp {
+            action: action.to_string(),
+            path: path.to_string(),
+            cid: cid.map(record_cid),
+            prev: prev.map(record_cid),
+        };
+        let commit = CommitEvent {
+            seq: 1,
+            repo: String::from("did:plc:alice"),
+            commit: *blocks.root().unwrap(),
+            rev: String::from("3l3qo2vutsw2c"),
+            since: Some(String::from("3l3qo2vutsw2b")),
+            blocks: blocks.to_bytes().unwrap(),
+            ops: vec![
+                op("update", "app.bsky.feed.post/b", Some("deux"), Some("two")),
+                // the cache holds a different version from the one this replaced
+                op(
+                    "update",
+                    "app.bsky.feed.post/c",
+                    Some("five"),
+                    Some("trois"),
+                ),
+                // from a relay too old to send prev
+                op("delete", "app.bsky.feed.post/d", None, None),
+                op("create", "app.bsky.feed.post/e", Some("five"), None),
+            ],
+            too_big: false,
+            time: String::from("2024-01-01T00:00:00.000Z"),
+        };
+        let diff = diff_commit(&commit, &cache).unwrap();
+        let old: Vec<Option<(Option<Cid>, Option<serde_json::Value>)>> = diff
+            .changes
+            .iter()
+            .map(|change| change.old.clone().map(|old| (old.cid, old.value)))
+            .collect();
+        assert_eq!(
+            old,
+            vec![
+                Some((Some(record_cid("two")), Some(json!({ "text": "two" })))),
+                Some((Some(record_cid("trois")), None)),
+                Some((None, None)),
+                None,
+            ]
+        );
+        assert_eq!(diff.changes[0].changed_fields(), vec!["text"]);
+        assert_eq!(
+            diff.changes[3].new.as_ref().unwrap().value,
+            Some(json!({ "text": "five" }))
+        );
+        assert!(diff.changes[2].new.is_none());
+        assert_eq!(diff.from.as_deref(), Some("3l3qo2vutsw2b"));
+    }
+}
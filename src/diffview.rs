@@ -0,0 +1,193 @@
+use crate::diff::{ChangeKind, RecordChange, RecordVersion, RepoDiff};
+use crate::types::AtUri;
+use relm4::adw::prelude::*;
+use relm4::gtk::glib;
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+use relm4::{gtk, ComponentParts, ComponentSender, SimpleComponent};
+use std::cell::Cell;
+use std::rc::Rc;
+
+/*
+what changed in a repository between two revisions, one expander per record with its old and new
+values side by side. comparing two exports can turn up thousands of changes, so a change's values are
+only laid out the first time it is expanded
+*/
+#[derive(Debug)]
+pub struct DiffView {
+    diff: Rc<RepoDiff>,
+}
+
+#[derive(Debug)]
+pub enum DiffOutput {
+    Open(AtUri),
+}
+
+fn version_column(heading: &str, version: Option<&RecordVersion>, missing: &str) -> gtk::Box {
+    let column = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    column.set_hexpand(true);
+    let heading = gtk::Label::new(Some(heading));
+    heading.set_xalign(0.0);
+    heading.add_css_class("heading");
+    column.append(&heading);
+
+    let cid = gtk::Label::new(Some(
+        &version
+            .and_then(|version| version.cid)
+            .map(|cid| cid.to_string())
+            .unwrap_or_default(),
+    ));
+    cid.set_xalign(0.0);
+    cid.set_selectable(true);
+    cid.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
+    cid.add_css_class("dim-label");
+    cid.add_css_class("monospace");
+    column.append(&cid);
+
+    let value = match version.and_then(|version| version.value.as_ref()) {
+        Some(value) => {
+            let label = gtk::Label::new(Some(
+                &serde_json::to_string_pretty(value).unwrap_or_default(),
+            ));
+            label.add_css_class("monospace");
+            label.set_selectable(true);
+            label
+        }
+        None => {
+            let label = gtk::Label::new(Some(missing));
+            label.add_css_class("dim-label");
+            label
+        }
+    };
+    value.set_xalign(0.0);
+    value.set_yalign(0.0);
+    value.set_wrap(true);
+    value.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+    column.append(&value);
+    column
+}
+
+fn missing_old(change: &RecordChange) -> &'static str {
+    match (change.kind, change.old.as_ref().and_then(|old| old.cid)) {
+        (ChangeKind::Created, _) => "The record didn't exist yet",
+        (_, None) => "The relay didn't say which version this replaced",
+        (_, Some(_)) => "Not available, Branches hadn't seen this version of the record",
+    }
+}
+
+fn change_row(did: &str, change: &RecordChange, sender: &ComponentSender<DiffView>) -> gtk::Widget {
+    let row = adw::ExpanderRow::new();
+    row.set_title(&glib::markup_escape_text(&change.key));
+    let fields = change.changed_fields();
+    if !fields.is_empty() {
+        row.set_subtitle(&glib::markup_escape_text(&format!(
+            "changed {}",
+            fields.join(", ")
+        )));
+    }
+    row.add_css_class("property");
+    let badge = gtk::Label::new(Some(change.kind.label()));
+    badge.set_css_classes(&["caption-heading", change.kind.css_class()]);
+    badge.set_width_chars(8);
+    badge.set_xalign(0.0);
+    row.add_prefix(&badge);
+
+    if change.kind != ChangeKind::Deleted {
+        if let Ok(uri) = format!("at://{}/{}", did, change.key).parse::<AtUri>() {
+            let open = gtk::Button::from_icon_name("go-next-symbolic");
+            open.set_tooltip_text(Some("Open the Record as It Is Now"));
+            open.set_valign(gtk::Align::Center);
+            open.add_css_class("flat");
+            let sender = sender.clone();
+            open.connect_clicked(move |_| {
+                sender.output(DiffOutput::Open(uri.clone())).unwrap();
+            });
+            row.add_suffix(&open);
+        }
+    }
+
+    let populated = Cell::new(false);
+    let change = change.clone();
+    row.connect_expanded_notify(move |row| {
+        if !row.is_expanded() || populated.replace(true) {
+            return;
+        }
+        let sides = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        sides.set_homogeneous(true);
+        sides.set_margin_all(10);
+        sides.append(&version_column(
+            "Before",
+            change.old.as_ref(),
+            missing_old(&change),
+        ));
+        sides.append(&version_column(
+            "After",
+            change.new.as_ref(),
+            "The record was deleted",
+        ));
+        row.add_row(&sides);
+    });
+    row.upcast()
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for DiffView {
+    type Init = RepoDiff;
+    type Input = ();
+    type Output = DiffOutput;
+
+    view! {
+        #[root]
+        gtk::ListBox {
+            set_hexpand: true,
+            set_margin_all: 10,
+            set_selection_mode: gtk::SelectionMode::None,
+            inline_css: "border-radius: 10px",
+            adw::ActionRow {
+                set_title: "Repository",
+                set_subtitle: &model.diff.did,
+                add_css_class: "property",
+            },
+            adw::ActionRow {
+                set_title: "Revisions",
+                set_subtitle: &format!(
+                    "{} → {}",
+                    model.diff.from.as_deref().unwrap_or("unknown"),
+                    model.diff.to
+                ),
+                add_css_class: "property",
+            },
+            adw::ActionRow {
+                set_title: "Changes",
+                set_subtitle: &format!(
+                    "{} created, {} updated, {} deleted",
+                    model.diff.count(ChangeKind::Created),
+                    model.diff.count(ChangeKind::Updated),
+                    model.diff.count(ChangeKind::Deleted)
+                ),
+                add_css_class: "property",
+            },
+        }
+    }
+
+    fn init(
+        diff: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {
+            diff: Rc::new(diff),
+        };
+        let widgets = view_output!();
+        for change in &model.diff.changes {
+            root.append(&change_row(&model.diff.did, change, &sender));
+        }
+        if model.diff.changes.is_empty() {
+            let row = adw::ActionRow::new();
+            row.set_title("No records changed");
+            row.add_css_class("property");
+            root.append(&row);
+        }
+        ComponentParts { model, widgets }
+    }
+}
@@ -0,0 +1,783 @@
+use crate::jetstream::{decode_message, JetstreamCommit};
+use futures::StreamExt;
+use ipld_core::cid::Cid;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{sleep_until, Instant};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/*
+com.atproto.sync.subscribeRepos, the stream of everything happening to the repositories a relay or
+PDS hosts. every WebSocket message is a frame of two DAG-CBOR objects back to back: a header saying
+what kind of message follows (`op` 1, with the kind in `t`), or that the server is giving up (`op` -1),
+and then the message itself
+*/
+
+#[derive(Deserialize)]
+struct FrameHeader {
+    op: i64,
+    t: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorFrame {
+    error: String,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepoOp {
+    // create, update or delete
+    pub action: String,
+    // collection/rkey
+    pub path: String,
+    // None for deletes
+    pub cid: Option<Cid>,
+    // what the record was before, on relays new enough to send it
+    pub prev: Option<Cid>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitEvent {
+    pub seq: i64,
+    pub repo: String,
+    pub commit: Cid,
+    pub rev: String,
+    pub since: Option<String>,
+    // a CAR slice holding the commit and the tree nodes and records it changed
+    #[serde(with = "serde_bytes")]
+    pub blocks: Vec<u8>,
+    pub ops: Vec<RepoOp>,
+    #[serde(default)]
+    pub too_big: bool,
+    pub time: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdentityEvent {
+    pub seq: i64,
+    pub did: String,
+    pub time: String,
+    pub handle: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountEvent {
+    pub seq: i64,
+    pub did: String,
+    pub time: String,
+    pub active: bool,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SyncEvent {
+    pub seq: i64,
+    pub did: String,
+    pub rev: String,
+    pub time: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InfoEvent {
+    pub name: String,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum FirehoseEvent {
+    Commit(CommitEvent),
+    Identity(IdentityEvent),
+    Account(AccountEvent),
+    Sync(SyncEvent),
+    Info(InfoEvent),
+    JetstreamCommit(JetstreamCommit),
+    // a kind of message newer than Branches
+    Other(String),
+}
+
+impl FirehoseEvent {
+    pub fn kind(&self) -> &str {
+        match self {
+            FirehoseEvent::Commit(_) | FirehoseEvent::JetstreamCommit(_) => "#commit",
+            FirehoseEvent::Identity(_) => "#identity",
+            FirehoseEvent::Account(_) => "#account",
+            FirehoseEvent::Sync(_) => "#sync",
+            FirehoseEvent::Info(_) => "#info",
+            FirehoseEvent::Other(kind) => kind,
+        }
+    }
+
+    pub fn did(&self) -> Option<&str> {
+        match self {
+            FirehoseEvent::Commit(commit) => Some(&commit.repo),
+            FirehoseEvent::JetstreamCommit(commit) => Some(&commit.did),
+            FirehoseEvent::Identity(identity) => Some(&identity.did),
+            FirehoseEvent::Account(account) => Some(&account.did),
+            FirehoseEvent::Sync(sync) => Some(&sync.did),
+            FirehoseEvent::Info(_) | FirehoseEvent::Other(_) => None,
+        }
+    }
+
+    pub fn seq(&self) -> Option<i64> {
+        match self {
+            FirehoseEvent::Commit(commit) => Some(commit.seq),
+            FirehoseEvent::Identity(identity) => Some(identity.seq),
+            FirehoseEvent::Account(account) => Some(account.seq),
+            FirehoseEvent::Sync(sync) => Some(sync.seq),
+            FirehoseEvent::Info(_)
+            | FirehoseEvent::JetstreamCommit(_)
+            | FirehoseEvent::Other(_) => None,
+        }
+    }
+
+    pub fn time(&self) -> Option<&str> {
+        match self {
+            FirehoseEvent::Commit(commit) => Some(&commit.time),
+            FirehoseEvent::JetstreamCommit(commit) => Some(&commit.time),
+            FirehoseEvent::Identity(identity) => Some(&identity.time),
+            FirehoseEvent::Account(account) => Some(&account.time),
+            FirehoseEvent::Sync(sync) => Some(&sync.time),
+            FirehoseEvent::Info(_) | FirehoseEvent::Other(_) => None,
+        }
+    }
+}
+
+/*
+what to keep of a firehose. each part left empty lets everything through. collections are NSIDs, or
+NSID prefixes ending in `*` (`app.bsky.feed.*`), and only narrow down commits: the other kinds of
+event aren't about any one collection
+*/
+#[derive(Debug, Clone, Default)]
+pub struct FirehoseFilter {
+    pub dids: Vec<String>,
+    pub collections: Vec<String>,
+    // kinds are hidden rather than chosen, so kinds newer than Branches still show up
+    pub hidden_kinds: Vec<String>,
+}
+
+pub fn collection_matches(pattern: &str, nsid: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => nsid.starts_with(prefix),
+        None => pattern == nsid,
+    }
+}
+
+impl FirehoseFilter {
+    pub fn is_empty(&self) -> bool {
+        self.dids.is_empty() && self.collections.is_empty() && self.hidden_kinds.is_empty()
+    }
+
+    fn shows_op(&self, op: &RepoOp) -> bool {
+        let collection = op.path.split('/').next().unwrap_or_default();
+        self.collections
+            .iter()
+            .any(|pattern| collection_matches(pattern, collection))
+    }
+
+    // the part of the event that gets through, commits keeping only the ops in matching collections
+    pub fn apply(&self, event: FirehoseEvent) -> Option<FirehoseEvent> {
+        if self.hidden_kinds.iter().any(|kind| kind == event.kind()) {
+            return None;
+        }
+        if !self.dids.is_empty()
+            && !event
+                .did()
+                .is_some_and(|did| self.dids.iter().any(|d| d == did))
+        {
+            return None;
+        }
+        match event {
+            FirehoseEvent::JetstreamCommit(commit)
+                if !self.collections.is_empty() && !self.shows_op(&commit.op) =>
+            {
+                None
+            }
+            FirehoseEvent::Commit(mut commit) if !self.collections.is_empty() => {
+                commit.ops.retain(|op| self.shows_op(op));
+                if commit.ops.is_empty() {
+                    None
+                } else {
+                    Some(FirehoseEvent::Commit(commit))
+                }
+            }
+            event => Some(event),
+        }
+    }
+}
+
+// a header is a flat map, anything nested deeper than this is an attempt to exhaust the stack
+const MAX_CBOR_DEPTH: usize = 64;
+
+/*
+how many bytes the CBOR item at the start of `bytes` takes up. only used to find where the header ends,
+so it only has to understand what a header can contain
+*/
+fn cbor_item_len(bytes: &[u8], depth: usize) -> Option<usize> {
+    if depth > MAX_CBOR_DEPTH {
+        return None;
+    }
+    let initial = *bytes.first()?;
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    let (argument, header_len) = match info {
+        0..=23 => (info as u64, 1),
+        24 => (*bytes.get(1)? as u64, 2),
+        25 => (
+            u16::from_be_bytes(bytes.get(1..3)?.try_into().ok()?) as u64,
+            3,
+        ),
+        26 => (
+            u32::from_be_bytes(bytes.get(1..5)?.try_into().ok()?) as u64,
+            5,
+        ),
+        27 => (u64::from_be_bytes(bytes.get(1..9)?.try_into().ok()?), 9),
+        _ => return None,
+    };
+    match major {
+        // integers
+        0 | 1 => Some(header_len),
+        // byte and text strings
+        2 | 3 => header_len.checked_add(usize::try_from(argument).ok()?),
+        // arrays and maps
+        4 | 5 => {
+            let items = if major == 5 {
+                argument.saturating_mul(2)
+            } else {
+                argument
+            };
+            let mut len = header_len;
+            for _ in 0..items {
+                len += cbor_item_len(bytes.get(len..)?, depth + 1)?;
+            }
+            Some(len)
+        }
+        // simple values (null, booleans)
+        7 => Some(header_len),
+        _ => None,
+    }
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    serde_ipld_dagcbor::from_slice(bytes).map_err(|err| err.to_string())
+}
+
+pub fn decode_frame(frame: &[u8]) -> Result<FirehoseEvent, String> {
+    let header_len = cbor_item_len(frame, 0)
+        .filter(|len| *len <= frame.len())
+        .ok_or("the frame header isn't valid CBOR")?;
+    let header: FrameHeader = decode(&frame[..header_len])?;
+    let body = &frame[header_len..];
+    if header.op == -1 {
+        let error: ErrorFrame = decode(body)?;
+        return Err(match error.message {
+            Some(message) => format!("{}: {}", error.error, message),
+            None => error.error,
+        });
+    }
+    Ok(match header.t.as_deref() {
+        Some("#commit") => FirehoseEvent::Commit(decode(body)?),
+        Some("#identity") => FirehoseEvent::Identity(decode(body)?),
+        Some("#account") => FirehoseEvent::Account(decode(body)?),
+        Some("#sync") => FirehoseEvent::Sync(decode(body)?),
+        Some("#info") => FirehoseEvent::Info(decode(body)?),
+        Some(kind) => FirehoseEvent::Other(kind.to_string()),
+        None => return Err(String::from("the frame header has no type")),
+    })
+}
+
+/*
+relays and Jetstream instances are usually given by their host alone, so the scheme is filled in when
+it is missing, and an http(s) address (what a local PDS is usually known by) is switched to the
+WebSocket one
+*/
+pub fn websocket_base(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else if url.starts_with("wss://") || url.starts_with("ws://") {
+        url.to_string()
+    } else {
+        format!("wss://{}", url)
+    }
+}
+
+// the subscribeRepos endpoint is filled in when it is missing too
+pub fn endpoint_for(url: &str) -> String {
+    let url = websocket_base(url);
+    if url.contains("/xrpc/") {
+        url
+    } else {
+        format!("{}/xrpc/com.atproto.sync.subscribeRepos", url)
+    }
+}
+
+// what a firehose tab is showing
+#[derive(Debug, Clone)]
+pub enum FirehoseSource {
+    // a relay or PDS
+    Live(String),
+    // a Jetstream instance
+    Jetstream(String),
+    // a session recorded earlier
+    Replay(PathBuf),
+}
+
+/*
+a recording is a session's frames exactly as they arrived, so that a replay goes through the same
+decoding (and the same bugs) the live stream did. after the header, each frame is written as the unix
+time it arrived in milliseconds (8 bytes), whether it was binary or text (1 byte) and its length (4
+bytes), all big-endian, followed by the frame itself
+*/
+const RECORDING_HEADER: &[u8] = b"branches firehose recording 1\n";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    // DAG-CBOR, as subscribeRepos sends
+    Binary,
+    // JSON, as Jetstream sends
+    Text,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    pub received: u64,
+    pub kind: FrameKind,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Recording {
+    pub path: PathBuf,
+    pub frames: u64,
+    file: BufWriter<File>,
+    // once writing fails the recording stops, and this is reported when it's finished
+    error: Option<io::Error>,
+}
+
+// shared between a view and its connection, so recording can be started and stopped at any time
+pub type Recorder = Arc<Mutex<Option<Recording>>>;
+
+impl Recording {
+    pub fn create(path: PathBuf) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(&path)?);
+        file.write_all(RECORDING_HEADER)?;
+        Ok(Self {
+            path,
+            frames: 0,
+            file,
+            error: None,
+        })
+    }
+
+    pub fn write(&mut self, kind: FrameKind, frame: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        let received = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let kind = match kind {
+            FrameKind::Binary => 0u8,
+            FrameKind::Text => 1u8,
+        };
+        let result = self
+            .file
+            .write_all(&received.to_be_bytes())
+            .and_then(|_| self.file.write_all(&[kind]))
+            .and_then(|_| self.file.write_all(&(frame.len() as u32).to_be_bytes()))
+            .and_then(|_| self.file.write_all(frame));
+        match result {
+            Ok(()) => self.frames += 1,
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    // how many frames were recorded
+    pub fn finish(mut self) -> io::Result<u64> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.file.flush().map(|_| self.frames),
+        }
+    }
+}
+
+pub fn read_recording(bytes: &[u8]) -> Result<Vec<RecordedFrame>, String> {
+    let mut rest = bytes
+        .strip_prefix(RECORDING_HEADER)
+        .ok_or("this isn't a firehose recording made by Branches")?;
+    let mut frames = vec![];
+    while !rest.is_empty() {
+        /* a recording cut short (Branches quitting mid-write, say) still replays up to the cut */
+        if rest.len() < 13 {
+            break;
+        }
+        let received = u64::from_be_bytes(rest[..8].try_into().unwrap());
+        let kind = match rest[8] {
+            0 => FrameKind::Binary,
+            1 => FrameKind::Text,
+            kind => return Err(format!("a frame of unknown kind {}", kind)),
+        };
+        let len = u32::from_be_bytes(rest[9..13].try_into().unwrap()) as usize;
+        let Some(bytes) = rest.get(13..13 + len) else {
+            break;
+        };
+        frames.push(RecordedFrame {
+            received,
+            kind,
+            bytes: bytes.to_vec(),
+        });
+        rest = &rest[13 + len..];
+    }
+    Ok(frames)
+}
+
+fn decode_recorded(frame: &RecordedFrame) -> Result<FirehoseEvent, String> {
+    match frame.kind {
+        FrameKind::Binary => decode_frame(&frame.bytes),
+        FrameKind::Text => std::str::from_utf8(&frame.bytes)
+            .map_err(|err| err.to_string())
+            .and_then(decode_message)
+            .map(|(event, _)| event),
+    }
+}
+
+/*
+plays a recording back with the gaps between frames it was recorded with, divided by `speed`, or with
+no gaps at all without one. frames due at the same time are handed over as one batch, as they would
+have been live
+*/
+pub async fn replay(
+    frames: Vec<RecordedFrame>,
+    speed: Option<f64>,
+    events: impl Fn(Vec<Result<FirehoseEvent, String>>),
+) {
+    let start = Instant::now();
+    let first = frames
+        .first()
+        .map(|frame| frame.received)
+        .unwrap_or_default();
+    let mut batch = vec![];
+    for frame in &frames {
+        if let Some(speed) = speed {
+            let due =
+                start + Duration::from_millis(frame.received.saturating_sub(first)).div_f64(speed);
+            if due > Instant::now() {
+                events(std::mem::take(&mut batch));
+                sleep_until(due).await;
+            }
+        }
+        batch.push(decode_recorded(frame));
+        if batch.len() >= 256 {
+            events(std::mem::take(&mut batch));
+            tokio::task::yield_now().await;
+        }
+    }
+    events(batch);
+}
+
+/*
+runs until the server closes the connection or the task is aborted. events are handed over in
+batches of whatever had already arrived, since at network scale there are far more of them than there
+are frames to draw them in
+*/
+pub async fn subscribe(
+    url: String,
+    recorder: Recorder,
+    events: impl Fn(Vec<Result<FirehoseEvent, String>>),
+) -> Result<(), String> {
+    let (socket, _) = connect_async(endpoint_for(&url))
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut frames = socket.ready_chunks(256);
+    while let Some(messages) = frames.next().await {
+        let mut batch = vec![];
+        for message in messages {
+            match message.map_err(|err| err.to_string())? {
+                Message::Binary(frame) => {
+                    if let Some(recording) = recorder.lock().unwrap().as_mut() {
+                        recording.write(FrameKind::Binary, &frame);
+                    }
+                    batch.push(decode_frame(&frame));
+                }
+                Message::Close(frame) => {
+                    events(batch);
+                    return match frame {
+                        Some(frame) if !frame.reason.is_empty() => Err(frame.reason.to_string()),
+                        _ => Ok(()),
+                    };
+                }
+                _ => (),
+            }
+        }
+        events(batch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Header<'a> {
+        op: i64,
+        t: &'a str,
+    }
+
+    #[derive(Serialize)]
+    struct Info<'a> {
+        name: &'a str,
+        message: &'a str,
+    }
+
+    fn info_frame() -> Vec<u8> {
+        let mut frame = serde_ipld_dagcbor::to_vec(&Header { op: 1, t: "#info" }).unwrap();
+        frame.extend(
+            serde_ipld_dagcbor::to_vec(&Info {
+                name: "OutdatedCursor",
+                message: "the cursor is too old",
+            })
+            .unwrap(),
+        );
+        frame
+    }
+
+    #[test]
+    fn frames_decode_into_events() {
+        match decode_frame(&info_frame()) {
+            Ok(FirehoseEvent::Info(info)) => assert_eq!(info.name, "OutdatedCursor"),
+            other => panic!("expected an #info event, got {:?}", other),
+        }
+
+        let mut error = serde_ipld_dagcbor::to_vec(&Header { op: -1, t: "" }).unwrap();
+        error.extend(
+            serde_ipld_dagcbor::to_vec(&serde_json::json!({ "error": "FutureCursor" })).unwrap(),
+        );
+        assert_eq!(decode_frame(&error).unwrap_err(), "FutureCursor");
+    }
+
+    #[test]
+    fn truncated_frames_are_refused() {
+        let frame = info_frame();
+        let header_len = cbor_item_len(&frame, 0).unwrap();
+        for len in [0, 1, header_len - 1, header_len + 3] {
+            assert!(decode_frame(&frame[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn lengths_past_the_frame_are_refused() {
+        // a text string claiming to be 4 GiB long
+        let frame = [0xa1, 0x61, b't', 0x7a, 0xff, 0xff, 0xff, 0xff, b'x'];
+        assert!(cbor_item_len(&frame, 0).unwrap() > frame.len());
+        assert!(decode_frame(&frame).is_err());
+        // an array claiming more items than there are bytes
+        let frame = [0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(cbor_item_len(&frame, 0), None);
+    }
+
+    #[test]
+    fn deep_nesting_is_refused_without_recursing_through_it() {
+        let mut frame = vec![0x81; MAX_CBOR_DEPTH];
+        frame.push(0x01);
+        assert_eq!(cbor_item_len(&frame, 0), Some(frame.len()));
+
+        let frame = vec![0x81; 1_000_000];
+        assert_eq!(cbor_item_len(&frame, 0), None);
+        assert!(decode_frame(&frame).is_err());
+    }
+
+    fn commit(repo: &str, paths: &[&str]) -> FirehoseEvent {
+        FirehoseEvent::Commit(CommitEvent {
+            seq: 1,
+            repo: repo.to_string(),
+            commit: crate::verify::cid_for(b"commit"),
+            rev: String::from("3l3qo2vutsw2b"),
+            since: None,
+            blocks: vec![],
+            ops: paths
+                .iter()
+                .map(|path| RepoOp {
+                    action: String::from("create"),
+                    path: path.to_string(),
+                    cid: None,
+                    prev: None,
+                })
+                .collect(),
+            too_big: false,
+            time: String::from("2024-01-01T00:00:00.000Z"),
+        })
+    }
+
+    fn account(did: &str) -> FirehoseEvent {
+        FirehoseEvent::Account(AccountEvent {
+            seq: 2,
+            did: did.to_string(),
+            time: String::from("2024-01-01T00:00:00.000Z"),
+            active: true,
+            status: None,
+        })
+    }
+
+    // the ops of a commit that got through
+    fn paths(event: Option<FirehoseEvent>) -> Vec<String> {
+        match event {
+            Some(FirehoseEvent::Commit(commit)) => {
+                commit.ops.into_iter().map(|op| op.path).collect()
+            }
+            other => panic!("expected a commit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn collection_patterns_match_whole_nsids_or_prefixes() {
+        assert!(collection_matches("app.bsky.feed.*", "app.bsky.feed.post"));
+        assert!(collection_matches("app.bsky.feed.*", "app.bsky.feed.like"));
+        assert!(!collection_matches(
+            "app.bsky.feed.*",
+            "app.bsky.graph.follow"
+        ));
+        assert!(collection_matches(
+            "app.bsky.feed.post",
+            "app.bsky.feed.post"
+        ));
+        assert!(!collection_matches(
+            "app.bsky.feed.post",
+            "app.bsky.feed.postgate"
+        ));
+    }
+
+    #[test]
+    fn collections_narrow_down_commits_only() {
+        let filter = FirehoseFilter {
+            collections: vec![String::from("app.bsky.feed.*")],
+            ..Default::default()
+        };
+        let event = commit(
+            "did:plc:alice",
+            &[
+                "app.bsky.feed.post/a",
+                "app.bsky.graph.follow/b",
+                "app.bsky.feed.like/c",
+            ],
+        );
+        assert_eq!(
+            paths(filter.apply(event)),
+            vec!["app.bsky.feed.post/a", "app.bsky.feed.like/c"]
+        );
+        assert!(filter
+            .apply(commit("did:plc:alice", &["app.bsky.graph.follow/b"]))
+            .is_none());
+        assert!(filter.apply(account("did:plc:alice")).is_some());
+        assert!(FirehoseFilter::default().is_empty());
+    }
+
+    #[test]
+    fn repositories_and_kinds_are_filtered_out() {
+        let filter = FirehoseFilter {
+            dids: vec![String::from("did:plc:alice")],
+            hidden_kinds: vec![String::from("#account")],
+            ..Default::default()
+        };
+        assert!(filter
+            .apply(commit("did:plc:alice", &["app.bsky.feed.post/a"]))
+            .is_some());
+        assert!(filter
+            .apply(commit("did:plc:bob", &["app.bsky.feed.post/a"]))
+            .is_none());
+        assert!(filter.apply(account("did:plc:alice")).is_none());
+    }
+
+    #[test]
+    fn handles_let_nothing_through_until_resolved() {
+        let mut filter = FirehoseFilter {
+            dids: vec![String::from("alice.test")],
+            ..Default::default()
+        };
+        assert!(filter
+            .apply(commit("did:plc:alice", &["app.bsky.feed.post/a"]))
+            .is_none());
+        assert!(filter.apply(account("did:plc:alice")).is_none());
+
+        filter.dids = vec![String::from("did:plc:alice")];
+        assert!(filter.apply(account("did:plc:alice")).is_some());
+    }
+
+    fn recorded(name: &str, frames: &[(FrameKind, &[u8])]) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "branches-recording-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let mut recording = Recording::create(path.clone()).unwrap();
+        for (kind, frame) in frames {
+            recording.write(*kind, frame);
+        }
+        assert_eq!(recording.finish().unwrap(), frames.len() as u64);
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        bytes
+    }
+
+    #[test]
+    fn recordings_read_back_as_written() {
+        let text = br#"{"did":"did:plc:alice","time_us":1,"kind":"identity"}"#;
+        let bytes = recorded(
+            "round-trip",
+            &[
+                (FrameKind::Binary, info_frame().as_slice()),
+                (FrameKind::Text, &text[..]),
+            ],
+        );
+        let frames = read_recording(&bytes).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].kind, FrameKind::Binary);
+        assert_eq!(frames[0].bytes, info_frame());
+        assert_eq!(frames[1].kind, FrameKind::Text);
+        assert_eq!(frames[1].bytes, text.to_vec());
+        assert!(frames[0].received > 0 && frames[0].received <= frames[1].received);
+        assert!(matches!(
+            decode_recorded(&frames[0]),
+            Ok(FirehoseEvent::Info(_))
+        ));
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert!(read_recording(b"").is_err());
+        assert!(read_recording(b"branches firehose recording 2\n").is_err());
+        assert!(read_recording(&info_frame()).is_err());
+
+        let mut bytes = recorded("unknown-kind", &[(FrameKind::Binary, &b"frame"[..])]);
+        bytes[RECORDING_HEADER.len() + 8] = 7;
+        assert!(read_recording(&bytes).is_err());
+    }
+
+    #[test]
+    fn a_recording_cut_short_replays_up_to_the_cut() {
+        let bytes = recorded(
+            "truncated",
+            &[
+                (FrameKind::Binary, &b"first"[..]),
+                (FrameKind::Binary, &b"second"[..]),
+            ],
+        );
+        for cut in [1, 6, 13 + 6 - 1] {
+            let frames = read_recording(&bytes[..bytes.len() - cut]).unwrap();
+            assert_eq!(frames.len(), 1, "{} bytes cut", cut);
+            assert_eq!(frames[0].bytes, b"first".to_vec());
+        }
+        assert!(read_recording(RECORDING_HEADER).unwrap().is_empty());
+    }
+}
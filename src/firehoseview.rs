@@ -0,0 +1,829 @@
+use crate::config::APP_ID;
+use crate::firehose::{
+    read_recording, replay, subscribe, CommitEvent, FirehoseEvent, FirehoseFilter, FirehoseSource,
+    Recorder, Recording, RepoOp,
+};
+use crate::jetstream;
+use crate::types::AtUri;
+use atrium_api::types::string::Handle;
+use futures::future::{abortable, AbortHandle};
+use relm4::adw::prelude::*;
+use relm4::gtk::prelude::*;
+use relm4::gtk::{gio, glib};
+use relm4::prelude::*;
+use relm4::{gtk, Component, ComponentParts, ComponentSender};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/*
+a view of a subscribeRepos stream, live, through Jetstream, or replayed from a recording. the newest events are at the
+top, and only the most recent ones are kept around (as many as the firehose-buffer setting says): at
+network scale the firehose carries hundreds of events a second
+*/
+const KINDS: [&str; 5] = ["#commit", "#identity", "#account", "#sync", "#info"];
+const SPEEDS: [(&str, Option<f64>); 5] = [
+    ("1×", Some(1.0)),
+    ("2×", Some(2.0)),
+    ("10×", Some(10.0)),
+    ("100×", Some(100.0)),
+    ("As fast as possible", None),
+];
+
+#[derive(Debug)]
+pub struct FirehoseView {
+    source: FirehoseSource,
+    // the live connection or the replay, whichever is running
+    connection: Option<AbortHandle>,
+    status: String,
+    received: u64,
+    // frames that couldn't be decoded, and why the last one couldn't
+    unreadable: u64,
+    last_error: Option<String>,
+    filter: FirehoseFilter,
+    // handles in the repositories filter that are still waiting on their DID
+    resolving: Vec<String>,
+    unresolved: Vec<String>,
+    // how many events were checked against the filters since they last changed, and how many passed
+    checked: u64,
+    matched: u64,
+    paused: bool,
+    // what arrived while paused, and how much didn't fit
+    waiting: VecDeque<FirehoseEvent>,
+    dropped: u64,
+    buffer: usize,
+    // newest first, with the row each is shown in
+    shown: VecDeque<(FirehoseEvent, gtk::Widget)>,
+    recorder: Recorder,
+    recording: Option<PathBuf>,
+    // how the last recording went
+    recorded: Option<String>,
+    // the newest Jetstream cursor seen, which the next connection resumes from
+    cursor: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum FirehoseInput {
+    Toggle,
+    // starts recording, or stops it if it already is
+    Record,
+    StartRecording(PathBuf),
+    Pause(bool),
+    // the repositories or collections entries were changed
+    ApplyFilters,
+    ShowKind(&'static str, bool),
+    Resolved(String, Result<String, String>),
+    // where Jetstream has got to
+    Cursor(i64),
+    Events(Vec<Result<FirehoseEvent, String>>),
+    Closed(Result<(), String>),
+}
+
+#[derive(Debug)]
+pub enum FirehoseOutput {
+    Open(AtUri),
+    ShowChanges(Box<CommitEvent>),
+    // the agent is asked, so that handles resolve the same way everywhere
+    Resolve(Handle),
+}
+
+fn format_time(time: &str) -> String {
+    glib::DateTime::from_iso8601(time, None)
+        .and_then(|time| time.to_local())
+        .and_then(|time| time.format("%X"))
+        .map(|time| time.to_string())
+        .unwrap_or_else(|_| time.to_string())
+}
+
+fn open_on_activate(row: &adw::ActionRow, uri: String, sender: &ComponentSender<FirehoseView>) {
+    if let Ok(uri) = uri.parse::<AtUri>() {
+        row.set_activatable(true);
+        row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+        let sender = sender.clone();
+        row.connect_activated(move |_| {
+            sender.output(FirehoseOutput::Open(uri.clone())).unwrap();
+        });
+    }
+}
+
+// a filter entry holds several values, separated by commas or spaces
+fn split_entry(text: &str) -> Vec<String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn buffer_size() -> usize {
+    gio::Settings::new(APP_ID).int("firehose-buffer").max(1) as usize
+}
+
+fn op_row(op: &RepoOp, repo: &str, sender: &ComponentSender<FirehoseView>) -> adw::ActionRow {
+    let row = adw::ActionRow::new();
+    row.set_title(&format!("{} {}", op.action, op.path));
+    row.set_subtitle(
+        &op.cid
+            .map(|cid| cid.to_string())
+            .unwrap_or_else(|| String::from("deleted")),
+    );
+    row.add_css_class("property");
+    if op.action != "delete" {
+        open_on_activate(&row, format!("at://{}/{}", repo, op.path), sender);
+    }
+    row
+}
+
+fn event_row(event: &FirehoseEvent, sender: &ComponentSender<FirehoseView>) -> gtk::Widget {
+    let kind = gtk::Label::new(Some(event.kind()));
+    kind.set_css_classes(&["caption-heading", "dim-label"]);
+    kind.set_width_chars(10);
+    kind.set_xalign(0.0);
+    let details = |details: String| {
+        let mut parts = vec![];
+        if let Some(seq) = event.seq() {
+            parts.push(format!("seq {}", seq));
+        }
+        parts.push(details);
+        if let Some(time) = event.time() {
+            parts.push(format_time(time));
+        }
+        glib::markup_escape_text(&parts.join(" · ")).to_string()
+    };
+
+    if let FirehoseEvent::Commit(commit) = event {
+        let row = adw::ExpanderRow::new();
+        row.add_prefix(&kind);
+        row.set_title(&commit.repo);
+        row.set_subtitle(&details(format!(
+            "rev {}, {} {}{}",
+            commit.rev,
+            commit.ops.len(),
+            if commit.ops.len() == 1 { "op" } else { "ops" },
+            if commit.too_big {
+                ", too big to include"
+            } else {
+                ""
+            }
+        )));
+        row.add_css_class("property");
+        /* a commit too big to include has no blocks to show the changes from */
+        if !commit.too_big {
+            let changes = gtk::Button::from_icon_name("document-properties-symbolic");
+            changes.set_tooltip_text(Some("Show Changes"));
+            changes.set_valign(gtk::Align::Center);
+            changes.add_css_class("flat");
+            let (commit, sender) = (commit.clone(), sender.clone());
+            changes.connect_clicked(move |_| {
+                sender
+                    .output(FirehoseOutput::ShowChanges(Box::new(commit.clone())))
+                    .unwrap();
+            });
+            row.add_suffix(&changes);
+        }
+        for op in &commit.ops {
+            row.add_row(&op_row(op, &commit.repo, sender));
+        }
+        return row.upcast();
+    }
+
+    /* Jetstream sends the record along with the op, which is worth having without another lookup */
+    if let FirehoseEvent::JetstreamCommit(commit) = event {
+        let row = adw::ExpanderRow::new();
+        row.add_prefix(&kind);
+        row.set_title(&commit.did);
+        row.set_subtitle(&details(format!(
+            "rev {}, {} {}",
+            commit.rev, commit.op.action, commit.op.path
+        )));
+        row.add_css_class("property");
+        row.add_row(&op_row(&commit.op, &commit.did, sender));
+        if let Some(record) = &commit.record {
+            let json = gtk::Label::new(Some(
+                &serde_json::to_string_pretty(record).unwrap_or_default(),
+            ));
+            json.set_xalign(0.0);
+            json.set_wrap(true);
+            json.set_selectable(true);
+            json.set_margin_all(10);
+            json.add_css_class("monospace");
+            row.add_row(&json);
+        }
+        return row.upcast();
+    }
+
+    let row = adw::ActionRow::new();
+    row.add_prefix(&kind);
+    row.add_css_class("property");
+    match event {
+        FirehoseEvent::Identity(identity) => {
+            row.set_title(&identity.did);
+            row.set_subtitle(&details(match &identity.handle {
+                Some(handle) => format!("handle {}", handle),
+                None => String::from("identity changed"),
+            }));
+            open_on_activate(&row, format!("at://{}", identity.did), sender);
+        }
+        FirehoseEvent::Account(account) => {
+            row.set_title(&account.did);
+            row.set_subtitle(&details(match (account.active, &account.status) {
+                (true, _) => String::from("active"),
+                (false, Some(status)) => format!("inactive, {}", status),
+                (false, None) => String::from("inactive"),
+            }));
+        }
+        FirehoseEvent::Sync(sync) => {
+            row.set_title(&sync.did);
+            row.set_subtitle(&details(format!("resynchronised at rev {}", sync.rev)));
+            open_on_activate(&row, format!("at://{}", sync.did), sender);
+        }
+        FirehoseEvent::Info(info) => {
+            row.set_title(&info.name);
+            row.set_subtitle(&glib::markup_escape_text(
+                info.message.as_deref().unwrap_or_default(),
+            ));
+        }
+        FirehoseEvent::Other(_) => row.set_title("A kind of event Branches doesn't know yet"),
+        FirehoseEvent::Commit(_) | FirehoseEvent::JetstreamCommit(_) => unreachable!(),
+    }
+    row.upcast()
+}
+
+#[relm4::component(pub)]
+impl Component for FirehoseView {
+    type Init = FirehoseSource;
+    type Input = FirehoseInput;
+    type Output = FirehoseOutput;
+    type CommandOutput = ();
+
+    view! {
+        #[root]
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_margin_all: 10,
+            set_spacing: 10,
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+                #[name(url)]
+                gtk::Entry {
+                    set_visible: !replaying,
+                    set_hexpand: true,
+                    set_text: &url,
+                    set_placeholder_text: Some(if jetstream {
+                        "Jetstream, e.g. wss://jetstream2.us-east.bsky.network"
+                    } else {
+                        "Relay or PDS, e.g. wss://bsky.network"
+                    }),
+                    set_input_purpose: gtk::InputPurpose::Url,
+                    #[watch]
+                    set_sensitive: model.connection.is_none(),
+                    connect_activate => FirehoseInput::Toggle,
+                },
+                #[name(cursor)]
+                gtk::Entry {
+                    set_visible: jetstream,
+                    set_width_chars: 16,
+                    set_placeholder_text: Some("Cursor"),
+                    set_tooltip_text: Some("Resume from this time, in microseconds since 1970. Filled in with where the last connection stopped"),
+                    set_input_purpose: gtk::InputPurpose::Digits,
+                    #[watch]
+                    set_sensitive: model.connection.is_none(),
+                    connect_activate => FirehoseInput::Toggle,
+                },
+                gtk::Label {
+                    set_visible: replaying,
+                    set_hexpand: true,
+                    set_xalign: 0.0,
+                    set_ellipsize: gtk::pango::EllipsizeMode::Middle,
+                    set_label: &recording_name,
+                },
+                #[name(speed)]
+                gtk::DropDown::from_strings(&SPEEDS.map(|(label, _)| label)) {
+                    set_visible: replaying,
+                    set_tooltip_text: Some("Replay speed"),
+                    #[watch]
+                    set_sensitive: model.connection.is_none(),
+                },
+                gtk::Button {
+                    set_visible: !replaying,
+                    #[watch]
+                    set_icon_name: if model.recording.is_some() { "media-playback-stop-symbolic" } else { "media-record-symbolic" },
+                    #[watch]
+                    set_tooltip_text: Some(if model.recording.is_some() { "Stop Recording" } else { "Record to a File…" }),
+                    connect_clicked => FirehoseInput::Record,
+                },
+                gtk::Button {
+                    #[watch]
+                    set_label: match (replaying, model.connection.is_some()) {
+                        (false, false) => "Connect",
+                        (false, true) => "Disconnect",
+                        (true, false) => "Replay",
+                        (true, true) => "Stop",
+                    },
+                    #[watch]
+                    set_css_classes: if model.connection.is_some() { &["destructive-action"] } else { &["suggested-action"] },
+                    connect_clicked => FirehoseInput::Toggle,
+                },
+            },
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+                #[name(repos)]
+                gtk::Entry {
+                    set_hexpand: true,
+                    set_placeholder_text: Some("Repositories, as DIDs or handles"),
+                    set_tooltip_text: Some("Press Enter to apply"),
+                    connect_activate => FirehoseInput::ApplyFilters,
+                },
+                #[name(collections)]
+                gtk::Entry {
+                    set_hexpand: true,
+                    set_placeholder_text: Some("Collections, e.g. app.bsky.feed.*"),
+                    set_tooltip_text: Some("Press Enter to apply"),
+                    connect_activate => FirehoseInput::ApplyFilters,
+                },
+            },
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+                #[name(kinds)]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_hexpand: true,
+                    add_css_class: "linked",
+                },
+                gtk::ToggleButton {
+                    set_icon_name: "media-playback-pause-symbolic",
+                    set_tooltip_text: Some("Pause, holding on to new events until resumed"),
+                    connect_toggled[sender] => move |button| {
+                        sender.input(FirehoseInput::Pause(button.is_active()));
+                    },
+                },
+            },
+            gtk::Label {
+                set_xalign: 0.0,
+                set_wrap: true,
+                add_css_class: "dim-label",
+                #[watch]
+                set_label: &model.status,
+            },
+            gtk::Label {
+                set_xalign: 0.0,
+                set_wrap: true,
+                add_css_class: "dim-label",
+                #[watch]
+                set_label: &model.summary(),
+                #[watch]
+                set_visible: !model.summary().is_empty(),
+            },
+            #[name(events)]
+            gtk::ListBox {
+                set_selection_mode: gtk::SelectionMode::None,
+                add_css_class: "boxed-list",
+            },
+        }
+    }
+
+    fn init(
+        source: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let (url, recording_name) = match &source {
+            FirehoseSource::Live(url) | FirehoseSource::Jetstream(url) => {
+                (url.clone(), String::new())
+            }
+            FirehoseSource::Replay(path) => (String::new(), path.display().to_string()),
+        };
+        let replaying = matches!(source, FirehoseSource::Replay(_));
+        let jetstream = matches!(source, FirehoseSource::Jetstream(_));
+        let model = Self {
+            source,
+            connection: None,
+            status: String::from("Not connected"),
+            received: 0,
+            unreadable: 0,
+            last_error: None,
+            filter: FirehoseFilter::default(),
+            resolving: vec![],
+            unresolved: vec![],
+            checked: 0,
+            matched: 0,
+            paused: false,
+            waiting: VecDeque::new(),
+            dropped: 0,
+            buffer: buffer_size(),
+            shown: VecDeque::new(),
+            recorder: Recorder::default(),
+            recording: None,
+            recorded: None,
+            cursor: None,
+        };
+        let widgets = view_output!();
+        for kind in KINDS {
+            let toggle = gtk::ToggleButton::with_label(kind);
+            toggle.set_active(true);
+            let sender = sender.clone();
+            toggle.connect_toggled(move |toggle| {
+                sender.input(FirehoseInput::ShowKind(kind, toggle.is_active()));
+            });
+            widgets.kinds.append(&toggle);
+        }
+        sender.input(FirehoseInput::Toggle);
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        match message {
+            FirehoseInput::Toggle => match self.connection.take() {
+                Some(connection) => {
+                    connection.abort();
+                    if let Some(cursor) = self.cursor {
+                        widgets.cursor.set_text(&cursor.to_string());
+                    }
+                    self.status = match self.source {
+                        FirehoseSource::Live(_) | FirehoseSource::Jetstream(_) => {
+                            format!("Disconnected after {} events", self.received)
+                        }
+                        FirehoseSource::Replay(_) => {
+                            format!("Stopped after {} events", self.received)
+                        }
+                    };
+                }
+                None => {
+                    self.received = 0;
+                    self.unreadable = 0;
+                    self.last_error = None;
+                    self.checked = 0;
+                    self.matched = 0;
+                    self.buffer = buffer_size();
+                    self.start(widgets, &sender);
+                }
+            },
+            FirehoseInput::Record => match self.recorder.lock().unwrap().take() {
+                Some(recording) => {
+                    let path = recording.path.clone();
+                    self.recording = None;
+                    self.recorded = Some(match recording.finish() {
+                        Ok(frames) => format!("recorded {} frames to {}", frames, path.display()),
+                        Err(err) => format!("the recording to {} failed: {}", path.display(), err),
+                    });
+                }
+                None => {
+                    let name = glib::DateTime::now_local()
+                        .and_then(|now| now.format("firehose-%Y-%m-%d-%H%M%S.firehose"))
+                        .map(|name| name.to_string())
+                        .unwrap_or_else(|_| String::from("firehose.firehose"));
+                    let dialog = gtk::FileDialog::builder()
+                        .title("Record Firehose")
+                        .initial_name(name.as_str())
+                        .modal(true)
+                        .build();
+                    let sender = sender.clone();
+                    dialog.save(
+                        root.root().and_downcast_ref::<gtk::Window>(),
+                        None::<&gio::Cancellable>,
+                        move |result| {
+                            if let Some(path) = result.ok().and_then(|file| file.path()) {
+                                sender.input(FirehoseInput::StartRecording(path));
+                            }
+                        },
+                    );
+                }
+            },
+            FirehoseInput::StartRecording(path) => match Recording::create(path.clone()) {
+                Ok(recording) => {
+                    *self.recorder.lock().unwrap() = Some(recording);
+                    self.recording = Some(path);
+                    self.recorded = None;
+                }
+                Err(err) => {
+                    self.recorded = Some(format!("couldn't record to {}: {}", path.display(), err))
+                }
+            },
+            FirehoseInput::Pause(paused) => {
+                self.paused = paused;
+                if paused {
+                    self.dropped = 0;
+                } else {
+                    for event in std::mem::take(&mut self.waiting) {
+                        self.show(widgets, event, &sender);
+                    }
+                    self.trim(widgets);
+                }
+            }
+            FirehoseInput::ApplyFilters => {
+                self.resolving.clear();
+                self.unresolved.clear();
+                let mut dids = vec![];
+                for repo in split_entry(&widgets.repos.text()) {
+                    let repo = repo.trim_start_matches('@').to_string();
+                    if repo.starts_with("did:") {
+                        dids.push(repo);
+                        continue;
+                    }
+                    match Handle::new(repo.clone()) {
+                        /*
+                        the handle stands in for its DID until it's resolved, so that nothing gets
+                        through in the meantime (or at all, if it can't be)
+                        */
+                        Ok(handle) => {
+                            self.resolving.push(repo.clone());
+                            sender.output(FirehoseOutput::Resolve(handle)).unwrap();
+                        }
+                        Err(err) => self.unresolved.push(format!("{} ({})", repo, err)),
+                    }
+                    dids.push(repo);
+                }
+                self.filter.dids = dids;
+                self.filter.collections = split_entry(&widgets.collections.text());
+                self.refilter(widgets, &sender);
+                self.restart(widgets, &sender);
+            }
+            FirehoseInput::ShowKind(kind, shown) => {
+                self.filter.hidden_kinds.retain(|hidden| hidden != kind);
+                if !shown {
+                    self.filter.hidden_kinds.push(kind.to_string());
+                }
+                self.refilter(widgets, &sender);
+            }
+            /* an answer for a handle that has since been taken out of the filter is too late */
+            FirehoseInput::Resolved(handle, did) => {
+                let Some(position) = self.resolving.iter().position(|other| *other == handle)
+                else {
+                    return;
+                };
+                self.resolving.remove(position);
+                match did {
+                    Ok(did) => {
+                        for repo in self.filter.dids.iter_mut() {
+                            if *repo == handle {
+                                *repo = did.clone();
+                            }
+                        }
+                        self.restart(widgets, &sender);
+                    }
+                    Err(err) => self.unresolved.push(format!("{} ({})", handle, err)),
+                }
+            }
+            FirehoseInput::Cursor(cursor) => self.cursor = Some(cursor),
+            FirehoseInput::Events(batch) => {
+                for event in batch {
+                    match event {
+                        Ok(event) => {
+                            self.received += 1;
+                            self.checked += 1;
+                            let Some(event) = self.filter.apply(event) else {
+                                continue;
+                            };
+                            self.matched += 1;
+                            if !self.paused {
+                                self.show(widgets, event, &sender);
+                            } else if self.waiting.len() < self.buffer {
+                                self.waiting.push_back(event);
+                            } else {
+                                self.dropped += 1;
+                            }
+                        }
+                        Err(err) => {
+                            self.unreadable += 1;
+                            self.last_error = Some(err);
+                        }
+                    }
+                }
+                self.trim(widgets);
+                if self.connection.is_some() {
+                    self.status = match &self.last_error {
+                        None => format!("{} events received", self.received),
+                        Some(err) => format!(
+                            "{} events received, {} couldn't be read (the last because {})",
+                            self.received, self.unreadable, err
+                        ),
+                    };
+                }
+            }
+            FirehoseInput::Closed(result) => {
+                self.connection = None;
+                if let Some(cursor) = self.cursor {
+                    widgets.cursor.set_text(&cursor.to_string());
+                }
+                self.status = match (&self.source, result) {
+                    (FirehoseSource::Live(_) | FirehoseSource::Jetstream(_), Ok(())) => format!(
+                        "The server closed the connection after {} events",
+                        self.received
+                    ),
+                    (FirehoseSource::Live(_) | FirehoseSource::Jetstream(_), Err(err)) => {
+                        format!("Disconnected: {}", err)
+                    }
+                    (FirehoseSource::Replay(_), Ok(())) => {
+                        format!("The replay finished after {} events", self.received)
+                    }
+                    (FirehoseSource::Replay(_), Err(err)) => format!("Couldn't replay: {}", err),
+                };
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+
+    fn shutdown(&mut self, _widgets: &mut Self::Widgets, _output: relm4::Sender<Self::Output>) {
+        if let Some(connection) = self.connection.take() {
+            connection.abort();
+        }
+        if let Some(recording) = self.recorder.lock().unwrap().take() {
+            let _ = recording.finish();
+        }
+    }
+}
+
+impl FirehoseView {
+    // connects, or starts the replay
+    fn start(&mut self, widgets: &FirehoseViewWidgets, sender: &ComponentSender<Self>) {
+        let events = sender.input_sender().clone();
+        let closed = sender.input_sender().clone();
+        let connection = match &self.source {
+            FirehoseSource::Live(_) => {
+                let url = widgets.url.text().to_string();
+                let _ = gio::Settings::new(APP_ID).set_string("firehose-url", &url);
+                self.status = format!("Connecting to {}…", url);
+                let recorder = self.recorder.clone();
+                let (task, handle) = abortable(async move {
+                    let result = subscribe(url, recorder, move |batch| {
+                        events.emit(FirehoseInput::Events(batch));
+                    })
+                    .await;
+                    closed.emit(FirehoseInput::Closed(result));
+                });
+                relm4::spawn(task);
+                handle
+            }
+            FirehoseSource::Jetstream(_) => {
+                let url = widgets.url.text().to_string();
+                let _ = gio::Settings::new(APP_ID).set_string("jetstream-url", &url);
+                let cursor = match widgets.cursor.text().trim() {
+                    "" => None,
+                    cursor => match cursor.parse() {
+                        Ok(cursor) => Some(cursor),
+                        Err(_) => {
+                            self.status = format!(
+                                "{} isn't a cursor, which is a time in microseconds",
+                                cursor
+                            );
+                            return;
+                        }
+                    },
+                };
+                /*
+                handles still being resolved are left out, and the connection is restarted with
+                their DIDs once they are
+                */
+                let dids: Vec<String> = self
+                    .filter
+                    .dids
+                    .iter()
+                    .filter(|did| did.starts_with("did:"))
+                    .cloned()
+                    .collect();
+                let endpoint =
+                    match jetstream::endpoint_for(&url, &dids, &self.filter.collections, cursor) {
+                        Ok(endpoint) => endpoint,
+                        Err(err) => {
+                            self.status = format!("Couldn't connect to {}: {}", url, err);
+                            return;
+                        }
+                    };
+                self.status = format!("Connecting to {}…", url);
+                let recorder = self.recorder.clone();
+                let (task, handle) = abortable(async move {
+                    let result = jetstream::subscribe(endpoint, recorder, move |batch, cursor| {
+                        if let Some(cursor) = cursor {
+                            events.emit(FirehoseInput::Cursor(cursor));
+                        }
+                        events.emit(FirehoseInput::Events(batch));
+                    })
+                    .await;
+                    closed.emit(FirehoseInput::Closed(result));
+                });
+                relm4::spawn(task);
+                handle
+            }
+            FirehoseSource::Replay(path) => {
+                let path = path.clone();
+                let (_, speed) = SPEEDS[widgets.speed.selected() as usize];
+                self.status = String::from("Replaying…");
+                let (task, handle) = abortable(async move {
+                    let frames = relm4::spawn_blocking(move || std::fs::read(path))
+                        .await
+                        .map_err(|err| err.to_string())
+                        .and_then(|bytes| bytes.map_err(|err| err.to_string()))
+                        .and_then(|bytes| read_recording(&bytes));
+                    let result = match frames {
+                        Ok(frames) => {
+                            replay(frames, speed, move |batch| {
+                                events.emit(FirehoseInput::Events(batch));
+                            })
+                            .await;
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    };
+                    closed.emit(FirehoseInput::Closed(result));
+                });
+                relm4::spawn(task);
+                handle
+            }
+        };
+        self.connection = Some(connection);
+    }
+
+    // what Jetstream sends is filtered by the server, so it has to be asked again when the filters change
+    fn restart(&mut self, widgets: &FirehoseViewWidgets, sender: &ComponentSender<Self>) {
+        if !matches!(self.source, FirehoseSource::Jetstream(_)) {
+            return;
+        }
+        if let Some(connection) = self.connection.take() {
+            connection.abort();
+            if let Some(cursor) = self.cursor {
+                widgets.cursor.set_text(&cursor.to_string());
+            }
+            self.start(widgets, sender);
+        }
+    }
+
+    fn show(
+        &mut self,
+        widgets: &FirehoseViewWidgets,
+        event: FirehoseEvent,
+        sender: &ComponentSender<Self>,
+    ) {
+        let row = event_row(&event, sender);
+        widgets.events.prepend(&row);
+        self.shown.push_front((event, row));
+    }
+
+    fn trim(&mut self, widgets: &FirehoseViewWidgets) {
+        while self.shown.len() > self.buffer {
+            if let Some((_, row)) = self.shown.pop_back() {
+                widgets.events.remove(&row);
+            }
+        }
+    }
+
+    /*
+    what's already been let through is run past the new filters. anything they now leave out is gone for
+    good, so loosening a filter only affects what arrives from then on
+    */
+    fn refilter(&mut self, widgets: &FirehoseViewWidgets, sender: &ComponentSender<Self>) {
+        self.checked = 0;
+        self.matched = 0;
+        for (event, row) in std::mem::take(&mut self.shown) {
+            widgets.events.remove(&row);
+            if let Some(event) = self.filter.apply(event) {
+                let row = event_row(&event, sender);
+                widgets.events.append(&row);
+                self.shown.push_back((event, row));
+            }
+        }
+        self.waiting = std::mem::take(&mut self.waiting)
+            .into_iter()
+            .filter_map(|event| self.filter.apply(event))
+            .collect();
+    }
+
+    fn summary(&self) -> String {
+        let mut parts = vec![];
+        if !self.filter.is_empty() {
+            parts.push(format!(
+                "{} of {} events matched the filters",
+                self.matched, self.checked
+            ));
+        }
+        if !self.resolving.is_empty() {
+            parts.push(format!("resolving {} handles", self.resolving.len()));
+        }
+        if !self.unresolved.is_empty() {
+            parts.push(format!("couldn't resolve {}", self.unresolved.join(", ")));
+        }
+        match (&self.recording, &self.recorded) {
+            (Some(path), _) => parts.push(format!("recording to {}", path.display())),
+            (None, Some(recorded)) => parts.push(recorded.clone()),
+            (None, None) => (),
+        }
+        if self.paused {
+            parts.push(format!("paused with {} waiting", self.waiting.len()));
+            if self.dropped > 0 {
+                parts.push(format!("{} dropped, the buffer is full", self.dropped));
+            }
+        }
+        let summary = parts.join(" · ");
+        let mut chars = summary.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => summary,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::car::{Car, Commit, MstEntry, MstNode};
+use crate::keys::PublicKey;
+use crate::verify::cid_for;
+use atrium_api::did_doc::DidDocument;
+use ipld_core::cid::Cid;
+use ipld_core::ipld::Ipld;
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+
+/*
+what the tests share: signing keys, DID documents naming them, and repositories built block by block
+under commits signed with them. these are built without going through the code under test, so a test
+can't pass by agreeing with itself
+*/
+
+pub fn key(seed: u8) -> SigningKey {
+    SigningKey::from_slice(&[seed; 32]).unwrap()
+}
+
+pub fn public(key: &SigningKey) -> PublicKey {
+    PublicKey::Secp256k1(VerifyingKey::from(key))
+}
+
+pub fn sign(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+    let signature: k256::ecdsa::Signature = k256::ecdsa::signature::Signer::sign(key, message);
+    signature.to_bytes().to_vec()
+}
+
+// a DID document with `key` as its #atproto key
+pub fn did_doc(did: &str, key: &SigningKey) -> DidDocument {
+    let point = VerifyingKey::from(key).to_encoded_point(true);
+    let multikey = multibase::encode(
+        multibase::Base::Base58Btc,
+        [[0xe7, 0x01].as_slice(), point.as_bytes()].concat(),
+    );
+    serde_json::from_value(json!({
+        "id": did,
+        "alsoKnownAs": ["at://alice.test"],
+        "verificationMethod": [{
+            "id": format!("{}#atproto", did),
+            "type": "Multikey",
+            "controller": did,
+            "publicKeyMultibase": multikey,
+        }],
+        "service": [{
+            "id": "#atproto_pds",
+            "type": "AtprotoPersonalDataServer",
+            "serviceEndpoint": "https://pds.test",
+        }],
+    }))
+    .unwrap()
+}
+
+// a commit of `data` by `did`, signed over the commit with its `sig` taken out
+pub fn signed_commit(key: &SigningKey, did: &str, data: Cid) -> Commit {
+    let mut commit = Commit {
+        did: did.to_string(),
+        version: 3,
+        data,
+        rev: String::from("3l3qo2vutsw2b"),
+        prev: None,
+        sig: vec![],
+    };
+    let Ipld::Map(mut unsigned) = ipld_core::serde::to_ipld(&commit).unwrap() else {
+        panic!("a commit is a map");
+    };
+    unsigned.remove("sig");
+    commit.sig = sign(key, &serde_ipld_dagcbor::to_vec(&unsigned).unwrap());
+    commit
+}
+
+pub fn entry(prefix_len: usize, key_suffix: &str, value: Cid, tree: Option<Cid>) -> MstEntry {
+    MstEntry {
+        prefix_len,
+        key_suffix: key_suffix.as_bytes().to_vec(),
+        value,
+        tree,
+    }
+}
+
+// the blocks of a repository, added as it's built from the records up
+#[derive(Default)]
+pub struct Repo {
+    blocks: HashMap<Cid, Vec<u8>>,
+}
+
+impl Repo {
+    pub fn put<T: Serialize>(&mut self, value: &T) -> Cid {
+        let bytes = serde_ipld_dagcbor::to_vec(value).unwrap();
+        let cid = cid_for(&bytes);
+        self.blocks.insert(cid, bytes);
+        cid
+    }
+
+    pub fn record(&mut self, text: &str) -> Cid {
+        self.put(&BTreeMap::from([("text", text)]))
+    }
+
+    pub fn node(&mut self, left: Option<Cid>, entries: Vec<MstEntry>) -> Cid {
+        self.put(&MstNode { left, entries })
+    }
+
+    // one node holding `keys`, which have to be in order, and the records they point at
+    pub fn leaf(&mut self, keys: &[&str]) -> (Cid, Vec<Cid>) {
+        let records: Vec<Cid> = keys.iter().map(|key| self.record(key)).collect();
+        let entries = keys
+            .iter()
+            .zip(&records)
+            .map(|(key, record)| entry(0, key, *record, None))
+            .collect();
+        (self.node(None, entries), records)
+    }
+
+    // the repository with `data` as its tree, committed as did:plc:alice
+    pub fn commit(mut self, key: &SigningKey, data: Cid) -> Car {
+        let root = self.put(&signed_commit(key, "did:plc:alice", data));
+        Car {
+            roots: vec![root],
+            blocks: self.blocks,
+        }
+    }
+}
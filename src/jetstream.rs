@@ -0,0 +1,205 @@
+use crate::firehose::{
+    websocket_base, AccountEvent, FirehoseEvent, FrameKind, IdentityEvent, Recorder, RepoOp,
+};
+use futures::StreamExt;
+use relm4::gtk::glib;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/*
+Jetstream is a relay's firehose turned into JSON, one operation per message, with the filtering done
+by the server: `wantedCollections` (NSIDs, or prefixes ending in `.*`) and `wantedDids` narrow down
+what it sends. every message carries `time_us`, the time Jetstream saw it in microseconds, which is also
+the cursor a connection can resume from
+*/
+
+#[derive(Deserialize)]
+struct JetstreamMessage {
+    did: String,
+    time_us: i64,
+    kind: String,
+    commit: Option<CommitData>,
+    identity: Option<IdentityEvent>,
+    account: Option<AccountEvent>,
+}
+
+#[derive(Deserialize)]
+struct CommitData {
+    rev: String,
+    operation: String,
+    collection: String,
+    rkey: String,
+    record: Option<serde_json::Value>,
+    cid: Option<String>,
+}
+
+// a commit as Jetstream sends it: a single op, with the record itself instead of the blocks
+#[derive(Debug, Clone)]
+pub struct JetstreamCommit {
+    pub did: String,
+    // time_us as an ISO 8601 date, like the times in the other events
+    pub time: String,
+    pub rev: String,
+    pub op: RepoOp,
+    pub record: Option<serde_json::Value>,
+}
+
+// the event, and the cursor it can be resumed after
+pub fn decode_message(text: &str) -> Result<(FirehoseEvent, i64), String> {
+    let message: JetstreamMessage = serde_json::from_str(text).map_err(|err| err.to_string())?;
+    let missing = || format!("a {} message without its {}", message.kind, message.kind);
+    let event = match message.kind.as_str() {
+        "commit" => {
+            let commit = message.commit.ok_or_else(missing)?;
+            let cid = match commit.cid {
+                Some(cid) => Some(cid.parse().map_err(|_| format!("{} isn't a CID", cid))?),
+                None => None,
+            };
+            FirehoseEvent::JetstreamCommit(JetstreamCommit {
+                time: glib::DateTime::from_unix_utc(message.time_us / 1_000_000)
+                    .and_then(|time| time.format_iso8601())
+                    .map(|time| time.to_string())
+                    .unwrap_or_default(),
+                did: message.did,
+                rev: commit.rev,
+                op: RepoOp {
+                    action: commit.operation,
+                    path: format!("{}/{}", commit.collection, commit.rkey),
+                    cid,
+                    prev: None,
+                },
+                record: commit.record,
+            })
+        }
+        "identity" => FirehoseEvent::Identity(message.identity.ok_or_else(missing)?),
+        "account" => FirehoseEvent::Account(message.account.ok_or_else(missing)?),
+        kind => FirehoseEvent::Other(format!("#{}", kind)),
+    };
+    Ok((event, message.time_us))
+}
+
+// the instance's /subscribe endpoint, asking for only what the filters let through
+pub fn endpoint_for(
+    url: &str,
+    dids: &[String],
+    collections: &[String],
+    cursor: Option<i64>,
+) -> Result<Url, String> {
+    let mut url = Url::parse(&websocket_base(url)).map_err(|err| err.to_string())?;
+    /* an instance can sit under a path of its own, behind a reverse proxy */
+    if !url.path().ends_with("/subscribe") {
+        let path = format!("{}/subscribe", url.path().trim_end_matches('/'));
+        url.set_path(&path);
+    }
+    // an empty query would still leave a `?` behind
+    if !collections.is_empty() || !dids.is_empty() || cursor.is_some() {
+        let mut query = url.query_pairs_mut();
+        for collection in collections {
+            query.append_pair("wantedCollections", collection);
+        }
+        for did in dids {
+            query.append_pair("wantedDids", did);
+        }
+        if let Some(cursor) = cursor {
+            query.append_pair("cursor", &cursor.to_string());
+        }
+    }
+    Ok(url)
+}
+
+/*
+the same as subscribing to a firehose, except that each batch comes with the cursor of the newest
+message in it, so the view can resume where it left off
+*/
+pub async fn subscribe(
+    url: Url,
+    recorder: Recorder,
+    events: impl Fn(Vec<Result<FirehoseEvent, String>>, Option<i64>),
+) -> Result<(), String> {
+    let (socket, _) = connect_async(url.as_str())
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut frames = socket.ready_chunks(256);
+    while let Some(messages) = frames.next().await {
+        let mut batch = vec![];
+        let mut cursor = None;
+        for message in messages {
+            match message.map_err(|err| err.to_string())? {
+                Message::Text(text) => {
+                    if let Some(recording) = recorder.lock().unwrap().as_mut() {
+                        recording.write(FrameKind::Text, text.as_bytes());
+                    }
+                    batch.push(decode_message(&text).map(|(event, time_us)| {
+                        cursor = Some(time_us);
+                        event
+                    }));
+                }
+                Message::Close(frame) => {
+                    events(batch, cursor);
+                    return match frame {
+                        Some(frame) if !frame.reason.is_empty() => Err(frame.reason.to_string()),
+                        _ => Ok(()),
+                    };
+                }
+                _ => (),
+            }
+        }
+        events(batch, cursor);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn endpoints_keep_the_instance_path() {
+        let endpoint = |url| endpoint_for(url, &[], &[], None).unwrap().to_string();
+        assert_eq!(
+            endpoint("jetstream2.us-east.bsky.network"),
+            "wss://jetstream2.us-east.bsky.network/subscribe"
+        );
+        assert_eq!(endpoint("https://host.test/"), "wss://host.test/subscribe");
+        assert_eq!(
+            endpoint("wss://host.test/jetstream"),
+            "wss://host.test/jetstream/subscribe"
+        );
+        assert_eq!(
+            endpoint("ws://localhost:6008/jetstream/subscribe"),
+            "ws://localhost:6008/jetstream/subscribe"
+        );
+    }
+
+    #[test]
+    fn filters_and_cursor_go_in_the_query() {
+        let url = endpoint_for(
+            "wss://host.test",
+            &strings(&["did:plc:alice", "did:plc:bob"]),
+            &strings(&["app.bsky.feed.*", "app.bsky.graph.follow"]),
+            Some(1725911162329308),
+        )
+        .unwrap();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(
+            query,
+            vec![
+                pair("wantedCollections", "app.bsky.feed.*"),
+                pair("wantedCollections", "app.bsky.graph.follow"),
+                pair("wantedDids", "did:plc:alice"),
+                pair("wantedDids", "did:plc:bob"),
+                pair("cursor", "1725911162329308"),
+            ]
+        );
+
+        let url = endpoint_for("wss://host.test", &[], &[], None).unwrap();
+        assert_eq!(url.query(), None);
+    }
+}
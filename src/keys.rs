@@ -0,0 +1,230 @@
+use atrium_api::did_doc::DidDocument;
+use k256::ecdsa::signature::Verifier;
+use sha2::{Digest, Sha256};
+
+/*
+the public keys atproto uses to sign repository commits. both curves are in use: secp256k1 (what
+most PDSes generate) and NIST P-256. keys are written as multibase strings, normally base58btc with
+a multicodec prefix saying which curve the compressed point that follows is on. DID documents from
+before the multicodec form was settled on give the curve in the verification method's `type` instead,
+and the key bare
+*/
+
+// multicodec prefixes, already varint encoded
+const SECP256K1_PUB: [u8; 2] = [0xe7, 0x01];
+const P256_PUB: [u8; 2] = [0x80, 0x24];
+
+#[derive(Debug, Clone)]
+pub enum PublicKey {
+    Secp256k1(k256::ecdsa::VerifyingKey),
+    P256(p256::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    pub fn from_multikey(multibase: &str) -> Result<Self, String> {
+        let (_, bytes) = multibase::decode(multibase)
+            .map_err(|err| format!("invalid multibase key: {}", err))?;
+        if let Some(point) = bytes.strip_prefix(&SECP256K1_PUB) {
+            Self::secp256k1(point)
+        } else if let Some(point) = bytes.strip_prefix(&P256_PUB) {
+            Self::p256(point)
+        } else {
+            Err(String::from("the key is on a curve Branches doesn't know"))
+        }
+    }
+
+    // a key in a DID document's verification method, in either the multikey or the legacy form
+    pub fn from_verification_method(r#type: &str, multibase: &str) -> Result<Self, String> {
+        match r#type {
+            "EcdsaSecp256k1VerificationKey2019" => Self::secp256k1(&Self::bare(multibase)?),
+            "EcdsaSecp256r1VerificationKey2019" => Self::p256(&Self::bare(multibase)?),
+            _ => Self::from_multikey(multibase),
+        }
+    }
+
+    pub fn from_did_key(did_key: &str) -> Result<Self, String> {
+        match did_key.strip_prefix("did:key:") {
+            Some(multibase) => Self::from_multikey(multibase),
+            None => Err(format!("{} isn't a did:key", did_key)),
+        }
+    }
+
+    // the `#atproto` key, which is the one commits are signed with
+    pub fn atproto_signing_key(did_doc: &DidDocument) -> Result<Self, String> {
+        let method = did_doc
+            .verification_method
+            .iter()
+            .flatten()
+            .find(|method| method.id.ends_with("#atproto"))
+            .ok_or_else(|| String::from("the DID document has no #atproto key"))?;
+        let multibase = method
+            .public_key_multibase
+            .as_ref()
+            .ok_or_else(|| String::from("the #atproto key has no publicKeyMultibase"))?;
+        Self::from_verification_method(&method.r#type, multibase)
+    }
+
+    fn bare(multibase: &str) -> Result<Vec<u8>, String> {
+        multibase::decode(multibase)
+            .map(|(_, bytes)| bytes)
+            .map_err(|err| format!("invalid multibase key: {}", err))
+    }
+
+    fn secp256k1(point: &[u8]) -> Result<Self, String> {
+        k256::ecdsa::VerifyingKey::from_sec1_bytes(point)
+            .map(PublicKey::Secp256k1)
+            .map_err(|_| String::from("not a valid secp256k1 public key"))
+    }
+
+    fn p256(point: &[u8]) -> Result<Self, String> {
+        p256::ecdsa::VerifyingKey::from_sec1_bytes(point)
+            .map(PublicKey::P256)
+            .map_err(|_| String::from("not a valid P-256 public key"))
+    }
+
+    // the 33 byte SEC1 compressed point
+    pub fn compressed(&self) -> Vec<u8> {
+        match self {
+            PublicKey::Secp256k1(key) => key.to_encoded_point(true).as_bytes().to_vec(),
+            PublicKey::P256(key) => key.to_encoded_point(true).as_bytes().to_vec(),
+        }
+    }
+
+    fn multicodec(&self) -> Vec<u8> {
+        let prefix = match self {
+            PublicKey::Secp256k1(_) => SECP256K1_PUB,
+            PublicKey::P256(_) => P256_PUB,
+        };
+        [prefix.as_slice(), &self.compressed()].concat()
+    }
+
+    // the multikey form, whatever form the key was written in
+    pub fn multikey(&self) -> String {
+        multibase::encode(multibase::Base::Base58Btc, self.multicodec())
+    }
+
+    pub fn did_key(&self) -> String {
+        format!("did:key:{}", self.multikey())
+    }
+
+    // short enough to compare by eye: the start of the sha256 of the key, in groups of four
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(self.multicodec());
+        digest[..8]
+            .chunks(2)
+            .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn curve(&self) -> &'static str {
+        match self {
+            PublicKey::Secp256k1(_) => "secp256k1",
+            PublicKey::P256(_) => "P-256",
+        }
+    }
+
+    /*
+    atproto signatures are the 64 byte r||s form over the sha256 of the message, and must use the
+    low-S form. k256 already refuses high-S signatures, p256 has to be told to
+    */
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), String> {
+        match self {
+            PublicKey::Secp256k1(key) => {
+                let signature = k256::ecdsa::Signature::from_slice(signature)
+                    .map_err(|_| String::from("the signature is malformed"))?;
+                key.verify(message, &signature)
+            }
+            PublicKey::P256(key) => {
+                let signature = p256::ecdsa::Signature::from_slice(signature)
+                    .map_err(|_| String::from("the signature is malformed"))?;
+                if signature.normalize_s().is_some() {
+                    return Err(String::from("the signature isn't in low-S form"));
+                }
+                key.verify(message, &signature)
+            }
+        }
+        .map_err(|_| String::from("the signature doesn't match the key"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, public};
+    use k256::ecdsa::signature::Signer;
+    use k256::elliptic_curve::PrimeField;
+
+    fn p256_key() -> p256::ecdsa::SigningKey {
+        p256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    #[test]
+    fn unknown_and_malformed_keys_are_refused() {
+        let ed25519: Vec<u8> = [[0xed, 0x01].as_slice(), &[1; 32]].concat();
+        assert!(
+            PublicKey::from_multikey(&multibase::encode(multibase::Base::Base58Btc, ed25519))
+                .is_err()
+        );
+        let short: Vec<u8> = [SECP256K1_PUB.as_slice(), &[2; 10]].concat();
+        assert!(
+            PublicKey::from_multikey(&multibase::encode(multibase::Base::Base58Btc, short))
+                .is_err()
+        );
+        assert!(PublicKey::from_did_key("did:web:example.com").is_err());
+    }
+
+    #[test]
+    fn secp256k1_signatures_must_be_low_s() {
+        let signer = fixtures::key(7);
+        let key = public(&signer);
+        let signature: k256::ecdsa::Signature = signer.sign(b"commit");
+        assert!(key.verify(b"commit", &signature.to_bytes()).is_ok());
+        assert!(key.verify(b"other commit", &signature.to_bytes()).is_err());
+
+        let (r, s) = signature.split_scalars();
+        let high = k256::ecdsa::Signature::from_scalars(r.to_repr(), (-*s).to_repr()).unwrap();
+        assert!(key.verify(b"commit", &high.to_bytes()).is_err());
+        assert!(key.verify(b"commit", &[0; 12]).is_err());
+    }
+
+    #[test]
+    fn p256_signatures_must_be_low_s() {
+        let signer = p256_key();
+        let key = PublicKey::P256(p256::ecdsa::VerifyingKey::from(&signer));
+        let signature: p256::ecdsa::Signature = signer.sign(b"commit");
+        let low = signature.normalize_s().unwrap_or(signature);
+        assert!(key.verify(b"commit", &low.to_bytes()).is_ok());
+
+        let (r, s) = low.split_scalars();
+        let high = p256::ecdsa::Signature::from_scalars(r.to_repr(), (-*s).to_repr()).unwrap();
+        assert!(key.verify(b"commit", &high.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn multikeys_round_trip_on_both_curves() {
+        let keys = [
+            public(&fixtures::key(7)),
+            PublicKey::P256(p256::ecdsa::VerifyingKey::from(&p256_key())),
+        ];
+        /* the multicodec prefixes are what give atproto keys their familiar starts */
+        for (key, start) in keys.iter().zip(["did:key:zQ3s", "did:key:zDna"]) {
+            assert!(key.did_key().starts_with(start), "{}", key.did_key());
+            let decoded = PublicKey::from_did_key(&key.did_key()).unwrap();
+            assert_eq!(decoded.compressed(), key.compressed());
+            assert_eq!(decoded.curve(), key.curve());
+            assert_eq!(decoded.compressed().len(), 33);
+        }
+    }
+
+    #[test]
+    fn legacy_keys_take_their_curve_from_the_type() {
+        let key = public(&fixtures::key(7));
+        let bare = multibase::encode(multibase::Base::Base58Btc, key.compressed());
+        let decoded =
+            PublicKey::from_verification_method("EcdsaSecp256k1VerificationKey2019", &bare)
+                .unwrap();
+        assert_eq!(decoded.multikey(), key.multikey());
+        assert!(PublicKey::from_verification_method("Multikey", &bare).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use crate::agent::{notifier, AgentError, AgentOutput, RequestId};
+use crate::api::get_did_doc_for;
+use crate::cache::{Cache, CacheKind};
+use crate::car::{Car, Commit};
+use crate::types::AtUri;
+use crate::verify::{check_block, check_commit, check_inclusion};
+use atrium_api::did_doc::DidDocument;
+use atrium_api::types::string::{Cid, Did, Handle, Nsid};
+use atrium_api::types::Unknown;
+use atrium_api::*;
+use ipld_core::cid::Cid as IpldCid;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/*
+a repository read from a CAR export on disk rather than from its PDS. it answers the same three
+questions as the network path (describe the repo, list a collection, get a record) and produces the
+same atrium types, so the views can't tell the difference
+*/
+#[derive(Debug)]
+pub struct LocalRepo {
+    pub path: PathBuf,
+    car: Car,
+    commit: Commit,
+    // "collection/rkey" -> record CID
+    records: BTreeMap<String, IpldCid>,
+}
+
+impl LocalRepo {
+    pub fn open(path: PathBuf) -> Result<Self, AgentError> {
+        let bytes = std::fs::read(&path)
+            .map_err(|err| AgentError::Io(format!("Couldn't read {}: {}", path.display(), err)))?;
+        let invalid = |err: crate::car::CarError| AgentError::InvalidCar(err.to_string());
+        let car = Car::from_bytes(&bytes).map_err(invalid)?;
+        let commit = car.commit().map_err(invalid)?;
+        let records = car.records().map_err(invalid)?.into_iter().collect();
+        Ok(Self {
+            path,
+            car,
+            commit,
+            records,
+        })
+    }
+
+    pub fn did(&self) -> &str {
+        &self.commit.did
+    }
+
+    fn uri(&self, key: &str) -> String {
+        format!("at://{}/{}", self.commit.did, key)
+    }
+
+    fn value(&self, cid: &IpldCid) -> Result<Unknown, AgentError> {
+        self.car
+            .decode(cid)
+            .map_err(|err| AgentError::InvalidCar(err.to_string()))
+    }
+
+    /*
+    the export doesn't carry the DID document or the handle, so those come from the cache if this
+    repository has been looked up online before
+    */
+    fn describe(
+        &self,
+        cache: &Cache,
+    ) -> Result<com::atproto::repo::describe_repo::OutputData, AgentError> {
+        let did = self
+            .did()
+            .parse::<Did>()
+            .map_err(|err| AgentError::InvalidCar(format!("invalid DID in commit: {}", err)))?;
+        let did_doc = cache.get::<DidDocument>(CacheKind::DidDoc, did.as_str());
+        let handle = did_doc
+            .as_ref()
+            .and_then(|did_doc| did_doc.value.also_known_as.as_ref())
+            .and_then(|aka| aka.iter().find_map(|aka| aka.strip_prefix("at://")))
+            .and_then(|handle| handle.parse::<Handle>().ok());
+        let mut collections: Vec<Nsid> = Vec::new();
+        for key in self.records.keys() {
+            if let Some(Ok(collection)) = key.split('/').next().map(|c| c.parse::<Nsid>()) {
+                if collections.last() != Some(&collection) {
+                    collections.push(collection);
+                }
+            }
+        }
+        Ok(com::atproto::repo::describe_repo::OutputData {
+            collections,
+            did,
+            did_doc: did_doc
+                .and_then(|did_doc| serde_json::to_value(did_doc.value).ok())
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or(Unknown::Null),
+            // only the cached DID document's claim, and resolving it back to the DID takes the network
+            handle_is_correct: false,
+            handle: handle.unwrap_or_else(|| {
+                Handle::new(String::from("handle.invalid"))
+                    .expect("handle.invalid is a valid handle")
+            }),
+        })
+    }
+
+    fn list(
+        &self,
+        collection: &Nsid,
+    ) -> Result<com::atproto::repo::list_records::OutputData, AgentError> {
+        let prefix = format!("{}/", collection.as_str());
+        let mut records = Vec::new();
+        for (key, cid) in self.records.range(prefix.clone()..) {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            records.push(
+                com::atproto::repo::list_records::RecordData {
+                    cid: Cid::new(*cid),
+                    uri: self.uri(key),
+                    value: self.value(cid)?,
+                }
+                .into(),
+            );
+        }
+        if records.is_empty() {
+            return Err(AgentError::RecordsNotFound(format!(
+                "{} has no {} records",
+                self.path.display(),
+                collection.as_str()
+            )));
+        }
+        Ok(com::atproto::repo::list_records::OutputData {
+            cursor: None,
+            records,
+        })
+    }
+
+    fn get(&self, key: &str) -> Result<com::atproto::repo::get_record::OutputData, AgentError> {
+        let cid = self.records.get(key).ok_or_else(|| {
+            AgentError::RecordNotFound(format!("{} has no record {}", self.path.display(), key))
+        })?;
+        Ok(com::atproto::repo::get_record::OutputData {
+            cid: Some(Cid::new(*cid)),
+            uri: self.uri(key),
+            value: self.value(cid)?,
+        })
+    }
+
+    // the record at `key`, if the version in this export is the one with `cid`
+    pub fn version(
+        &self,
+        key: &str,
+        cid: &IpldCid,
+    ) -> Option<com::atproto::repo::get_record::OutputData> {
+        match self.records.get(key) {
+            Some(found) if found == cid => self.get(key).ok(),
+            _ => None,
+        }
+    }
+
+    /*
+    an export can be checked against the DID document as it was cached, which is the point of
+    looking at it offline; only when there's no cached copy does this go out for one
+    */
+    async fn did_doc(
+        &self,
+        cache: &Cache,
+        id: RequestId,
+        output: &relm4::Sender<AgentOutput>,
+    ) -> Result<DidDocument, AgentError> {
+        match cache.get::<DidDocument>(CacheKind::DidDoc, self.did()) {
+            Some(cached) => Ok(cached.value),
+            None if cache.offline_only() => Err(AgentError::NotCached(format!(
+                "The DID document for {}",
+                self.did()
+            ))),
+            None => match self.did().parse::<Did>() {
+                Ok(did) => get_did_doc_for(&did, &notifier(id, output))
+                    .await
+                    .map_err(|err| AgentError::NoDidDoc(err.to_string())),
+                Err(err) => Err(AgentError::InvalidCar(format!(
+                    "invalid DID in commit: {}",
+                    err
+                ))),
+            },
+        }
+    }
+
+    pub async fn check_commit(
+        &self,
+        id: RequestId,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        match (self.did_doc(cache, id, output).await, self.car.root()) {
+            (Ok(did_doc), Ok(root)) => output.emit(AgentOutput::CommitChecked(
+                id,
+                check_commit(*root, &self.commit, &did_doc),
+            )),
+            (Err(err), _) => output.emit(AgentOutput::CommitCheckFailed(id, err)),
+            (_, Err(err)) => output.emit(AgentOutput::CommitCheckFailed(
+                id,
+                AgentError::InvalidCar(err.to_string()),
+            )),
+        }
+    }
+
+    // a whole export proves a record just as well as the partial one sync.getRecord returns
+    pub async fn prove_record(
+        &self,
+        id: RequestId,
+        uri: AtUri,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        let (Some(collection), Some(rkey)) = (&uri.collection, &uri.rkey) else {
+            output.emit(AgentOutput::ProofFailed(
+                id,
+                AgentError::RecordNotFound(uri.to_string()),
+            ));
+            return;
+        };
+        let key = format!("{}/{}", collection.as_str(), rkey.as_str());
+        match self.did_doc(cache, id, output).await {
+            Ok(did_doc) => output.emit(AgentOutput::RecordProved(
+                id,
+                check_inclusion(&self.car, &did_doc, &key),
+            )),
+            Err(err) => output.emit(AgentOutput::ProofFailed(id, err)),
+        }
+    }
+
+    pub fn mst(&self, id: RequestId, output: &relm4::Sender<AgentOutput>) {
+        match self.car.mst() {
+            Ok(mst) => {
+                output.emit(AgentOutput::Mst(id, mst));
+                output.emit(AgentOutput::Local(id, self.path.clone()));
+            }
+            Err(err) => output.emit(AgentOutput::Failed(
+                id,
+                AgentError::InvalidCar(err.to_string()),
+            )),
+        }
+    }
+
+    pub fn lookup(
+        &self,
+        id: RequestId,
+        uri: AtUri,
+        cache: &Cache,
+        output: &relm4::Sender<AgentOutput>,
+    ) {
+        let result = match (&uri.collection, &uri.rkey) {
+            (Some(collection), Some(rkey)) => self
+                .get(&format!("{}/{}", collection.as_str(), rkey.as_str()))
+                .map(|record| AgentOutput::Record(id, record)),
+            (Some(collection), None) => self
+                .list(collection)
+                .map(|records| AgentOutput::Records(id, records)),
+            (None, _) => self.describe(cache).map(|repo| AgentOutput::Repo(id, repo)),
+        };
+        match result {
+            Ok(result) => {
+                let is_record = matches!(result, AgentOutput::Record(..));
+                output.emit(result);
+                output.emit(AgentOutput::Local(id, self.path.clone()));
+                /* the raw block is at hand, so there's no need to trust a re-encoding of it */
+                if let (true, Some(collection), Some(rkey)) =
+                    (is_record, &uri.collection, &uri.rkey)
+                {
+                    let key = format!("{}/{}", collection.as_str(), rkey.as_str());
+                    if let Some(cid) = self.records.get(&key) {
+                        if let Ok(block) = self.car.block(cid) {
+                            output.emit(AgentOutput::CidChecked(id, check_block(cid, block)));
+                        }
+                    }
+                }
+            }
+            Err(err) => output.emit(AgentOutput::Failed(id, err)),
+        }
+    }
+}
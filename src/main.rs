@@ -3,10 +3,30 @@ mod config;
 mod agent;
 mod api;
 mod app;
+mod blob;
+mod blobview;
+mod cache;
+mod car;
+mod client;
+mod diff;
+mod diffview;
+mod firehose;
+mod firehoseview;
+#[cfg(test)]
+mod fixtures;
+mod jetstream;
+mod keys;
+mod local;
 mod modals;
+mod mstview;
+mod plc;
 mod recordview;
+mod serverview;
+mod snapshots;
+mod tab;
 mod templates;
 mod types;
+mod verify;
 
 use config::{APP_ID, GETTEXT_PACKAGE, LOCALEDIR, RESOURCES_FILE};
 use gettextrs::{gettext, LocaleCategory};
@@ -1 +1,2 @@
 pub mod about;
+pub mod preferences;
@@ -0,0 +1,123 @@
+use gtk::prelude::GtkWindowExt;
+use relm4::adw::prelude::*;
+use relm4::gtk::gio;
+use relm4::{adw, gtk, ComponentParts, ComponentSender, SimpleComponent};
+
+use crate::config::APP_ID;
+
+pub struct PreferencesDialog {
+    _settings: gio::Settings,
+}
+
+impl SimpleComponent for PreferencesDialog {
+    type Init = ();
+    type Widgets = adw::PreferencesWindow;
+    type Input = ();
+    type Output = ();
+    type Root = adw::PreferencesWindow;
+
+    fn init_root() -> Self::Root {
+        adw::PreferencesWindow::builder()
+            .search_enabled(false)
+            .hide_on_close(true)
+            .build()
+    }
+
+    fn init(
+        _: Self::Init,
+        root: Self::Root,
+        _sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let settings = gio::Settings::new(APP_ID);
+
+        let offline_only = adw::SwitchRow::builder()
+            .title("Offline only")
+            .subtitle("Only show cached data, and never touch the network")
+            .build();
+        settings
+            .bind("offline-only", &offline_only, "active")
+            .build();
+
+        let size_limit = adw::SpinRow::builder()
+            .title("Cache size limit")
+            .subtitle("In megabytes. The least recently used data is removed first")
+            .adjustment(&gtk::Adjustment::new(
+                settings.int("cache-size-limit") as f64,
+                16.0,
+                16384.0,
+                16.0,
+                256.0,
+                0.0,
+            ))
+            .build();
+        {
+            let settings = settings.clone();
+            size_limit.connect_value_notify(move |row| {
+                let _ = settings.set_int("cache-size-limit", row.value() as i32);
+            });
+        }
+
+        let cache = adw::PreferencesGroup::builder()
+            .title("Cache")
+            .description(
+                "Records, listings, DID documents and blobs are kept on disk so they can be viewed offline",
+            )
+            .build();
+        cache.add(&offline_only);
+        cache.add(&size_limit);
+
+        let firehose_url = adw::EntryRow::builder().title("Relay or PDS").build();
+        settings.bind("firehose-url", &firehose_url, "text").build();
+
+        let jetstream_url = adw::EntryRow::builder().title("Jetstream").build();
+        settings
+            .bind("jetstream-url", &jetstream_url, "text")
+            .build();
+
+        let firehose_buffer = adw::SpinRow::builder()
+            .title("Events kept")
+            .subtitle(
+                "The oldest are let go first, and while paused, new ones beyond this are dropped",
+            )
+            .adjustment(&gtk::Adjustment::new(
+                settings.int("firehose-buffer") as f64,
+                50.0,
+                10000.0,
+                50.0,
+                500.0,
+                0.0,
+            ))
+            .build();
+        {
+            let settings = settings.clone();
+            firehose_buffer.connect_value_notify(move |row| {
+                let _ = settings.set_int("firehose-buffer", row.value() as i32);
+            });
+        }
+
+        let firehose = adw::PreferencesGroup::builder()
+            .title("Firehose")
+            .description("Where the firehose tab connects to. A local PDS works too, e.g. http://localhost:2583")
+            .build();
+        firehose.add(&firehose_url);
+        firehose.add(&jetstream_url);
+        firehose.add(&firehose_buffer);
+
+        let page = adw::PreferencesPage::new();
+        page.add(&cache);
+        page.add(&firehose);
+        root.add(&page);
+
+        let model = Self {
+            _settings: settings,
+        };
+
+        let widgets = root.clone();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_view(&self, dialog: &mut Self::Widgets, _sender: ComponentSender<Self>) {
+        dialog.present();
+    }
+}
@@ -0,0 +1,145 @@
+use crate::car::{Mst, MstSubtree};
+use crate::types::AtUri;
+use relm4::adw::prelude::*;
+use relm4::gtk::glib;
+use relm4::gtk::prelude::*;
+use relm4::prelude::*;
+use relm4::{gtk, ComponentParts, ComponentSender, SimpleComponent};
+use std::cell::Cell;
+use std::rc::Rc;
+
+/*
+shows the merkle search tree underneath a repository, one node per expander. repositories can hold
+hundreds of thousands of records, so a node's children are only built the first time it is expanded
+*/
+#[derive(Debug)]
+pub struct MstView {
+    mst: Rc<Mst>,
+}
+
+#[derive(Debug)]
+pub enum MstOutput {
+    Open(AtUri),
+}
+
+fn subtree_row(
+    mst: &Rc<Mst>,
+    subtree: &MstSubtree,
+    sender: &ComponentSender<MstView>,
+) -> gtk::Widget {
+    let index = match subtree {
+        MstSubtree::Node(index) => *index,
+        MstSubtree::Missing(cid) => {
+            let row = adw::ActionRow::new();
+            row.set_title("Subtree not in this file");
+            row.set_subtitle(&cid.to_string());
+            row.add_css_class("property");
+            return row.upcast();
+        }
+    };
+    let node = &mst.nodes[index];
+    let row = adw::ExpanderRow::new();
+    row.set_title(&format!("Layer {} node", node.layer));
+    row.set_subtitle(&format!(
+        "{}\n{} entries, {} records beneath",
+        node.cid,
+        node.entries.len(),
+        mst.records_under(subtree)
+    ));
+
+    let populated = Cell::new(false);
+    let mst = mst.clone();
+    let sender = sender.clone();
+    row.connect_expanded_notify(move |row| {
+        if !row.is_expanded() || populated.replace(true) {
+            return;
+        }
+        let node = &mst.nodes[index];
+        if let Some(left) = &node.left {
+            row.add_row(&subtree_row(&mst, left, &sender));
+        }
+        let mut previous = "";
+        for entry in &node.entries {
+            /* the part of the key shared with the entry before it is dimmed, the rest is what `k` stores */
+            let shared = entry.prefix_len.min(entry.key.len());
+            let (prefix, suffix) = entry.key.split_at(shared);
+            let entry_row = adw::ActionRow::new();
+            entry_row.set_title(&format!(
+                "<span alpha=\"50%\">{}</span>{}",
+                glib::markup_escape_text(prefix),
+                glib::markup_escape_text(suffix)
+            ));
+            entry_row.set_subtitle(&format!(
+                "{} bytes shared with {}\nrecord {}",
+                entry.prefix_len,
+                if previous.is_empty() { "nothing" } else { previous },
+                entry.value
+            ));
+            entry_row.add_css_class("property");
+            if let Ok(uri) = format!("at://{}/{}", mst.did, entry.key).parse::<AtUri>() {
+                entry_row.set_activatable(true);
+                entry_row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+                let sender = sender.clone();
+                entry_row.connect_activated(move |_| {
+                    sender.output(MstOutput::Open(uri.clone())).unwrap();
+                });
+            }
+            row.add_row(&entry_row);
+            if let Some(right) = &entry.right {
+                row.add_row(&subtree_row(&mst, right, &sender));
+            }
+            previous = &entry.key;
+        }
+    });
+    row.upcast()
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for MstView {
+    type Init = Mst;
+    type Input = ();
+    type Output = MstOutput;
+
+    view! {
+        #[root]
+        gtk::ListBox {
+            set_hexpand: true,
+            set_margin_all: 10,
+            inline_css: "border-radius: 10px",
+            adw::ActionRow {
+                set_title: "Commit",
+                set_subtitle: &model.mst.commit.to_string(),
+                add_css_class: "property",
+            },
+            adw::ActionRow {
+                set_title: "Revision",
+                set_subtitle: &model.mst.rev,
+                add_css_class: "property",
+            },
+            adw::ActionRow {
+                set_title: "Size",
+                set_subtitle: &format!(
+                    "{} nodes, {} records",
+                    model.mst.nodes.len(),
+                    model.mst.record_count()
+                ),
+                add_css_class: "property",
+            },
+        }
+    }
+
+    fn init(
+        mst: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self { mst: Rc::new(mst) };
+        let widgets = view_output!();
+        let tree = subtree_row(&model.mst, &model.mst.root, &sender);
+        if let Some(tree) = tree.downcast_ref::<adw::ExpanderRow>() {
+            tree.set_expanded(true);
+        }
+        root.append(&tree);
+        ComponentParts { model, widgets }
+    }
+}
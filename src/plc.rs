@@ -0,0 +1,363 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/*
+did:plc identities as plc.directory keeps them. the DID document is generated from this data, but
+leaves out the rotation keys, which are what actually control the identity
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlcService {
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub endpoint: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlcData {
+    pub did: String,
+    // by id, without the leading '#'; values are did:keys
+    pub verification_methods: BTreeMap<String, String>,
+    // highest priority first
+    pub rotation_keys: Vec<String>,
+    pub also_known_as: Vec<String>,
+    pub services: BTreeMap<String, PlcService>,
+}
+
+/*
+one entry of plc.directory's audit log. every change to a did:plc is a signed operation pointing at
+the CID of the one before it in `prev`. when a higher priority rotation key signs an operation with
+the same `prev` as one from the last 72 hours, the newer one wins and the one it replaced is kept
+in the log marked as nullified
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlcLogEntry {
+    pub did: String,
+    pub operation: PlcOperation,
+    pub cid: String,
+    pub nullified: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PlcOperation {
+    #[serde(rename = "plc_operation", rename_all = "camelCase")]
+    Operation {
+        rotation_keys: Vec<String>,
+        verification_methods: BTreeMap<String, String>,
+        also_known_as: Vec<String>,
+        services: BTreeMap<String, PlcService>,
+        prev: Option<String>,
+        sig: String,
+    },
+    #[serde(rename = "plc_tombstone")]
+    Tombstone { prev: String, sig: String },
+    // the format genesis operations were written in before `plc_operation`
+    #[serde(rename = "create", rename_all = "camelCase")]
+    LegacyCreate {
+        signing_key: String,
+        recovery_key: String,
+        handle: String,
+        service: String,
+        prev: Option<String>,
+        sig: String,
+    },
+}
+
+impl PlcOperation {
+    pub fn prev(&self) -> Option<&str> {
+        match self {
+            PlcOperation::Operation { prev, .. } | PlcOperation::LegacyCreate { prev, .. } => {
+                prev.as_deref()
+            }
+            PlcOperation::Tombstone { prev, .. } => Some(prev),
+        }
+    }
+
+    // what the identity looks like after this operation, or None if it deactivates it
+    pub fn data(&self, did: &str) -> Option<PlcData> {
+        match self {
+            PlcOperation::Operation {
+                rotation_keys,
+                verification_methods,
+                also_known_as,
+                services,
+                ..
+            } => Some(PlcData {
+                did: did.to_string(),
+                verification_methods: verification_methods.clone(),
+                rotation_keys: rotation_keys.clone(),
+                also_known_as: also_known_as.clone(),
+                services: services.clone(),
+            }),
+            /* legacy creates are read the way plc.directory upgrades them */
+            PlcOperation::LegacyCreate {
+                signing_key,
+                recovery_key,
+                handle,
+                service,
+                ..
+            } => Some(PlcData {
+                did: did.to_string(),
+                verification_methods: BTreeMap::from([(
+                    String::from("atproto"),
+                    signing_key.clone(),
+                )]),
+                rotation_keys: vec![recovery_key.clone(), signing_key.clone()],
+                also_known_as: vec![format!("at://{}", handle)],
+                services: BTreeMap::from([(
+                    String::from("atproto_pds"),
+                    PlcService {
+                        r#type: String::from("AtprotoPersonalDataServer"),
+                        endpoint: service.clone(),
+                    },
+                )]),
+            }),
+            PlcOperation::Tombstone { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlcChange {
+    Created,
+    Deactivated,
+    AlsoKnownAs {
+        removed: Vec<String>,
+        added: Vec<String>,
+    },
+    Service {
+        id: String,
+        before: Option<PlcService>,
+        after: Option<PlcService>,
+    },
+    VerificationMethod {
+        id: String,
+        before: Option<String>,
+        after: Option<String>,
+    },
+    RotationKeys {
+        before: Vec<String>,
+        after: Vec<String>,
+    },
+}
+
+impl PlcChange {
+    // a few words for the summary line of an operation
+    pub fn label(&self) -> String {
+        match self {
+            PlcChange::Created => String::from("Created"),
+            PlcChange::Deactivated => String::from("Deactivated"),
+            PlcChange::AlsoKnownAs { .. } => String::from("Handle change"),
+            PlcChange::Service { id, .. } if id == "atproto_pds" => String::from("PDS migration"),
+            PlcChange::Service { id, .. } => format!("Service #{} changed", id),
+            PlcChange::VerificationMethod { id, .. } if id == "atproto" => {
+                String::from("Signing key change")
+            }
+            PlcChange::VerificationMethod { id, .. } => format!("Key #{} changed", id),
+            PlcChange::RotationKeys { .. } => String::from("Rotation key change"),
+        }
+    }
+}
+
+impl std::fmt::Display for PlcChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_none = |value: Option<&str>| value.unwrap_or("(none)").to_string();
+        match self {
+            PlcChange::Created => write!(f, "The identity was created"),
+            PlcChange::Deactivated => write!(f, "The identity was tombstoned"),
+            PlcChange::AlsoKnownAs { removed, added } => {
+                for aka in removed {
+                    writeln!(f, "− {}", aka)?;
+                }
+                for aka in added {
+                    writeln!(f, "+ {}", aka)?;
+                }
+                Ok(())
+            }
+            PlcChange::Service { before, after, .. } => write!(
+                f,
+                "− {}\n+ {}",
+                or_none(before.as_ref().map(|service| service.endpoint.as_str())),
+                or_none(after.as_ref().map(|service| service.endpoint.as_str()))
+            ),
+            PlcChange::VerificationMethod { before, after, .. } => write!(
+                f,
+                "− {}\n+ {}",
+                or_none(before.as_deref()),
+                or_none(after.as_deref())
+            ),
+            PlcChange::RotationKeys { before, after } => {
+                for key in before.iter().filter(|key| !after.contains(key)) {
+                    writeln!(f, "− {}", key)?;
+                }
+                for key in after.iter().filter(|key| !before.contains(key)) {
+                    writeln!(f, "+ {}", key)?;
+                }
+                if before.iter().all(|key| after.contains(key))
+                    && after.iter().all(|key| before.contains(key))
+                {
+                    write!(f, "reordered: {}", after.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// what changed between the state an operation started from and the state it left behind
+pub fn changes(before: Option<&PlcData>, after: Option<&PlcData>) -> Vec<PlcChange> {
+    let (before, after) = match (before, after) {
+        (None, None) => return vec![],
+        (None, Some(_)) => return vec![PlcChange::Created],
+        (Some(_), None) => return vec![PlcChange::Deactivated],
+        (Some(before), Some(after)) => (before, after),
+    };
+    let mut changes = vec![];
+    if before.also_known_as != after.also_known_as {
+        changes.push(PlcChange::AlsoKnownAs {
+            removed: difference(&before.also_known_as, &after.also_known_as),
+            added: difference(&after.also_known_as, &before.also_known_as),
+        });
+    }
+    let ids: BTreeSet<&String> = before
+        .services
+        .keys()
+        .chain(after.services.keys())
+        .collect();
+    for id in ids {
+        let (old, new) = (before.services.get(id), after.services.get(id));
+        if old != new {
+            changes.push(PlcChange::Service {
+                id: id.clone(),
+                before: old.cloned(),
+                after: new.cloned(),
+            });
+        }
+    }
+    let ids: BTreeSet<&String> = before
+        .verification_methods
+        .keys()
+        .chain(after.verification_methods.keys())
+        .collect();
+    for id in ids {
+        let (old, new) = (
+            before.verification_methods.get(id),
+            after.verification_methods.get(id),
+        );
+        if old != new {
+            changes.push(PlcChange::VerificationMethod {
+                id: id.clone(),
+                before: old.cloned(),
+                after: new.cloned(),
+            });
+        }
+    }
+    if before.rotation_keys != after.rotation_keys {
+        changes.push(PlcChange::RotationKeys {
+            before: before.rotation_keys.clone(),
+            after: after.rotation_keys.clone(),
+        });
+    }
+    changes
+}
+
+fn difference(from: &[String], without: &[String]) -> Vec<String> {
+    from.iter()
+        .filter(|value| !without.contains(value))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(handle: &str, pds: &str) -> PlcData {
+        PlcData {
+            did: String::from("did:plc:alice"),
+            verification_methods: BTreeMap::from([(
+                String::from("atproto"),
+                String::from("did:key:zQ3shsigning"),
+            )]),
+            rotation_keys: vec![String::from("did:key:zQ3shrotation")],
+            also_known_as: vec![format!("at://{}", handle)],
+            services: BTreeMap::from([(
+                String::from("atproto_pds"),
+                PlcService {
+                    r#type: String::from("AtprotoPersonalDataServer"),
+                    endpoint: pds.to_string(),
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn operations_read_as_plc_directory_serves_them() {
+        let operation: PlcOperation = serde_json::from_value(serde_json::json!({
+            "type": "plc_tombstone",
+            "prev": "bafyreib2rxk3rh6kzwq",
+            "sig": "c2ln",
+        }))
+        .unwrap();
+        assert_eq!(operation.prev(), Some("bafyreib2rxk3rh6kzwq"));
+        assert_eq!(operation.data("did:plc:alice"), None);
+
+        let genesis: PlcOperation = serde_json::from_value(serde_json::json!({
+            "type": "plc_operation",
+            "rotationKeys": ["did:key:zQ3shrotation"],
+            "verificationMethods": { "atproto": "did:key:zQ3shsigning" },
+            "alsoKnownAs": ["at://alice.test"],
+            "services": {
+                "atproto_pds": { "type": "AtprotoPersonalDataServer", "endpoint": "https://pds.test" },
+            },
+            "prev": null,
+            "sig": "c2ln",
+        }))
+        .unwrap();
+        assert_eq!(genesis.prev(), None);
+        assert_eq!(
+            genesis.data("did:plc:alice"),
+            Some(state("alice.test", "https://pds.test"))
+        );
+    }
+
+    #[test]
+    fn legacy_creates_are_upgraded() {
+        let create = PlcOperation::LegacyCreate {
+            signing_key: String::from("did:key:zQ3shsigning"),
+            recovery_key: String::from("did:key:zQ3shrecovery"),
+            handle: String::from("alice.test"),
+            service: String::from("https://pds.test"),
+            prev: None,
+            sig: String::new(),
+        };
+        let data = create.data("did:plc:alice").unwrap();
+        assert_eq!(
+            data.rotation_keys,
+            vec!["did:key:zQ3shrecovery", "did:key:zQ3shsigning"]
+        );
+        assert_eq!(data.also_known_as, vec!["at://alice.test"]);
+        assert_eq!(
+            data.services["atproto_pds"].endpoint,
+            String::from("https://pds.test")
+        );
+    }
+
+    #[test]
+    fn changes_name_what_an_operation_did() {
+        let before = state("alice.old", "https://old.test");
+        let after = state("alice.test", "https://pds.test");
+        let labels: Vec<String> = changes(Some(&before), Some(&after))
+            .iter()
+            .map(PlcChange::label)
+            .collect();
+        assert_eq!(labels, vec!["Handle change", "PDS migration"]);
+        assert_eq!(changes(None, Some(&after)), vec![PlcChange::Created]);
+        assert_eq!(changes(Some(&after), None), vec![PlcChange::Deactivated]);
+        assert!(changes(Some(&after), Some(&after)).is_empty());
+    }
+}
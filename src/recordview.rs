@@ -1,108 +1,453 @@
 use std::fmt::Error;
 
+use crate::agent::{AgentError, RepoDownload};
+use crate::api::server_url;
+use crate::blob::{blob_refs, Blob, BlobRef};
+use crate::blobview::BlobRow;
+use crate::diff::{ChangeKind, RecordChange, RepoDiff};
+use crate::keys::PublicKey;
+use crate::plc::{changes, PlcData, PlcLogEntry};
 use crate::templates::AppBskyFeedPost;
-use crate::types::AtUri;
+use crate::templates::KeyLabel;
+use crate::types::{strong_refs, AtUri, StrongRef};
+use crate::verify::{check_record, CidCheck, CommitCheck, InclusionCheck, PlcLogCheck};
 use atrium_api::did_doc::*;
-use atrium_api::types::string::{Cid, Did, Handle, Nsid};
+use atrium_api::types::string::{AtIdentifier, Cid, Did, Handle, Nsid};
 use atrium_api::types::Unknown as AtUnknown;
 use atrium_api::*;
+use ipld_core::cid::Cid as IpldCid;
 use relm4::adw::prelude::*;
-use relm4::factory::{AsyncFactoryComponent, FactoryView};
 use relm4::gtk::prelude::*;
-use relm4::{gtk, Component, ComponentParts, ComponentSender};
-use relm4::{prelude::*, AsyncFactorySender};
+use relm4::gtk::{gio, glib};
+use relm4::prelude::*;
+use relm4::{gtk, Component, ComponentParts, ComponentSender, SimpleComponent};
+use std::path::PathBuf;
 use types::TryFromUnknown;
 
-#[derive(Debug)]
-enum RecordViewError {
-    NullRecord,
-    Other,
-}
-
 #[derive(Debug)]
 pub struct GetRecordView {
     uri: AtUri,
     cid: Option<Cid>,
     value: AtUnknown,
+    cid_check: CidCheck,
+    proving: bool,
+    proof: Option<InclusionCheck>,
+    proof_status: String,
+    blobs: Vec<BlobRow>,
+}
+
+#[derive(Debug)]
+pub enum GetRecordInput {
+    // a check made against the raw block, which trumps re-encoding the record ourselves
+    CidChecked(CidCheck),
+    Prove,
+    Proved(InclusionCheck),
+    ProofFailed(AgentError),
+    Blob(Blob),
+    BlobFailed(IpldCid, AgentError),
 }
 
-#[relm4::factory(async, pub)]
-impl AsyncFactoryComponent for GetRecordView {
+#[derive(Debug)]
+pub enum GetRecordOutput {
+    Prove(AtUri),
+    FetchBlob(AtIdentifier, BlobRef),
+    Open(AtUri),
+    // the version of a record a strongRef points at
+    OpenVersion(AtUri, IpldCid),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for GetRecordView {
     type Init = com::atproto::repo::get_record::OutputData;
-    type Input = ();
-    type Output = ();
-    type CommandOutput = ();
-    type ParentWidget = adw::TabView;
+    type Input = GetRecordInput;
+    type Output = GetRecordOutput;
 
     view! {
         gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+                set_margin_all: 10,
+                #[template]
+                KeyLabel {
+                    set_label: "cid",
+                },
+                gtk::Label {
+                    set_label: &model
+                        .cid
+                        .as_ref()
+                        .map(|cid| cid.as_ref().to_string())
+                        .unwrap_or_else(|| String::from("none")),
+                    set_selectable: true,
+                    set_ellipsize: gtk::pango::EllipsizeMode::Middle,
+                    inline_css: "font-family: monospace, monospace",
+                },
+                #[name(cid_badge)]
+                gtk::Label {
+                    #[watch]
+                    set_label: model.cid_check.badge(),
+                    #[watch]
+                    set_css_classes: &["caption-heading", model.cid_check.css_class()],
+                    #[watch]
+                    set_tooltip_text: Some(&model.cid_check.to_string()),
+                },
+            },
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+                set_margin_all: 10,
+                #[template]
+                KeyLabel {
+                    set_label: "proof",
+                },
+                gtk::Label {
+                    set_hexpand: true,
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                    #[watch]
+                    set_label: &model.proof_status,
+                },
+                gtk::Label {
+                    #[watch]
+                    set_visible: model.proof.is_some(),
+                    #[watch]
+                    set_label: model.proof.as_ref().map(|proof| proof.badge()).unwrap_or_default(),
+                    #[watch]
+                    set_css_classes: &[
+                        "caption-heading",
+                        model.proof.as_ref().map(|proof| proof.css_class()).unwrap_or_default(),
+                    ],
+                },
+                gtk::Button {
+                    set_label: "Prove",
+                    set_valign: gtk::Align::Center,
+                    set_tooltip_text: Some("Check that the repository's signed commit includes this record"),
+                    #[watch]
+                    set_sensitive: !model.proving,
+                    connect_clicked => GetRecordInput::Prove,
+                },
+            },
+            #[name(blobs)]
+            gtk::ListBox {
+                set_margin_all: 10,
+                set_selection_mode: gtk::SelectionMode::None,
+                add_css_class: "boxed-list",
+                set_visible: false,
+            },
+            #[name(references)]
+            gtk::ListBox {
+                set_margin_all: 10,
+                set_selection_mode: gtk::SelectionMode::None,
+                add_css_class: "boxed-list",
+                set_visible: false,
+            },
             #[name(post)]
             #[template]
             AppBskyFeedPost,
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 10,
+                set_margin_all: 10,
+                #[template]
+                KeyLabel {
+                    set_label: "value",
+                },
+                #[name(json)]
+                gtk::Label {
+                    set_xalign: 0.0,
+                    set_selectable: true,
+                    set_wrap: true,
+                    set_wrap_mode: gtk::pango::WrapMode::WordChar,
+                    inline_css: "font-family: monospace, monospace",
+                },
+            },
         }
     }
 
-    async fn init_model(
+    fn init(
         value: Self::Init,
-        _index: &DynamicIndex,
-        _sender: AsyncFactorySender<Self>,
-    ) -> Self {
-        Self {
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let mut model = Self {
             uri: value
                 .uri
                 .parse::<AtUri>()
-                .expect("record uri is somehow invalid despite being retrieved from the PDS"),
+                .expect("the tab only shows records whose URI it could parse"),
+            cid_check: check_record(value.cid.as_ref(), &value.value),
+            proving: false,
+            proof: None,
+            proof_status: String::from("Not checked against the repository yet"),
+            blobs: vec![],
             cid: value.cid,
             value: value.value,
-        }
-    }
-    fn init_widgets(
-        &mut self,
-        _index: &DynamicIndex,
-        root: Self::Root,
-        _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
-        _sender: AsyncFactorySender<Self>,
-    ) -> Self::Widgets {
+        };
         let widgets = view_output!();
 
-        if let Ok(record) = match &self.value {
-            AtUnknown::Object(o) => Ok(o),
-            AtUnknown::Null => Err(RecordViewError::NullRecord),
-            AtUnknown::Other(_) => Err(RecordViewError::Other),
-        } {
-            if let Ok(text) = serde_json::to_string(
-                record
-                    .get("text")
-                    .expect("did not get a valid datamodel from record text"),
-            ) {
-                widgets.post.text.key.set_text("text");
-                widgets.post.text.value.set_text(text.as_str());
-            }
-
-            if let Ok(t) = serde_json::to_string(
-                record
-                    .get("$type")
-                    .expect("did not get a valid datamodel from record type"),
-            ) {
-                widgets.post.r#type.key.set_text("type");
-                widgets.post.r#type.value.set_text(t.as_str());
+        let refs = serde_json::to_value(&model.value)
+            .map(|value| blob_refs(&value))
+            .unwrap_or_default();
+        for reference in refs {
+            let repo = model.uri.authority.clone();
+            let sender = sender.clone();
+            let row = BlobRow::new(reference, move |reference| {
+                sender
+                    .output(GetRecordOutput::FetchBlob(repo.clone(), reference))
+                    .unwrap();
+            });
+            widgets.blobs.append(row.widget());
+            /* images are previewed straight away, anything else waits to be asked for */
+            if row.reference.is_image() {
+                row.widget().set_expanded(true);
             }
+            model.blobs.push(row);
+        }
+        widgets.blobs.set_visible(!model.blobs.is_empty());
 
-            // we're not doing the nested ones yet
+        let refs = serde_json::to_value(&model.value)
+            .map(|value| strong_refs(&value))
+            .unwrap_or_default();
+        for reference in &refs {
+            widgets
+                .references
+                .append(&reference_row(reference, &sender));
+        }
+        widgets.references.set_visible(!refs.is_empty());
 
-            if let Ok(created_at) = serde_json::to_string(
-                record
-                    .get("createdAt")
-                    .expect("did not get a valid datamodel from record createdAt"),
-            ) {
-                widgets.post.created_at.key.set_text("createdAt");
-                widgets.post.created_at.value.set_text(created_at.as_str());
+        /*
+        posts get their familiar fields picked out, but any record can turn up here, so only the fields
+        it actually has are shown and the whole value is always there as JSON underneath
+        */
+        let record = match &model.value {
+            AtUnknown::Object(record) => Some(record),
+            _ => None,
+        };
+        for (field, row) in [
+            ("text", &widgets.post.text),
+            ("$type", &widgets.post.r#type),
+            ("createdAt", &widgets.post.created_at),
+        ] {
+            match record
+                .and_then(|record| record.get(field))
+                .and_then(|value| serde_json::to_string(value).ok())
+            {
+                Some(value) => {
+                    row.key.set_text(field.trim_start_matches('$'));
+                    row.value.set_text(&value);
+                }
+                None => row.set_visible(false),
             }
-        } else {
-            panic!("we don't know what to do here: {:?}", self.value);
         }
+        // we're not doing the nested ones yet
+        widgets.post.embed.set_visible(false);
+        widgets.post.langs.set_visible(false);
         widgets
+            .json
+            .set_text(&serde_json::to_string_pretty(&model.value).unwrap_or_default());
+        ComponentParts { model, widgets }
     }
+
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>) {
+        match message {
+            GetRecordInput::CidChecked(check) => self.cid_check = check,
+            GetRecordInput::Prove => {
+                self.proving = true;
+                self.proof_status = String::from("Fetching an inclusion proof…");
+                sender.output(GetRecordOutput::Prove(self.uri.clone()));
+            }
+            GetRecordInput::Proved(proof) => {
+                self.proving = false;
+                self.proof_status = proof.to_string();
+                /* a proof for a different version of the record doesn't vouch for the one shown here */
+                if let (InclusionCheck::Proven { record, .. }, Some(cid)) = (&proof, &self.cid) {
+                    if record != cid.as_ref() {
+                        self.proof_status = format!(
+                            "{}, but this tab shows {}, an older or altered version",
+                            self.proof_status,
+                            cid.as_ref()
+                        );
+                    }
+                }
+                self.proof = Some(proof);
+            }
+            GetRecordInput::ProofFailed(error) => {
+                self.proving = false;
+                self.proof = None;
+                self.proof_status = format!("{}: {}", error.title(), error);
+            }
+            GetRecordInput::Blob(blob) => {
+                if let Some(row) = self.blob_row(&blob.reference.cid) {
+                    row.show(&blob);
+                }
+            }
+            GetRecordInput::BlobFailed(cid, error) => {
+                if let Some(row) = self.blob_row(&cid) {
+                    row.failed(&error);
+                }
+            }
+        }
+    }
+}
+
+impl GetRecordView {
+    fn blob_row(&self, cid: &IpldCid) -> Option<&BlobRow> {
+        self.blobs.iter().find(|row| &row.reference.cid == cid)
+    }
+}
+
+/*
+the record a strongRef points at, as it was when it was referenced. it may have been edited or deleted
+since, so the current version is a separate button
+*/
+fn reference_row(reference: &StrongRef, sender: &ComponentSender<GetRecordView>) -> adw::ActionRow {
+    let row = adw::ActionRow::new();
+    row.set_title(&glib::markup_escape_text(&reference.path));
+    row.set_subtitle(&glib::markup_escape_text(&format!(
+        "{}\n{}",
+        reference.uri, reference.cid
+    )));
+    row.add_css_class("property");
+    row.set_activatable(true);
+    row.set_tooltip_text(Some("Open the Version This Refers To"));
+    let current = gtk::Button::from_icon_name("view-refresh-symbolic");
+    current.set_tooltip_text(Some("Open the Record as It Is Now"));
+    current.set_valign(gtk::Align::Center);
+    current.add_css_class("flat");
+    let uri = reference.uri.clone();
+    let output = sender.output_sender().clone();
+    current.connect_clicked(move |_| {
+        output.emit(GetRecordOutput::Open(uri.clone()));
+    });
+    row.add_suffix(&current);
+    row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+    let (uri, cid) = (reference.uri.clone(), reference.cid);
+    let output = sender.output_sender().clone();
+    row.connect_activated(move |_| {
+        output.emit(GetRecordOutput::OpenVersion(uri.clone(), cid));
+    });
+    row
+}
+
+// what a key is, in the forms it turns up in elsewhere, so keys can be compared across documents
+fn add_key_rows(row: &adw::ExpanderRow, key: Result<PublicKey, String>) {
+    let key = match key {
+        Ok(key) => key,
+        Err(err) => {
+            let error = adw::ActionRow::new();
+            error.set_title("Couldn't decode key");
+            error.set_subtitle(&glib::markup_escape_text(&err));
+            row.add_row(&error);
+            return;
+        }
+    };
+    let compressed: String = key
+        .compressed()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    for (title, value) in [
+        ("curve", key.curve().to_string()),
+        ("compressed public key", compressed),
+        ("did:key", key.did_key()),
+        ("fingerprint", key.fingerprint()),
+    ] {
+        let property = adw::ActionRow::new();
+        property.set_title(title);
+        property.set_subtitle(&value);
+        property.set_subtitle_selectable(true);
+        property.add_css_class("property");
+        row.add_row(&property);
+    }
+}
+
+/*
+an operation is compared with the one its `prev` names rather than the one before it in the log, since
+a nullified operation in between never took effect
+*/
+fn history_row(log: &[PlcLogEntry], entry: &PlcLogEntry) -> adw::ExpanderRow {
+    let before = entry.operation.prev().and_then(|prev| {
+        log.iter()
+            .find(|other| other.cid == prev)
+            .and_then(|other| other.operation.data(&other.did))
+    });
+    let changes = changes(before.as_ref(), entry.operation.data(&entry.did).as_ref());
+    let summary = if changes.is_empty() {
+        String::from("No changes")
+    } else {
+        changes
+            .iter()
+            .map(|change| change.label())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let row = adw::ExpanderRow::new();
+    row.set_title(
+        &glib::DateTime::from_iso8601(&entry.created_at, None)
+            .and_then(|time| time.to_local())
+            .and_then(|time| time.format("%x %X"))
+            .map(|time| time.to_string())
+            .unwrap_or_else(|_| entry.created_at.clone()),
+    );
+    row.set_subtitle(&glib::markup_escape_text(&format!(
+        "{}\n{}",
+        summary, entry.cid
+    )));
+    if entry.nullified {
+        let badge = gtk::Label::new(Some("Nullified"));
+        badge.set_css_classes(&["caption-heading", "warning"]);
+        badge.set_tooltip_text(Some(
+            "A higher priority rotation key overrode this operation, so it never took effect",
+        ));
+        row.add_suffix(&badge);
+        row.add_css_class("dim-label");
+    }
+    for change in &changes {
+        let change_row = adw::ActionRow::new();
+        change_row.set_title(&change.label());
+        change_row.set_subtitle(&glib::markup_escape_text(change.to_string().trim_end()));
+        change_row.set_subtitle_selectable(true);
+        change_row.add_css_class("property");
+        row.add_row(&change_row);
+    }
+    row
+}
+
+// a record that arrived in a refresh, which opens the record unless it was deleted
+fn changelog_row(
+    did: &Did,
+    change: &RecordChange,
+    sender: &ComponentSender<DescribeRepoView>,
+) -> adw::ActionRow {
+    let row = adw::ActionRow::new();
+    row.set_title(&glib::markup_escape_text(&change.key));
+    let fields = change.changed_fields();
+    if !fields.is_empty() {
+        row.set_subtitle(&glib::markup_escape_text(&format!(
+            "changed {}",
+            fields.join(", ")
+        )));
+    }
+    row.add_css_class("property");
+    let badge = gtk::Label::new(Some(change.kind.label()));
+    badge.set_css_classes(&["caption-heading", change.kind.css_class()]);
+    badge.set_width_chars(8);
+    badge.set_xalign(0.0);
+    row.add_prefix(&badge);
+    if change.kind != ChangeKind::Deleted {
+        if let Ok(uri) = format!("at://{}/{}", did.as_str(), change.key).parse::<AtUri>() {
+            row.set_activatable(true);
+            row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+            let sender = sender.clone();
+            row.connect_activated(move |_| {
+                sender
+                    .output(DescribeRepoOutput::Open(uri.clone()))
+                    .unwrap();
+            });
+        }
+    }
+    row
 }
 
 #[derive(Debug)]
@@ -112,14 +457,64 @@ pub struct DescribeRepoView {
     did_doc: AtUnknown,
     handle: Handle,
     handle_is_correct: bool,
+    downloading: bool,
+    download_status: String,
+    // where the repository was last saved, which Refresh brings up to date
+    saved: Option<PathBuf>,
+    changelog: Vec<adw::ActionRow>,
+    checking_commit: bool,
+    commit_check: Option<CommitCheck>,
+    commit_status: String,
+    plc_check: Option<PlcLogCheck>,
+    listing_blobs: bool,
+    more_blobs: bool,
+    blobs_cursor: Option<String>,
+    blobs_status: String,
+    blobs: Vec<BlobRow>,
 }
-#[relm4::factory(async, pub)]
-impl AsyncFactoryComponent for DescribeRepoView {
+
+#[derive(Debug)]
+pub enum DescribeRepoInput {
+    PlcData(PlcData),
+    PlcLog(Vec<PlcLogEntry>),
+    PlcLogChecked(PlcLogCheck),
+    ExploreMst,
+    CheckCommit,
+    CommitChecked(CommitCheck),
+    CommitCheckFailed(AgentError),
+    ChooseDownload,
+    Download(PathBuf),
+    Downloaded(RepoDownload),
+    ChooseRefresh,
+    Refresh(PathBuf),
+    Refreshed(RepoDownload, RepoDiff),
+    DownloadFailed(AgentError),
+    ListBlobs,
+    Blobs(Vec<IpldCid>, Option<String>),
+    BlobsFailed(AgentError),
+    Blob(Blob),
+    BlobFailed(IpldCid, AgentError),
+}
+
+#[derive(Debug)]
+pub enum DescribeRepoOutput {
+    Download(Did, PathBuf),
+    Refresh(Did, PathBuf),
+    Open(AtUri),
+    // a PDS, by its base URL
+    OpenServer(String),
+    ExploreMst(Did),
+    CheckCommit(Did),
+    ListBlobs(Did, Option<String>),
+    FetchBlob(AtIdentifier, BlobRef),
+}
+
+#[relm4::component(pub)]
+impl Component for DescribeRepoView {
     type Init = com::atproto::repo::describe_repo::OutputData;
-    type Input = ();
-    type Output = ();
+    type Input = DescribeRepoInput;
+    type Output = DescribeRepoOutput;
     type CommandOutput = ();
-    type ParentWidget = adw::TabView;
     view! {
         #[root]
         gtk::ListBox {
@@ -132,15 +527,99 @@ impl AsyncFactoryComponent for DescribeRepoView {
                 set_expanded: false,
                 add_row = &adw::ActionRow {
                     set_title: "DID",
-                    set_subtitle: &format!("{}", &self.did.to_string()),
+                    set_subtitle: &format!("{}", &model.did.to_string()),
                     add_css_class: "property"
                 },
                 add_row = &adw::ActionRow {
                     set_title: "Handle",
-                    set_subtitle: &format!("{}", &self.handle.to_string()),
-                    add_css_class: "property"
+                    set_subtitle: &format!("{}", &model.handle.to_string()),
+                    add_css_class: "property",
+                    add_suffix = &gtk::Label {
+                        set_valign: gtk::Align::Center,
+                        set_visible: !model.handle_is_correct,
+                        set_label: "Unverified",
+                        set_tooltip_text: Some("The handle hasn't been confirmed to resolve back to this DID"),
+                        set_css_classes: &["caption-heading", "warning"],
+                    },
                 }
             },
+            adw::ActionRow {
+                set_title: "Commit Signature",
+                #[watch]
+                set_subtitle: &glib::markup_escape_text(&model.commit_status),
+                add_suffix = &gtk::Label {
+                    set_valign: gtk::Align::Center,
+                    #[watch]
+                    set_visible: model.commit_check.is_some(),
+                    #[watch]
+                    set_label: model.commit_check.as_ref().map(|check| check.badge()).unwrap_or_default(),
+                    #[watch]
+                    set_css_classes: &[
+                        "caption-heading",
+                        model.commit_check.as_ref().map(|check| check.css_class()).unwrap_or_default(),
+                    ],
+                },
+                add_suffix = &gtk::Button {
+                    set_label: "Verify",
+                    set_valign: gtk::Align::Center,
+                    #[watch]
+                    set_sensitive: !model.checking_commit,
+                    connect_clicked => DescribeRepoInput::CheckCommit,
+                },
+            },
+            adw::ActionRow {
+                set_title: "Merkle Search Tree",
+                set_subtitle: "The tree of nodes the repository's records are stored in",
+                set_activatable: true,
+                add_suffix = &gtk::Image {
+                    set_icon_name: Some("go-next-symbolic"),
+                },
+                connect_activated => DescribeRepoInput::ExploreMst,
+            },
+            adw::ActionRow {
+                set_title: "Download Repository",
+                #[watch]
+                set_subtitle: &glib::markup_escape_text(&model.download_status),
+                add_suffix = &gtk::Button {
+                    set_icon_name: "document-save-symbolic",
+                    set_tooltip_text: Some("Save the whole repository as a CAR file"),
+                    set_valign: gtk::Align::Center,
+                    add_css_class: "flat",
+                    #[watch]
+                    set_sensitive: !model.downloading,
+                    connect_clicked => DescribeRepoInput::ChooseDownload,
+                },
+                add_suffix = &gtk::Button {
+                    set_icon_name: "view-refresh-symbolic",
+                    set_tooltip_text: Some("Fetch only what changed since a saved CAR file"),
+                    set_valign: gtk::Align::Center,
+                    add_css_class: "flat",
+                    #[watch]
+                    set_sensitive: !model.downloading,
+                    connect_clicked => DescribeRepoInput::ChooseRefresh,
+                },
+            },
+            #[name(changes)]
+            adw::ExpanderRow {
+                set_title: "Changes Since Last Download",
+                set_visible: false,
+            },
+            #[name(blob_list)]
+            adw::ExpanderRow {
+                set_title: "Blobs",
+                #[watch]
+                set_subtitle: &glib::markup_escape_text(&model.blobs_status),
+                add_suffix = &gtk::Button {
+                    #[watch]
+                    set_label: if model.blobs.is_empty() { "List" } else { "More" },
+                    set_valign: gtk::Align::Center,
+                    #[watch]
+                    set_visible: model.more_blobs,
+                    #[watch]
+                    set_sensitive: !model.listing_blobs,
+                    connect_clicked => DescribeRepoInput::ListBlobs,
+                },
+            },
             #[name(collections)]
             adw::ExpanderRow {
                 set_title: "Collections",
@@ -151,39 +630,87 @@ impl AsyncFactoryComponent for DescribeRepoView {
                 set_title: "DID Document",
                 set_expanded: true,
             },
-        },
-        #[local_ref]
-        returned_widget -> adw::TabPage {
-            set_title: &format!("{}", &self.did.to_string())
+            #[name(rotation_keys)]
+            adw::ExpanderRow {
+                set_title: "PLC Rotation Keys",
+                set_subtitle: "The keys that can change this identity, highest priority first",
+                set_visible: false,
+            },
+            #[name(history)]
+            adw::ExpanderRow {
+                set_title: "History",
+                set_visible: false,
+                add_suffix = &gtk::Label {
+                    set_valign: gtk::Align::Center,
+                    #[watch]
+                    set_visible: model.plc_check.is_some(),
+                    #[watch]
+                    set_label: model.plc_check.as_ref().map(|check| check.badge()).unwrap_or_default(),
+                    #[watch]
+                    set_css_classes: &[
+                        "caption-heading",
+                        model.plc_check.as_ref().map(|check| check.css_class()).unwrap_or_default(),
+                    ],
+                },
+                add_row = &adw::ActionRow {
+                    set_title: "Operation Chain",
+                    #[watch]
+                    set_subtitle: &model
+                        .plc_check
+                        .as_ref()
+                        .map(|check| glib::markup_escape_text(&check.to_string()).to_string())
+                        .unwrap_or_else(|| String::from("Replaying the log…")),
+                    set_subtitle_selectable: true,
+                    add_css_class: "property",
+                },
+            },
         }
     }
-    async fn init_model(
+    fn init(
         value: Self::Init,
-        _index: &DynamicIndex,
-        _sender: AsyncFactorySender<Self>,
-    ) -> Self {
-        Self {
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {
             collections: value.collections,
             did: value.did,
             did_doc: value.did_doc,
             handle: value.handle,
             handle_is_correct: value.handle_is_correct,
-        }
-    }
-    fn init_widgets(
-        &mut self,
-        index: &DynamicIndex,
-        root: Self::Root,
-        returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
-        sender: AsyncFactorySender<Self>,
-    ) -> Self::Widgets {
+            downloading: false,
+            download_status: String::from("Fetch every record and commit as a CAR file"),
+            saved: None,
+            changelog: vec![],
+            checking_commit: false,
+            commit_check: None,
+            plc_check: None,
+            commit_status: String::from("Check the latest commit against the DID's #atproto key"),
+            listing_blobs: false,
+            more_blobs: true,
+            blobs_cursor: None,
+            blobs_status: String::from("The images, video and other media the records refer to"),
+            blobs: vec![],
+        };
         let widgets = view_output!();
-        for collection in &self.collections {
+        for collection in &model.collections {
+            let uri = AtUri {
+                authority: AtIdentifier::Did(model.did.clone()),
+                collection: Some(collection.clone()),
+                rkey: None,
+            };
             let row = adw::ActionRow::new();
             row.set_title(&collection.to_string());
+            row.set_activatable(true);
+            row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+            let sender = sender.clone();
+            row.connect_activated(move |_| {
+                sender
+                    .output(DescribeRepoOutput::Open(uri.clone()))
+                    .unwrap();
+            });
             widgets.collections.add_row(&row);
         }
-        if let Ok(did_doc) = DidDocument::try_from_unknown(self.did_doc.clone()) {
+        if let Ok(did_doc) = DidDocument::try_from_unknown(model.did_doc.clone()) {
             println!("did_doc verified: {:?}", did_doc);
             let context = adw::ExpanderRow::new();
             context.set_title("Context");
@@ -250,6 +777,10 @@ impl AsyncFactoryComponent for DescribeRepoView {
                         public_key.set_subtitle(&public_key_multibase);
                         public_key.add_css_class("property");
                         vm_row.add_row(&public_key);
+                        add_key_rows(
+                            &vm_row,
+                            PublicKey::from_verification_method(&vm.r#type, &public_key_multibase),
+                        );
                     }
                     verification_methods.add_row(&vm_row);
                 }
@@ -274,13 +805,26 @@ impl AsyncFactoryComponent for DescribeRepoView {
                                 set_subtitle: &s.r#type,
                                 add_css_class: "property"
                             },
-                            add_row = &adw::ActionRow {
-                                set_title: "serviceEndpoint",
-                                set_subtitle: &s.service_endpoint,
-                                add_css_class: "property"
-                            },
                         }
                     }
+                    let endpoint = adw::ActionRow::new();
+                    endpoint.set_title("serviceEndpoint");
+                    endpoint.set_subtitle(&s.service_endpoint);
+                    endpoint.add_css_class("property");
+                    /* the PDS is a server in its own right, with its own tab */
+                    if s.r#type == "AtprotoPersonalDataServer" {
+                        endpoint.set_activatable(true);
+                        endpoint.set_tooltip_text(Some("Show the Server"));
+                        endpoint.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+                        let url = server_url(&s.service_endpoint);
+                        let sender = sender.clone();
+                        endpoint.connect_activated(move |_| {
+                            sender
+                                .output(DescribeRepoOutput::OpenServer(url.clone()))
+                                .unwrap();
+                        });
+                    }
+                    service_row.add_row(&endpoint);
                     services.add_row(&service_row);
                 }
                 widgets.did_doc.add_row(&services);
@@ -288,7 +832,219 @@ impl AsyncFactoryComponent for DescribeRepoView {
         } else {
             println!("invalid did_doc!");
         }
-        widgets
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        match message {
+            DescribeRepoInput::PlcData(data) => {
+                for (priority, did_key) in data.rotation_keys.iter().enumerate() {
+                    let row = adw::ExpanderRow::new();
+                    row.set_title(&format!("Rotation key {}", priority + 1));
+                    row.set_subtitle(did_key);
+                    add_key_rows(&row, PublicKey::from_did_key(did_key));
+                    widgets.rotation_keys.add_row(&row);
+                }
+                widgets
+                    .rotation_keys
+                    .set_visible(!data.rotation_keys.is_empty());
+            }
+            DescribeRepoInput::Blobs(cids, cursor) => {
+                for cid in cids {
+                    let repo = AtIdentifier::Did(self.did.clone());
+                    let output = sender.output_sender().clone();
+                    let row = BlobRow::new(BlobRef::from_cid(cid), move |reference| {
+                        output.emit(DescribeRepoOutput::FetchBlob(repo.clone(), reference));
+                    });
+                    widgets.blob_list.add_row(row.widget());
+                    self.blobs.push(row);
+                }
+                self.listing_blobs = false;
+                self.blobs_status = match &cursor {
+                    Some(_) => format!("The first {} blobs", self.blobs.len()),
+                    None => format!("{} blobs", self.blobs.len()),
+                };
+                self.more_blobs = cursor.is_some();
+                self.blobs_cursor = cursor;
+                widgets.blob_list.set_expanded(true);
+            }
+            DescribeRepoInput::PlcLog(log) => {
+                let nullified = log.iter().filter(|entry| entry.nullified).count();
+                widgets.history.set_subtitle(&format!(
+                    "{} PLC operations, {} nullified",
+                    log.len(),
+                    nullified
+                ));
+                /* newest first, which is the end of the log */
+                for entry in log.iter().rev() {
+                    widgets.history.add_row(&history_row(&log, entry));
+                }
+                widgets.history.set_visible(!log.is_empty());
+            }
+            DescribeRepoInput::Refreshed(download, changes) => {
+                for row in self.changelog.drain(..) {
+                    widgets.changes.remove(&row);
+                }
+                for change in &changes.changes {
+                    let row = changelog_row(&self.did, change, &sender);
+                    widgets.changes.add_row(&row);
+                    self.changelog.push(row);
+                }
+                widgets.changes.set_subtitle(&format!(
+                    "{} created, {} updated, {} deleted between rev {} and rev {}",
+                    changes.count(ChangeKind::Created),
+                    changes.count(ChangeKind::Updated),
+                    changes.count(ChangeKind::Deleted),
+                    changes.from.as_deref().unwrap_or("unknown"),
+                    changes.to
+                ));
+                widgets.changes.set_visible(true);
+                widgets.changes.set_expanded(!changes.changes.is_empty());
+                self.downloading = false;
+                self.download_status = format!(
+                    "Brought {} up to date ({}, {} blocks)\nroot {}, rev {}",
+                    download.path.display(),
+                    glib::format_size(download.size as u64),
+                    download.blocks,
+                    download.root,
+                    download.rev
+                );
+                self.saved = Some(download.path);
+            }
+            message => self.update(message, sender.clone(), root),
+        }
+        self.update_view(widgets, sender);
+    }
+
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, root: &Self::Root) {
+        match message {
+            DescribeRepoInput::PlcData(_)
+            | DescribeRepoInput::PlcLog(_)
+            | DescribeRepoInput::Blobs(_, _)
+            | DescribeRepoInput::Refreshed(_, _) => (),
+            DescribeRepoInput::ListBlobs => {
+                self.listing_blobs = true;
+                self.blobs_status = String::from("Listing blobs…");
+                sender.output(DescribeRepoOutput::ListBlobs(
+                    self.did.clone(),
+                    self.blobs_cursor.clone(),
+                ));
+            }
+            DescribeRepoInput::BlobsFailed(error) => {
+                self.listing_blobs = false;
+                self.blobs_status = format!("{}: {}", error.title(), error);
+            }
+            DescribeRepoInput::Blob(blob) => {
+                if let Some(row) = self
+                    .blobs
+                    .iter()
+                    .find(|row| row.reference.cid == blob.reference.cid)
+                {
+                    row.show(&blob);
+                }
+            }
+            DescribeRepoInput::BlobFailed(cid, error) => {
+                if let Some(row) = self.blobs.iter().find(|row| row.reference.cid == cid) {
+                    row.failed(&error);
+                }
+            }
+            DescribeRepoInput::PlcLogChecked(check) => self.plc_check = Some(check),
+            DescribeRepoInput::ExploreMst => {
+                sender.output(DescribeRepoOutput::ExploreMst(self.did.clone()));
+            }
+            DescribeRepoInput::CheckCommit => {
+                self.checking_commit = true;
+                self.commit_status = String::from("Checking the latest commit…");
+                sender.output(DescribeRepoOutput::CheckCommit(self.did.clone()));
+            }
+            DescribeRepoInput::CommitChecked(check) => {
+                self.checking_commit = false;
+                self.commit_status = check.to_string();
+                self.commit_check = Some(check);
+            }
+            DescribeRepoInput::CommitCheckFailed(error) => {
+                self.checking_commit = false;
+                self.commit_check = None;
+                self.commit_status = format!("{}: {}", error.title(), error);
+            }
+            DescribeRepoInput::ChooseDownload => {
+                let dialog = gtk::FileDialog::builder()
+                    .title("Download Repository")
+                    .initial_name(format!("{}.car", self.handle.as_str()))
+                    .modal(true)
+                    .build();
+                let input = sender.input_sender().clone();
+                dialog.save(
+                    root.root().and_downcast_ref::<gtk::Window>(),
+                    None::<&gio::Cancellable>,
+                    move |result| {
+                        /* dismissing the dialog shows up as an error too, and isn't worth reporting */
+                        if let Some(path) = result.ok().and_then(|file| file.path()) {
+                            input.emit(DescribeRepoInput::Download(path));
+                        }
+                    },
+                );
+            }
+            DescribeRepoInput::Download(path) => {
+                self.downloading = true;
+                self.download_status = format!("Downloading to {}…", path.display());
+                sender.output(DescribeRepoOutput::Download(self.did.clone(), path));
+            }
+            DescribeRepoInput::Downloaded(download) => {
+                self.downloading = false;
+                self.download_status = format!(
+                    "Saved {} ({} blocks) to {}\nroot {}, rev {}",
+                    glib::format_size(download.size as u64),
+                    download.blocks,
+                    download.path.display(),
+                    download.root,
+                    download.rev
+                );
+                self.saved = Some(download.path);
+            }
+            DescribeRepoInput::ChooseRefresh => {
+                if let Some(path) = &self.saved {
+                    sender.input(DescribeRepoInput::Refresh(path.clone()));
+                    return;
+                }
+                /* nothing was saved this session, so ask which earlier export to bring up to date */
+                let filter = gtk::FileFilter::new();
+                filter.set_name(Some("CAR files"));
+                filter.add_pattern("*.car");
+                let filters = gio::ListStore::new::<gtk::FileFilter>();
+                filters.append(&filter);
+                let dialog = gtk::FileDialog::builder()
+                    .title("Refresh Repository")
+                    .filters(&filters)
+                    .modal(true)
+                    .build();
+                let input = sender.input_sender().clone();
+                dialog.open(
+                    root.root().and_downcast_ref::<gtk::Window>(),
+                    None::<&gio::Cancellable>,
+                    move |result| {
+                        if let Some(path) = result.ok().and_then(|file| file.path()) {
+                            input.emit(DescribeRepoInput::Refresh(path));
+                        }
+                    },
+                );
+            }
+            DescribeRepoInput::Refresh(path) => {
+                self.downloading = true;
+                self.download_status = format!("Fetching what changed since {}…", path.display());
+                sender.output(DescribeRepoOutput::Refresh(self.did.clone(), path));
+            }
+            DescribeRepoInput::DownloadFailed(error) => {
+                self.downloading = false;
+                self.download_status = format!("{}: {}", error.title(), error);
+            }
+        }
     }
 }
 
@@ -298,13 +1054,16 @@ pub struct ListRecordsView {
     records: Vec<com::atproto::repo::list_records::Record>,
 }
 
-#[relm4::factory(async, pub)]
-impl AsyncFactoryComponent for ListRecordsView {
+#[derive(Debug)]
+pub enum ListRecordsOutput {
+    Open(AtUri),
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for ListRecordsView {
     type Init = com::atproto::repo::list_records::OutputData;
     type Input = ();
-    type Output = ();
-    type CommandOutput = ();
-    type ParentWidget = adw::TabView;
+    type Output = ListRecordsOutput;
     view! {
         #[root]
         gtk::ListBox {
@@ -314,31 +1073,30 @@ impl AsyncFactoryComponent for ListRecordsView {
         }
     }
 
-    async fn init_model(
+    fn init(
         value: Self::Init,
-        _index: &DynamicIndex,
-        _sender: AsyncFactorySender<Self>,
-    ) -> Self {
-        Self {
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {
             cursor: value.cursor,
             records: value.records,
-        }
-    }
-
-    fn init_widgets(
-        &mut self,
-        _index: &DynamicIndex,
-        root: Self::Root,
-        _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
-        _sender: AsyncFactorySender<Self>,
-    ) -> Self::Widgets {
+        };
         let widgets = view_output!();
-        for record in &self.records {
+        for record in &model.records {
             let row = adw::ActionRow::new();
             row.set_title(&record.data.uri.to_string());
+            if let Ok(uri) = record.data.uri.parse::<AtUri>() {
+                row.set_activatable(true);
+                row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+                let sender = sender.clone();
+                row.connect_activated(move |_| {
+                    sender.output(ListRecordsOutput::Open(uri.clone())).unwrap();
+                });
+            }
             root.append(&row);
         }
-        widgets
+        ComponentParts { model, widgets }
     }
 }
 
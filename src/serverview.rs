@@ -0,0 +1,153 @@
+use crate::agent::ServerOverview;
+use relm4::adw::prelude::*;
+use relm4::gtk::prelude::*;
+use relm4::gtk::{gio, glib};
+use relm4::prelude::*;
+use relm4::{gtk, ComponentParts, ComponentSender, SimpleComponent};
+
+/*
+a PDS as a host rather than as the home of any one repository: what describeServer says about signing
+up there and who runs it, and whether /xrpc/_health answers. the tab only gets this far if the server
+answered at least one of them, and not every server implements both, so each row says what it could
+find out
+*/
+#[derive(Debug)]
+pub struct ServerView {
+    overview: ServerOverview,
+}
+
+fn property_row(title: &str, subtitle: &str) -> adw::ActionRow {
+    let row = adw::ActionRow::new();
+    row.set_title(title);
+    row.set_subtitle(&glib::markup_escape_text(subtitle));
+    row.set_subtitle_selectable(true);
+    row.add_css_class("property");
+    row
+}
+
+// a link the server gives, which opens in the browser if it's a web page
+fn link_row(title: &str, link: Option<&str>) -> adw::ActionRow {
+    let Some(link) = link else {
+        return property_row(title, "Not given");
+    };
+    let row = property_row(title, link);
+    /* the server can name anything here, and only a web page is safe to hand to the launcher */
+    if !(link.starts_with("https://") || link.starts_with("http://")) {
+        return row;
+    }
+    let open = gtk::Button::from_icon_name("adw-external-link-symbolic");
+    open.set_tooltip_text(Some("Open in Browser"));
+    open.set_valign(gtk::Align::Center);
+    open.add_css_class("flat");
+    let link = link.to_string();
+    open.connect_clicked(move |button| {
+        gtk::UriLauncher::new(&link).launch(
+            button.root().and_downcast_ref::<gtk::Window>(),
+            None::<&gio::Cancellable>,
+            |_| (),
+        );
+    });
+    row.add_suffix(&open);
+    row
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for ServerView {
+    type Init = ServerOverview;
+    type Input = ();
+    type Output = ();
+
+    view! {
+        #[root]
+        gtk::ListBox {
+            set_hexpand: true,
+            set_margin_all: 10,
+            set_selection_mode: gtk::SelectionMode::None,
+            inline_css: "border-radius: 10px",
+            adw::ActionRow {
+                set_title: "Server",
+                set_subtitle: &glib::markup_escape_text(&model.overview.url),
+                set_subtitle_selectable: true,
+                add_css_class: "property",
+                add_suffix = &gtk::Label {
+                    set_valign: gtk::Align::Center,
+                    set_label: if model.overview.health.is_ok() { "Healthy" } else { "No Health Check" },
+                    set_css_classes: &[
+                        "caption-heading",
+                        if model.overview.health.is_ok() { "success" } else { "warning" },
+                    ],
+                },
+            },
+            adw::ActionRow {
+                set_title: "Health",
+                set_subtitle: &glib::markup_escape_text(&match &model.overview.health {
+                    Ok(_) => String::from("/xrpc/_health answered"),
+                    Err(err) => format!("/xrpc/_health didn't answer: {}", err),
+                }),
+                add_css_class: "property",
+            },
+            adw::ActionRow {
+                set_title: "Version",
+                set_subtitle: &glib::markup_escape_text(
+                    model
+                        .overview
+                        .health
+                        .as_ref()
+                        .ok()
+                        .and_then(|health| health.version.as_deref())
+                        .unwrap_or("Not reported"),
+                ),
+                add_css_class: "property",
+            },
+        }
+    }
+
+    fn init(
+        overview: Self::Init,
+        root: Self::Root,
+        _sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self { overview };
+        let widgets = view_output!();
+        match &model.overview.description {
+            Ok(description) => {
+                root.append(&property_row("DID", &description.did));
+                root.append(&property_row(
+                    "User Domains",
+                    &if description.available_user_domains.is_empty() {
+                        String::from("None")
+                    } else {
+                        description.available_user_domains.join(", ")
+                    },
+                ));
+                let sign_up = match (
+                    description.invite_code_required,
+                    description.phone_verification_required,
+                ) {
+                    (true, true) => "Invite code and phone verification required",
+                    (true, false) => "Invite code required",
+                    (false, true) => "Phone verification required",
+                    (false, false) => "Open to anyone",
+                };
+                root.append(&property_row("Sign Up", sign_up));
+                root.append(&property_row(
+                    "Contact",
+                    description.contact.email.as_deref().unwrap_or("Not given"),
+                ));
+                root.append(&link_row(
+                    "Privacy Policy",
+                    description.links.privacy_policy.as_deref(),
+                ));
+                root.append(&link_row(
+                    "Terms of Service",
+                    description.links.terms_of_service.as_deref(),
+                ));
+            }
+            Err(err) => root.append(&property_row(
+                "Description",
+                &format!("describeServer didn't answer: {}", err),
+            )),
+        }
+        ComponentParts { model, widgets }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::cache::format_cached_at;
+use relm4::gtk::glib;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/*
+dated copies of repositories, taken whenever one is downloaded or refreshed, so that its records can
+be looked at as they were at the time. they live under $XDG_DATA_HOME/branches/snapshots rather than
+in the cache, where eviction would eventually throw them away.
+
+each repository gets a directory named after its DID, and each snapshot is the CAR export named after
+the revision of its commit. revisions are TIDs, which sort by the time they were made and carry that
+time, so the file names alone are enough to order and date the timeline
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub did: String,
+    pub rev: String,
+    pub path: PathBuf,
+    // seconds since the unix epoch, if the revision is a TID
+    pub time: Option<i64>,
+}
+
+impl Snapshot {
+    pub fn date(&self) -> String {
+        match self.time {
+            Some(time) => format_cached_at(time),
+            None => String::from("an unknown time"),
+        }
+    }
+}
+
+/*
+both parts of a snapshot's path come from data a PDS served, so they're checked to be what they claim
+before going anywhere near the file system: a DID is `did:` and a method-specific id without path
+separators, and a revision has to be a TID
+*/
+fn directory_for(did: &str) -> Option<PathBuf> {
+    let valid = did.starts_with("did:")
+        && !did.contains(['/', '\\'])
+        && !did.contains("..")
+        && did
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ":._%-".contains(c));
+    valid.then(|| {
+        glib::user_data_dir()
+            .join("branches")
+            .join("snapshots")
+            .join(did)
+    })
+}
+
+// TIDs are 64 bits in sortable base32: the top bit is zero, then microseconds, then a 10 bit clock id
+fn tid_time(rev: &str) -> Option<i64> {
+    const ALPHABET: &[u8] = b"234567abcdefghijklmnopqrstuvwxyz";
+    if rev.len() != 13 {
+        return None;
+    }
+    let mut value: u64 = 0;
+    for byte in rev.bytes() {
+        let digit = ALPHABET.iter().position(|c| *c == byte)? as u64;
+        value = value.checked_mul(32)?.checked_add(digit)?;
+    }
+    Some(((value >> 10) / 1_000_000) as i64)
+}
+
+// a snapshot already taken at the same revision is left as it is
+pub fn save(did: &str, rev: &str, bytes: &[u8]) -> io::Result<PathBuf> {
+    if tid_time(rev).is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} isn't a TID", rev),
+        ));
+    }
+    let directory = directory_for(did).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't a DID", did))
+    })?;
+    fs::create_dir_all(&directory)?;
+    let path = directory.join(format!("{}.car", rev));
+    if !path.exists() {
+        fs::write(&path, bytes)?;
+    }
+    Ok(path)
+}
+
+// oldest first
+pub fn list(did: &str) -> Vec<Snapshot> {
+    let Some(Ok(entries)) = directory_for(did).map(fs::read_dir) else {
+        return vec![];
+    };
+    let mut snapshots: Vec<Snapshot> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let rev = path
+                .file_name()?
+                .to_str()?
+                .strip_suffix(".car")?
+                .to_string();
+            Some(Snapshot {
+                did: did.to_string(),
+                time: tid_time(&rev),
+                rev,
+                path,
+            })
+        })
+        .collect();
+    snapshots.sort_by(|a, b| a.rev.cmp(&b.rev));
+    snapshots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tids_carry_the_time_they_were_made() {
+        // 2024-09-09 19:46:02 UTC
+        assert_eq!(tid_time("3l3qo2vutsw2b"), Some(1725911162));
+        assert_eq!(tid_time("2222222222222"), Some(0));
+    }
+
+    #[test]
+    fn anything_but_a_tid_is_refused() {
+        for rev in [
+            "",
+            "3l3qo2vutsw2",
+            "3l3qo2vutsw2bb",
+            "3L3QO2VUTSW2B",
+            "3l3qo2vutsw21",
+            "../../../etc",
+            // more than 64 bits
+            "kzzzzzzzzzzzz",
+        ] {
+            assert_eq!(tid_time(rev), None, "{}", rev);
+            assert_eq!(
+                save("did:plc:alice", rev, b"").unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+
+    #[test]
+    fn dids_stay_inside_the_snapshots_directory() {
+        let path = directory_for("did:plc:alice").unwrap();
+        assert!(path.ends_with("branches/snapshots/did:plc:alice"));
+        assert!(directory_for("did:web:example.com%3A8080").is_some());
+        for did in [
+            "did:plc:alice/..",
+            "did:web:example.com/../../..",
+            "did:plc:..",
+            "did:plc:a\\b",
+            "did:plc:alice bob",
+            "alice.test",
+        ] {
+            assert_eq!(directory_for(did), None, "{}", did);
+            assert_eq!(
+                save(did, "3l3qo2vutsw2b", b"").unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+            assert!(list(did).is_empty());
+        }
+    }
+}
@@ -0,0 +1,701 @@
+use crate::agent::{AgentError, LookupStage, RepoDownload, ServerOverview};
+use crate::blob::{Blob, BlobRef};
+use crate::cache::format_cached_at;
+use crate::car::Mst;
+use crate::diff::{DiffSource, RepoDiff};
+use crate::diffview::{DiffOutput, DiffView};
+use crate::firehose::FirehoseSource;
+use crate::firehoseview::{FirehoseInput, FirehoseOutput, FirehoseView};
+use crate::mstview::{MstOutput, MstView};
+use crate::plc::{PlcData, PlcLogEntry};
+use crate::recordview::{
+    DescribeRepoInput, DescribeRepoOutput, DescribeRepoView, GetRecordInput, GetRecordOutput,
+    GetRecordView, ListRecordsOutput, ListRecordsView,
+};
+use crate::serverview::ServerView;
+use crate::snapshots::Snapshot;
+use crate::types::AtUri;
+use crate::verify::{CidCheck, CommitCheck, InclusionCheck, PlcLogCheck};
+use atrium_api::types::string::{AtIdentifier, Did, Handle};
+use atrium_api::types::Unknown;
+use atrium_api::*;
+use ipld_core::cid::Cid as IpldCid;
+use relm4::adw::prelude::*;
+use relm4::factory::{AsyncFactoryComponent, FactoryView};
+use relm4::gtk::prelude::*;
+use relm4::{gtk, Component, ComponentController, Controller};
+use relm4::{prelude::*, AsyncFactorySender};
+use std::path::PathBuf;
+
+/*
+a tab owns whichever view it is currently showing, so that the views themselves don't need to know
+anything about the adw::TabView they end up in. every tab starts out loading, and is replaced in place
+once the agent answers
+*/
+enum TabContent {
+    Loading(adw::StatusPage),
+    Record(Controller<GetRecordView>),
+    Records(Controller<ListRecordsView>),
+    Repo(Controller<DescribeRepoView>),
+    Mst(Controller<MstView>),
+    Diff(Controller<DiffView>),
+    Server(Controller<ServerView>),
+    Firehose(Controller<FirehoseView>),
+    Failed(adw::StatusPage),
+}
+
+impl TabContent {
+    fn widget(&self) -> gtk::Widget {
+        match self {
+            TabContent::Loading(page) => page.clone().upcast(),
+            TabContent::Record(view) => view.widget().clone().upcast(),
+            TabContent::Records(view) => view.widget().clone().upcast(),
+            TabContent::Repo(view) => view.widget().clone().upcast(),
+            TabContent::Mst(view) => view.widget().clone().upcast(),
+            TabContent::Diff(view) => view.widget().clone().upcast(),
+            TabContent::Server(view) => view.widget().clone().upcast(),
+            TabContent::Firehose(view) => view.widget().clone().upcast(),
+            TabContent::Failed(page) => page.clone().upcast(),
+        }
+    }
+}
+
+// what a tab is for: something with an at:// URI, now or as it was in a snapshot, one version of a
+// record, a view of a repository that doesn't have one, a PDS, or a firehose, live or recorded
+#[derive(Debug, Clone)]
+pub enum TabTarget {
+    Uri(AtUri),
+    AsOf(AtUri, Snapshot),
+    Version(AtUri, IpldCid),
+    // a URI in a CAR export that's been opened, which links within its repository stay in
+    Local(PathBuf, AtUri),
+    Mst(Did),
+    LocalMst(PathBuf, Did),
+    // a PDS, by its base URL
+    Server(String),
+    Diff(DiffSource),
+    Firehose(FirehoseSource),
+}
+
+impl std::fmt::Display for TabTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TabTarget::Uri(uri) => write!(f, "{}", uri),
+            TabTarget::AsOf(uri, snapshot) => write!(f, "{} as of {}", uri, snapshot.date()),
+            TabTarget::Version(uri, cid) => write!(f, "{} at {}", uri, cid),
+            TabTarget::Local(path, uri) => write!(
+                f,
+                "{} in {}",
+                uri,
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            TabTarget::Mst(did) => write!(f, "Merkle Search Tree of {}", did.as_str()),
+            TabTarget::LocalMst(path, did) => write!(
+                f,
+                "Merkle Search Tree of {} in {}",
+                did.as_str(),
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            TabTarget::Server(url) => write!(
+                f,
+                "Server {}",
+                url.trim_start_matches("https://")
+                    .trim_start_matches("http://")
+            ),
+            TabTarget::Diff(DiffSource::Cars(first, second)) => write!(
+                f,
+                "Changes between {} and {}",
+                first.file_name().unwrap_or_default().to_string_lossy(),
+                second.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            TabTarget::Diff(DiffSource::Commit(commit)) => {
+                write!(f, "Changes in {} at {}", commit.repo, commit.rev)
+            }
+            TabTarget::Firehose(FirehoseSource::Live(_)) => write!(f, "Firehose"),
+            TabTarget::Firehose(FirehoseSource::Jetstream(_)) => write!(f, "Jetstream"),
+            TabTarget::Firehose(FirehoseSource::Replay(path)) => write!(
+                f,
+                "Replay of {}",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TabInput {
+    Stage(LookupStage),
+    // the content being shown came from the cache
+    Cached(i64),
+    // the content being shown was read from a local CAR export
+    Local(PathBuf),
+    // snapshots of the repository the content belongs to, which the timeline can go back to
+    Snapshots(Vec<Snapshot>),
+    // the timeline was moved
+    TimeTravel,
+    Record(com::atproto::repo::get_record::OutputData),
+    Records(com::atproto::repo::list_records::OutputData),
+    Repo(com::atproto::repo::describe_repo::OutputData),
+    Mst(Mst),
+    Diff(RepoDiff),
+    Server(ServerOverview),
+    PlcData(PlcData),
+    PlcLog(Vec<PlcLogEntry>),
+    PlcLogChecked(PlcLogCheck),
+    CidChecked(CidCheck),
+    Failed(AgentError),
+    Retry,
+    Open(TabTarget),
+    DownloadRepo(Did, PathBuf),
+    RefreshRepo(Did, PathBuf),
+    Downloaded(RepoDownload),
+    Refreshed(RepoDownload, RepoDiff),
+    DownloadFailed(AgentError),
+    CheckCommit(Did),
+    CommitChecked(CommitCheck),
+    CommitCheckFailed(AgentError),
+    ProveRecord(AtUri),
+    RecordProved(InclusionCheck),
+    ProofFailed(AgentError),
+    ListBlobs(Did, Option<String>),
+    Blobs(Vec<IpldCid>, Option<String>),
+    BlobsFailed(AgentError),
+    FetchBlob(AtIdentifier, BlobRef),
+    Blob(Blob),
+    BlobFailed(IpldCid, AgentError),
+    ResolveHandle(Handle),
+    Resolved(Handle, Did),
+    ResolveFailed(Handle, AgentError),
+}
+
+#[derive(Debug)]
+pub enum TabOutput {
+    Retry(DynamicIndex, TabTarget),
+    Open(TabTarget),
+    DownloadRepo(DynamicIndex, Did, PathBuf),
+    RefreshRepo(DynamicIndex, Did, PathBuf),
+    // the CAR export the tab was opened from, if any, answers these instead of the network
+    CheckCommit(DynamicIndex, Did, Option<PathBuf>),
+    ProveRecord(DynamicIndex, AtUri, Option<PathBuf>),
+    ListBlobs(DynamicIndex, Did, Option<String>),
+    FetchBlob(DynamicIndex, AtIdentifier, BlobRef),
+    ResolveHandle(DynamicIndex, Handle),
+}
+
+pub struct Tab {
+    index: DynamicIndex,
+    target: TabTarget,
+    content: TabContent,
+    snapshots: Vec<Snapshot>,
+}
+
+impl Tab {
+    // the URI being looked at, whether now or in a snapshot
+    fn uri(&self) -> Option<&AtUri> {
+        match &self.target {
+            TabTarget::Uri(uri) | TabTarget::AsOf(uri, _) => Some(uri),
+            _ => None,
+        }
+    }
+
+    // the CAR export this tab was opened from, and the DID of the repository in it
+    fn file(&self) -> Option<(&PathBuf, &Did)> {
+        match &self.target {
+            TabTarget::Local(path, uri) => match &uri.authority {
+                AtIdentifier::Did(did) => Some((path, did)),
+                AtIdentifier::Handle(_) => None,
+            },
+            TabTarget::LocalMst(path, did) => Some((path, did)),
+            _ => None,
+        }
+    }
+
+    /*
+    anything a tab opened from a file links to in the same repository is read from that file too.
+    links anywhere else go online as usual
+    */
+    fn scoped(&self, target: TabTarget) -> TabTarget {
+        let Some((path, did)) = self.file() else {
+            return target;
+        };
+        match target {
+            TabTarget::Uri(uri) if uri.authority == AtIdentifier::Did(did.clone()) => {
+                TabTarget::Local(path.clone(), uri)
+            }
+            TabTarget::Mst(other) if other == *did => TabTarget::LocalMst(path.clone(), other),
+            target => target,
+        }
+    }
+
+    // where the timeline should be: a snapshot's position, or one past the last for now
+    fn position(&self) -> usize {
+        match &self.target {
+            TabTarget::AsOf(_, snapshot) => self
+                .snapshots
+                .iter()
+                .position(|other| other.rev == snapshot.rev)
+                .unwrap_or(self.snapshots.len()),
+            _ => self.snapshots.len(),
+        }
+    }
+
+    fn moment(&self) -> String {
+        match &self.target {
+            TabTarget::AsOf(_, snapshot) => format!("{}, rev {}", snapshot.date(), snapshot.rev),
+            _ => String::from("Now"),
+        }
+    }
+
+    fn loading_page(target: &TabTarget) -> adw::StatusPage {
+        relm4::view! {
+            page = adw::StatusPage {
+                set_title: &target.to_string(),
+                set_description: Some("Starting lookup…"),
+                set_vexpand: true,
+                #[wrap(Some)]
+                set_child = &gtk::Spinner {
+                    set_spinning: true,
+                    set_size_request: (32, 32),
+                    set_halign: gtk::Align::Center,
+                },
+            }
+        }
+        page
+    }
+
+    fn failure_page(error: &AgentError, sender: &AsyncFactorySender<Self>) -> adw::StatusPage {
+        relm4::view! {
+            page = adw::StatusPage {
+                set_icon_name: Some("dialog-error-symbolic"),
+                set_title: error.title(),
+                set_description: Some(&error.to_string()),
+                set_vexpand: true,
+                #[wrap(Some)]
+                set_child = &gtk::Button {
+                    set_label: "Retry",
+                    set_halign: gtk::Align::Center,
+                    add_css_class: "pill",
+                    add_css_class: "suggested-action",
+                    connect_clicked[sender] => move |_| {
+                        sender.input(TabInput::Retry);
+                    },
+                },
+            }
+        }
+        page
+    }
+
+    fn is_loading(&self) -> bool {
+        matches!(self.content, TabContent::Loading(_))
+    }
+}
+
+#[relm4::factory(async, pub)]
+impl AsyncFactoryComponent for Tab {
+    type Init = TabTarget;
+    type Input = TabInput;
+    type Output = TabOutput;
+    type CommandOutput = ();
+    type ParentWidget = adw::TabView;
+
+    view! {
+        #[root]
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            #[name(banner)]
+            adw::Banner {
+                set_revealed: false,
+            },
+            #[name(timeline)]
+            gtk::Box {
+                set_spacing: 10,
+                set_margin_start: 10,
+                set_margin_end: 10,
+                set_margin_top: 5,
+                set_visible: false,
+                gtk::Label {
+                    set_label: "Snapshots",
+                    add_css_class: "heading",
+                },
+                #[name(slider)]
+                gtk::Scale {
+                    set_hexpand: true,
+                    set_digits: 0,
+                    set_round_digits: 0,
+                    set_draw_value: false,
+                    set_increments: (1.0, 1.0),
+                },
+                #[name(moment)]
+                gtk::Label {
+                    add_css_class: "dim-label",
+                },
+            },
+            #[name(content)]
+            gtk::ScrolledWindow {
+                set_vexpand: true,
+                set_hscrollbar_policy: gtk::PolicyType::Never,
+            },
+        },
+        #[local_ref]
+        returned_widget -> adw::TabPage {
+            #[watch]
+            set_title: &self.target.to_string(),
+            #[watch]
+            set_loading: self.is_loading(),
+        }
+    }
+
+    async fn init_model(
+        target: Self::Init,
+        index: &DynamicIndex,
+        sender: AsyncFactorySender<Self>,
+    ) -> Self {
+        /* a firehose isn't looked up, it is connected to as soon as it's shown */
+        let content = match &target {
+            TabTarget::Firehose(source) => {
+                TabContent::Firehose(FirehoseView::builder().launch(source.clone()).forward(
+                    sender.input_sender(),
+                    |output| match output {
+                        FirehoseOutput::Open(uri) => TabInput::Open(TabTarget::Uri(uri)),
+                        FirehoseOutput::ShowChanges(commit) => {
+                            TabInput::Open(TabTarget::Diff(DiffSource::Commit(commit)))
+                        }
+                        FirehoseOutput::Resolve(handle) => TabInput::ResolveHandle(handle),
+                    },
+                ))
+            }
+            _ => TabContent::Loading(Self::loading_page(&target)),
+        };
+        Self {
+            index: index.clone(),
+            content,
+            target,
+            snapshots: vec![],
+        }
+    }
+
+    fn init_widgets(
+        &mut self,
+        _index: &DynamicIndex,
+        root: Self::Root,
+        returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
+        sender: AsyncFactorySender<Self>,
+    ) -> Self::Widgets {
+        let widgets = view_output!();
+        widgets.content.set_child(Some(&self.content.widget()));
+        widgets.slider.connect_value_changed(move |_| {
+            sender.input(TabInput::TimeTravel);
+        });
+        widgets
+    }
+
+    async fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: AsyncFactorySender<Self>,
+    ) {
+        let content = match message {
+            TabInput::Stage(stage) => {
+                if let TabContent::Loading(page) = &self.content {
+                    page.set_description(Some(&stage.to_string()));
+                }
+                None
+            }
+            TabInput::Cached(cached_at) => {
+                widgets.banner.set_title(&format!(
+                    "Showing a copy cached at {}",
+                    format_cached_at(cached_at)
+                ));
+                widgets.banner.set_revealed(true);
+                None
+            }
+            TabInput::Local(path) => {
+                widgets.banner.set_title(&match &self.target {
+                    TabTarget::AsOf(_, snapshot) => format!(
+                        "Showing the repository as it was on {}, at rev {}",
+                        snapshot.date(),
+                        snapshot.rev
+                    ),
+                    /* snapshots are named after their revision */
+                    TabTarget::Version(..) => format!(
+                        "Showing this version from the snapshot at rev {}",
+                        path.file_stem().unwrap_or_default().to_string_lossy()
+                    ),
+                    _ => format!("Reading offline from {}", path.display()),
+                });
+                widgets.banner.set_revealed(true);
+                None
+            }
+            TabInput::Snapshots(snapshots) => {
+                self.snapshots = snapshots;
+                let now = self.snapshots.len() as f64;
+                widgets.slider.clear_marks();
+                for position in 0..self.snapshots.len() {
+                    widgets
+                        .slider
+                        .add_mark(position as f64, gtk::PositionType::Bottom, None);
+                }
+                widgets
+                    .slider
+                    .add_mark(now, gtk::PositionType::Bottom, Some("Now"));
+                widgets.slider.set_range(0.0, now);
+                widgets.slider.set_value(self.position() as f64);
+                widgets.moment.set_label(&self.moment());
+                widgets.timeline.set_visible(self.uri().is_some());
+                None
+            }
+            /* the slider can't tell a drag from being set above, so only an actual move looks anything up */
+            TabInput::TimeTravel => {
+                let position = widgets.slider.value().round() as usize;
+                match self.uri().cloned() {
+                    Some(uri) if position != self.position() => {
+                        self.target = match self.snapshots.get(position) {
+                            Some(snapshot) => TabTarget::AsOf(uri, snapshot.clone()),
+                            None => TabTarget::Uri(uri),
+                        };
+                        widgets.moment.set_label(&self.moment());
+                        sender.output(TabOutput::Retry(self.index.clone(), self.target.clone()));
+                        Some(TabContent::Loading(Self::loading_page(&self.target)))
+                    }
+                    _ => None,
+                }
+            }
+            TabInput::Record(record) if record.uri.parse::<AtUri>().is_err() => {
+                Some(TabContent::Failed(Self::failure_page(
+                    &AgentError::InvalidRecord(format!("{} isn't a valid at:// URI", record.uri)),
+                    &sender,
+                )))
+            }
+            TabInput::Record(record) if !matches!(record.value, Unknown::Object(_)) => {
+                Some(TabContent::Failed(Self::failure_page(
+                    &AgentError::InvalidRecord(format!("{} isn't an object", record.uri)),
+                    &sender,
+                )))
+            }
+            TabInput::Record(record) => Some(TabContent::Record(
+                GetRecordView::builder()
+                    .launch(record)
+                    .forward(sender.input_sender(), |output| match output {
+                        GetRecordOutput::Prove(uri) => TabInput::ProveRecord(uri),
+                        GetRecordOutput::FetchBlob(repo, blob) => TabInput::FetchBlob(repo, blob),
+                        GetRecordOutput::Open(uri) => TabInput::Open(TabTarget::Uri(uri)),
+                        GetRecordOutput::OpenVersion(uri, cid) => {
+                            TabInput::Open(TabTarget::Version(uri, cid))
+                        }
+                    }),
+            )),
+            TabInput::Records(records) => Some(TabContent::Records(
+                ListRecordsView::builder().launch(records).forward(
+                    sender.input_sender(),
+                    |output| match output {
+                        ListRecordsOutput::Open(uri) => TabInput::Open(TabTarget::Uri(uri)),
+                    },
+                ),
+            )),
+            TabInput::Repo(repo) => Some(TabContent::Repo(
+                DescribeRepoView::builder()
+                    .launch(repo)
+                    .forward(sender.input_sender(), |output| match output {
+                        DescribeRepoOutput::Download(did, path) => {
+                            TabInput::DownloadRepo(did, path)
+                        }
+                        DescribeRepoOutput::Refresh(did, path) => TabInput::RefreshRepo(did, path),
+                        DescribeRepoOutput::Open(uri) => TabInput::Open(TabTarget::Uri(uri)),
+                        DescribeRepoOutput::OpenServer(url) => {
+                            TabInput::Open(TabTarget::Server(url))
+                        }
+                        DescribeRepoOutput::ExploreMst(did) => TabInput::Open(TabTarget::Mst(did)),
+                        DescribeRepoOutput::CheckCommit(did) => TabInput::CheckCommit(did),
+                        DescribeRepoOutput::ListBlobs(did, cursor) => {
+                            TabInput::ListBlobs(did, cursor)
+                        }
+                        DescribeRepoOutput::FetchBlob(repo, blob) => {
+                            TabInput::FetchBlob(repo, blob)
+                        }
+                    }),
+            )),
+            TabInput::Mst(mst) => Some(TabContent::Mst(MstView::builder().launch(mst).forward(
+                sender.input_sender(),
+                |output| match output {
+                    MstOutput::Open(uri) => TabInput::Open(TabTarget::Uri(uri)),
+                },
+            ))),
+            TabInput::Diff(diff) => Some(TabContent::Diff(
+                DiffView::builder()
+                    .launch(diff)
+                    .forward(sender.input_sender(), |output| match output {
+                        DiffOutput::Open(uri) => TabInput::Open(TabTarget::Uri(uri)),
+                    }),
+            )),
+            TabInput::Server(overview) => Some(TabContent::Server(
+                ServerView::builder().launch(overview).detach(),
+            )),
+            TabInput::Failed(error) => {
+                Some(TabContent::Failed(Self::failure_page(&error, &sender)))
+            }
+            TabInput::Retry => {
+                sender.output(TabOutput::Retry(self.index.clone(), self.target.clone()));
+                Some(TabContent::Loading(Self::loading_page(&self.target)))
+            }
+            TabInput::Open(target) => {
+                sender.output(TabOutput::Open(self.scoped(target)));
+                None
+            }
+            TabInput::DownloadRepo(did, path) => {
+                sender.output(TabOutput::DownloadRepo(self.index.clone(), did, path));
+                None
+            }
+            TabInput::RefreshRepo(did, path) => {
+                sender.output(TabOutput::RefreshRepo(self.index.clone(), did, path));
+                None
+            }
+            TabInput::Downloaded(download) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::Downloaded(download));
+                }
+                None
+            }
+            TabInput::Refreshed(download, changes) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::Refreshed(download, changes));
+                }
+                None
+            }
+            TabInput::PlcData(data) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::PlcData(data));
+                }
+                None
+            }
+            TabInput::PlcLog(log) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::PlcLog(log));
+                }
+                None
+            }
+            TabInput::PlcLogChecked(check) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::PlcLogChecked(check));
+                }
+                None
+            }
+            TabInput::CheckCommit(did) => {
+                sender.output(TabOutput::CheckCommit(
+                    self.index.clone(),
+                    did,
+                    self.file().map(|(path, _)| path.clone()),
+                ));
+                None
+            }
+            TabInput::CommitChecked(check) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::CommitChecked(check));
+                }
+                None
+            }
+            TabInput::CommitCheckFailed(error) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::CommitCheckFailed(error));
+                }
+                None
+            }
+            TabInput::ProveRecord(uri) => {
+                sender.output(TabOutput::ProveRecord(
+                    self.index.clone(),
+                    uri,
+                    self.file().map(|(path, _)| path.clone()),
+                ));
+                None
+            }
+            TabInput::RecordProved(proof) => {
+                if let TabContent::Record(view) = &self.content {
+                    view.emit(GetRecordInput::Proved(proof));
+                }
+                None
+            }
+            TabInput::ProofFailed(error) => {
+                if let TabContent::Record(view) = &self.content {
+                    view.emit(GetRecordInput::ProofFailed(error));
+                }
+                None
+            }
+            TabInput::ListBlobs(did, cursor) => {
+                sender.output(TabOutput::ListBlobs(self.index.clone(), did, cursor));
+                None
+            }
+            TabInput::Blobs(cids, cursor) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::Blobs(cids, cursor));
+                }
+                None
+            }
+            TabInput::BlobsFailed(error) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::BlobsFailed(error));
+                }
+                None
+            }
+            TabInput::FetchBlob(repo, blob) => {
+                sender.output(TabOutput::FetchBlob(self.index.clone(), repo, blob));
+                None
+            }
+            TabInput::Blob(blob) => {
+                match &self.content {
+                    TabContent::Record(view) => view.emit(GetRecordInput::Blob(blob)),
+                    TabContent::Repo(view) => view.emit(DescribeRepoInput::Blob(blob)),
+                    _ => (),
+                }
+                None
+            }
+            TabInput::BlobFailed(cid, error) => {
+                match &self.content {
+                    TabContent::Record(view) => view.emit(GetRecordInput::BlobFailed(cid, error)),
+                    TabContent::Repo(view) => view.emit(DescribeRepoInput::BlobFailed(cid, error)),
+                    _ => (),
+                }
+                None
+            }
+            TabInput::ResolveHandle(handle) => {
+                sender.output(TabOutput::ResolveHandle(self.index.clone(), handle));
+                None
+            }
+            TabInput::Resolved(handle, did) => {
+                if let TabContent::Firehose(view) = &self.content {
+                    view.emit(FirehoseInput::Resolved(
+                        handle.as_str().to_string(),
+                        Ok(did.as_str().to_string()),
+                    ));
+                }
+                None
+            }
+            TabInput::ResolveFailed(handle, error) => {
+                if let TabContent::Firehose(view) = &self.content {
+                    view.emit(FirehoseInput::Resolved(
+                        handle.as_str().to_string(),
+                        Err(error.to_string()),
+                    ));
+                }
+                None
+            }
+            TabInput::CidChecked(check) => {
+                if let TabContent::Record(view) = &self.content {
+                    view.emit(GetRecordInput::CidChecked(check));
+                }
+                None
+            }
+            TabInput::DownloadFailed(error) => {
+                if let TabContent::Repo(view) = &self.content {
+                    view.emit(DescribeRepoInput::DownloadFailed(error));
+                }
+                None
+            }
+        };
+        if let Some(content) = content {
+            self.content = content;
+            widgets.banner.set_revealed(false);
+            widgets.content.set_child(Some(&self.content.widget()));
+        }
+        self.update_view(widgets, sender);
+    }
+}
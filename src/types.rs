@@ -1,15 +1,30 @@
 use atrium_api::types::string::{AtIdentifier, Nsid, RecordKey};
+use ipld_core::cid::Cid;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AtUriError {
     MissingAuthority,
     InvalidAuthority,
     InvalidNsid,
     InvalidRecordKey,
+    InvalidCid,
 }
 
-#[derive(Debug)]
+impl std::fmt::Display for AtUriError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            AtUriError::MissingAuthority => "Enter a handle, DID, or at:// URI",
+            AtUriError::InvalidAuthority => "That doesn't look like a valid handle or DID",
+            AtUriError::InvalidNsid => "The collection in that URI isn't a valid NSID",
+            AtUriError::InvalidRecordKey => "The record key in that URI isn't valid",
+            AtUriError::InvalidCid => "The cid after that URI isn't a valid CID",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct AtUri {
     pub authority: AtIdentifier,
     pub collection: Option<Nsid>,
@@ -75,3 +90,143 @@ impl std::fmt::Display for AtUri {
         write!(f, "at://{}", uri)
     }
 }
+
+// a URI as typed, optionally followed by `?cid=` to ask for one version of the record
+pub fn parse_versioned(s: &str) -> Result<(AtUri, Option<Cid>), AtUriError> {
+    match s.split_once("?cid=") {
+        Some((uri, cid)) => Ok((
+            uri.parse()?,
+            Some(cid.trim().parse().map_err(|_| AtUriError::InvalidCid)?),
+        )),
+        None => Ok((s.parse()?, None)),
+    }
+}
+
+/*
+a com.atproto.repo.strongRef, {"uri": …, "cid": …}, which points at one version of a record rather
+than whatever it is now. it's how replies, likes, reposts and quotes name the post they're about
+*/
+#[derive(Debug, Clone)]
+pub struct StrongRef {
+    // where in the record it was found, as in "reply.parent"
+    pub path: String,
+    pub uri: AtUri,
+    pub cid: Cid,
+}
+
+// every strongRef in a record, in the order they appear
+pub fn strong_refs(value: &serde_json::Value) -> Vec<StrongRef> {
+    let mut refs = vec![];
+    collect_strong_refs(value, String::new(), &mut refs);
+    refs
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn collect_strong_refs(value: &serde_json::Value, path: String, refs: &mut Vec<StrongRef>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let uri = map
+                .get("uri")
+                .and_then(|uri| uri.as_str())
+                .filter(|uri| uri.starts_with("at://"));
+            let cid = map.get("cid").and_then(|cid| cid.as_str());
+            if let (Some(Ok(uri)), Some(Ok(cid))) = (
+                uri.map(|uri| uri.parse::<AtUri>()),
+                cid.map(|cid| cid.parse::<Cid>()),
+            ) {
+                refs.push(StrongRef { path, uri, cid });
+                return;
+            }
+            for (key, value) in map {
+                collect_strong_refs(value, join(&path, key), refs);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                collect_strong_refs(value, join(&path, &index.to_string()), refs);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::cid_for;
+    use serde_json::json;
+
+    const POST: &str = "at://did:plc:alice/app.bsky.feed.post/3l3qo2vutsw2b";
+
+    #[test]
+    fn versions_are_asked_for_after_the_uri() {
+        let cid = cid_for(b"post");
+        let (uri, version) = parse_versioned(&format!("{}?cid={}", POST, cid)).unwrap();
+        assert_eq!((uri.to_string(), version), (POST.to_string(), Some(cid)));
+
+        let (uri, version) = parse_versioned(&format!("{}?cid= {} ", POST, cid)).unwrap();
+        assert_eq!((uri.to_string(), version), (POST.to_string(), Some(cid)));
+
+        let (uri, version) = parse_versioned(POST).unwrap();
+        assert_eq!((uri.to_string(), version), (POST.to_string(), None));
+    }
+
+    #[test]
+    fn versions_have_to_be_cids_of_valid_uris() {
+        for versioned in [
+            format!("{}?cid=", POST),
+            format!("{}?cid=not-a-cid", POST),
+            format!("{}?cid={}", POST, POST),
+        ] {
+            assert!(matches!(
+                parse_versioned(&versioned),
+                Err(AtUriError::InvalidCid)
+            ));
+        }
+        assert!(matches!(
+            parse_versioned(&format!(
+                "at://did:plc:alice/not a collection?cid={}",
+                cid_for(b"post")
+            )),
+            Err(AtUriError::InvalidNsid)
+        ));
+    }
+
+    #[test]
+    fn strong_refs_need_an_at_uri_and_a_valid_cid() {
+        let (root, parent) = (cid_for(b"root"), cid_for(b"parent"));
+        let record = json!({
+            "text": "hello",
+            "reply": {
+                "root": { "uri": POST, "cid": root.to_string() },
+                "parent": { "uri": POST, "cid": parent.to_string() },
+            },
+            "embed": {
+                "record": { "uri": POST, "cid": "not-a-cid" },
+            },
+            "facets": [{
+                "features": [{ "uri": "https://example.com", "cid": root.to_string() }],
+            }],
+            "subject": { "uri": POST },
+        });
+        let mut refs: Vec<(String, String, Cid)> = strong_refs(&record)
+            .into_iter()
+            .map(|strong_ref| (strong_ref.path, strong_ref.uri.to_string(), strong_ref.cid))
+            .collect();
+        refs.sort();
+        assert_eq!(
+            refs,
+            vec![
+                (String::from("reply.parent"), POST.to_string(), parent),
+                (String::from("reply.root"), POST.to_string(), root),
+            ]
+        );
+    }
+}
@@ -0,0 +1,949 @@
+use crate::blob::BlobRef;
+use crate::car::{Car, Commit};
+use crate::keys::PublicKey;
+use crate::plc::{PlcData, PlcLogEntry, PlcOperation};
+use atrium_api::did_doc::DidDocument;
+use atrium_api::types::Unknown;
+use base64::Engine;
+use ipld_core::cid::multihash::Multihash;
+use ipld_core::cid::Cid;
+use ipld_core::ipld::Ipld;
+use relm4::gtk::gio;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+/*
+checks that content really is what its CID says it is. atproto records are addressed by a CIDv1 of
+the sha256 of their DAG-CBOR encoding, so a record that came as a raw block (from a CAR) is hashed
+as-is, and one that came as JSON (from getRecord or the cache) is converted back to the atproto data
+model and re-encoded first. in JSON, links are written as {"$link": cid} and bytes as {"$bytes": base64}
+*/
+
+const DAG_CBOR: u64 = 0x71;
+const SHA2_256: u64 = 0x12;
+
+#[derive(Debug, Clone)]
+pub enum CidCheck {
+    Verified,
+    // the CID the content actually hashes to
+    Mismatch(Cid),
+    Unverifiable(String),
+}
+
+impl CidCheck {
+    pub fn badge(&self) -> &'static str {
+        match self {
+            CidCheck::Verified => "Verified",
+            CidCheck::Mismatch(_) => "Mismatch",
+            CidCheck::Unverifiable(_) => "Unverified",
+        }
+    }
+
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            CidCheck::Verified => "success",
+            CidCheck::Mismatch(_) => "error",
+            CidCheck::Unverifiable(_) => "warning",
+        }
+    }
+}
+
+impl std::fmt::Display for CidCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CidCheck::Verified => write!(f, "The content hashes to this CID"),
+            CidCheck::Mismatch(actual) => {
+                write!(
+                    f,
+                    "The content doesn't match this CID, it hashes to {}",
+                    actual
+                )
+            }
+            CidCheck::Unverifiable(reason) => write!(f, "Couldn't check this CID: {}", reason),
+        }
+    }
+}
+
+pub fn cid_for(bytes: &[u8]) -> Cid {
+    let digest = Sha256::digest(bytes);
+    Cid::new_v1(
+        DAG_CBOR,
+        Multihash::wrap(SHA2_256, &digest).expect("a sha256 digest fits in a multihash"),
+    )
+}
+
+pub fn check_block(claimed: &Cid, bytes: &[u8]) -> CidCheck {
+    if claimed.hash().code() != SHA2_256 {
+        return CidCheck::Unverifiable(format!(
+            "unsupported hash function 0x{:x}",
+            claimed.hash().code()
+        ));
+    }
+    /* the codec isn't part of the hash, so a raw (0x55) CID for the same bytes still verifies */
+    let actual = cid_for(bytes);
+    if actual.hash() == claimed.hash() {
+        CidCheck::Verified
+    } else {
+        CidCheck::Mismatch(Cid::new_v1(claimed.codec(), *actual.hash()))
+    }
+}
+
+pub fn check_record(claimed: Option<&atrium_api::types::string::Cid>, value: &Unknown) -> CidCheck {
+    let Some(claimed) = claimed else {
+        return CidCheck::Unverifiable(String::from("the PDS didn't say which CID this is"));
+    };
+    match encode_record(value) {
+        Ok(bytes) => check_block(claimed.as_ref(), &bytes),
+        Err(err) => CidCheck::Unverifiable(err),
+    }
+}
+
+/*
+a blob ref promises three things about the bytes it points at: their hash (blob CIDs use the raw
+codec, but the hash is all that's compared), their MIME type and their size. the type is only checked
+when the bytes are recognisable enough for GIO to be sure what they are
+*/
+#[derive(Debug, Clone)]
+pub struct BlobCheck {
+    pub cid: CidCheck,
+    pub mime_type: Option<String>,
+    pub problems: Vec<String>,
+}
+
+impl BlobCheck {
+    pub fn badge(&self) -> &'static str {
+        match (&self.cid, self.problems.is_empty()) {
+            (CidCheck::Verified, true) => "Verified",
+            (CidCheck::Unverifiable(_), true) => "Unverified",
+            _ => "Mismatch",
+        }
+    }
+
+    pub fn css_class(&self) -> &'static str {
+        match (&self.cid, self.problems.is_empty()) {
+            (CidCheck::Verified, true) => "success",
+            (CidCheck::Unverifiable(_), true) => "warning",
+            _ => "error",
+        }
+    }
+}
+
+impl std::fmt::Display for BlobCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.cid)?;
+        for problem in &self.problems {
+            write!(f, "\n{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn check_blob(reference: &BlobRef, bytes: &[u8]) -> BlobCheck {
+    let mut problems = vec![];
+    if let Some(size) = reference.size {
+        if size != bytes.len() as u64 {
+            problems.push(format!(
+                "The record says the blob is {} bytes, but {} were downloaded",
+                size,
+                bytes.len()
+            ));
+        }
+    }
+    let (content_type, uncertain) = gio::content_type_guess(None::<&str>, bytes);
+    let mime_type = gio::content_type_get_mime_type(&content_type)
+        .filter(|_| !uncertain)
+        .map(|mime_type| mime_type.to_string());
+    if let (Some(declared), Some(actual)) = (&reference.mime_type, &mime_type) {
+        if declared != actual && actual != "application/octet-stream" {
+            problems.push(format!(
+                "The record says the blob is {}, but it looks like {}",
+                declared, actual
+            ));
+        }
+    }
+    BlobCheck {
+        cid: check_block(&reference.cid, bytes),
+        mime_type,
+        problems,
+    }
+}
+
+pub fn encode_record(value: &Unknown) -> Result<Vec<u8>, String> {
+    let json = serde_json::to_value(value).map_err(|err| err.to_string())?;
+    serde_ipld_dagcbor::to_vec(&ipld_from_json(json)?).map_err(|err| err.to_string())
+}
+
+pub fn ipld_from_json(value: serde_json::Value) -> Result<Ipld, String> {
+    Ok(match value {
+        serde_json::Value::Null => Ipld::Null,
+        serde_json::Value::Bool(b) => Ipld::Bool(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => Ipld::Integer(n as i128),
+            None => return Err(format!("{} isn't an integer, and atproto has no floats", n)),
+        },
+        serde_json::Value::String(s) => Ipld::String(s),
+        serde_json::Value::Array(values) => Ipld::List(
+            values
+                .into_iter()
+                .map(ipld_from_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        serde_json::Value::Object(map) => {
+            if map.len() == 1 {
+                if let Some(serde_json::Value::String(link)) = map.get("$link") {
+                    return link
+                        .parse::<Cid>()
+                        .map(Ipld::Link)
+                        .map_err(|err| format!("invalid $link {}: {}", link, err));
+                }
+                if let Some(serde_json::Value::String(bytes)) = map.get("$bytes") {
+                    return base64::engine::general_purpose::STANDARD_NO_PAD
+                        .decode(bytes.trim_end_matches('='))
+                        .map(Ipld::Bytes)
+                        .map_err(|err| format!("invalid $bytes: {}", err));
+                }
+            }
+            Ipld::Map(
+                map.into_iter()
+                    .map(|(key, value)| Ok((key, ipld_from_json(value)?)))
+                    .collect::<Result<BTreeMap<_, _>, String>>()?,
+            )
+        }
+    })
+}
+
+/*
+a commit is signed over the DAG-CBOR encoding of itself without the `sig` field. DAG-CBOR wants map
+keys shortest first, which is why the fields are in this order
+*/
+#[derive(Serialize)]
+struct UnsignedCommit<'a> {
+    did: &'a str,
+    rev: &'a str,
+    data: &'a Cid,
+    prev: &'a Option<Cid>,
+    version: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum SignatureCheck {
+    Valid(&'static str),
+    Invalid(String),
+    Unverifiable(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitCheck {
+    pub commit: Cid,
+    pub rev: String,
+    pub signature: SignatureCheck,
+}
+
+impl CommitCheck {
+    pub fn badge(&self) -> &'static str {
+        match self.signature {
+            SignatureCheck::Valid(_) => "Valid",
+            SignatureCheck::Invalid(_) => "Invalid",
+            SignatureCheck::Unverifiable(_) => "Unverified",
+        }
+    }
+
+    pub fn css_class(&self) -> &'static str {
+        match self.signature {
+            SignatureCheck::Valid(_) => "success",
+            SignatureCheck::Invalid(_) => "error",
+            SignatureCheck::Unverifiable(_) => "warning",
+        }
+    }
+}
+
+impl std::fmt::Display for CommitCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.signature {
+            SignatureCheck::Valid(curve) => write!(
+                f,
+                "Commit {} (rev {}) is signed by the DID's #atproto {} key",
+                self.commit, self.rev, curve
+            ),
+            SignatureCheck::Invalid(reason) => write!(
+                f,
+                "Commit {} (rev {}) has a bad signature: {}",
+                self.commit, self.rev, reason
+            ),
+            SignatureCheck::Unverifiable(reason) => write!(
+                f,
+                "Couldn't check commit {} (rev {}): {}",
+                self.commit, self.rev, reason
+            ),
+        }
+    }
+}
+
+pub fn check_commit(cid: Cid, commit: &Commit, did_doc: &DidDocument) -> CommitCheck {
+    let signature = if commit.did != did_doc.id {
+        SignatureCheck::Invalid(format!(
+            "the commit is for {}, not {}",
+            commit.did, did_doc.id
+        ))
+    } else {
+        match PublicKey::atproto_signing_key(did_doc) {
+            Err(err) => SignatureCheck::Unverifiable(err),
+            Ok(key) => {
+                let unsigned = UnsignedCommit {
+                    did: &commit.did,
+                    rev: &commit.rev,
+                    data: &commit.data,
+                    prev: &commit.prev,
+                    version: commit.version,
+                };
+                match serde_ipld_dagcbor::to_vec(&unsigned) {
+                    Err(err) => SignatureCheck::Unverifiable(err.to_string()),
+                    Ok(bytes) => match key.verify(&bytes, &commit.sig) {
+                        Ok(()) => SignatureCheck::Valid(key.curve()),
+                        Err(err) => SignatureCheck::Invalid(err),
+                    },
+                }
+            }
+        }
+    };
+    CommitCheck {
+        commit: cid,
+        rev: commit.rev.clone(),
+        signature,
+    }
+}
+
+/*
+what a sync.getRecord proof shows: the signed commit, then every tree node from the commit's root down
+to the record, each of which has to hash to the CID its parent gave for it
+*/
+#[derive(Debug, Clone)]
+pub enum InclusionCheck {
+    Proven {
+        rev: String,
+        record: Cid,
+        depth: usize,
+    },
+    Absent {
+        rev: String,
+    },
+    Failed(String),
+}
+
+impl InclusionCheck {
+    pub fn badge(&self) -> &'static str {
+        match self {
+            InclusionCheck::Proven { .. } => "Proven",
+            InclusionCheck::Absent { .. } => "Absent",
+            InclusionCheck::Failed(_) => "Unproven",
+        }
+    }
+
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            InclusionCheck::Proven { .. } => "success",
+            InclusionCheck::Absent { .. } | InclusionCheck::Failed(_) => "error",
+        }
+    }
+}
+
+impl std::fmt::Display for InclusionCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InclusionCheck::Proven { rev, record, depth } => write!(
+                f,
+                "Proven present in repo rev {} as {}, {} tree nodes below the signed commit",
+                rev, record, depth
+            ),
+            InclusionCheck::Absent { rev } => {
+                write!(
+                    f,
+                    "The repository at rev {} proves this record isn't in it",
+                    rev
+                )
+            }
+            InclusionCheck::Failed(reason) => write!(f, "The proof doesn't hold: {}", reason),
+        }
+    }
+}
+
+pub fn check_inclusion(car: &Car, did_doc: &DidDocument, key: &str) -> InclusionCheck {
+    let failed = |reason: String| InclusionCheck::Failed(reason);
+    let (root, commit) = match car.root().and_then(|root| Ok((*root, car.commit()?))) {
+        Ok(commit) => commit,
+        Err(err) => return failed(err.to_string()),
+    };
+    let check = check_commit(root, &commit, did_doc);
+    if !matches!(check.signature, SignatureCheck::Valid(_)) {
+        return failed(check.to_string());
+    }
+    let (record, path) = match car.find(key) {
+        Ok(found) => found,
+        Err(err) => return failed(err.to_string()),
+    };
+    for cid in std::iter::once(&root).chain(&path).chain(&record) {
+        let block = match car.block(cid) {
+            Ok(block) => block,
+            Err(err) => return failed(err.to_string()),
+        };
+        if let CidCheck::Mismatch(_) | CidCheck::Unverifiable(_) = check_block(cid, block) {
+            return failed(format!("block {} doesn't match its CID", cid));
+        }
+    }
+    match record {
+        Some(record) => InclusionCheck::Proven {
+            rev: commit.rev,
+            record,
+            depth: path.len(),
+        },
+        None => InclusionCheck::Absent { rev: commit.rev },
+    }
+}
+
+/*
+replays a did:plc's audit log rather than trusting the state plc.directory resolved from it. every
+operation has to hash to its CID, be signed by one of the rotation keys in effect at the operation it
+names in `prev`, and the operations that weren't nullified have to form a single chain back to the
+genesis operation, whose hash the DID itself is derived from. whatever state the chain ends in is then
+compared with the DID document
+*/
+#[derive(Debug, Clone)]
+pub struct PlcLogCheck {
+    pub operations: usize,
+    pub problems: Vec<String>,
+}
+
+impl PlcLogCheck {
+    pub fn badge(&self) -> &'static str {
+        if self.problems.is_empty() {
+            "Valid"
+        } else {
+            "Invalid"
+        }
+    }
+
+    pub fn css_class(&self) -> &'static str {
+        if self.problems.is_empty() {
+            "success"
+        } else {
+            "error"
+        }
+    }
+}
+
+impl std::fmt::Display for PlcLogCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.problems.is_empty() {
+            write!(
+                f,
+                "All {} operations are signed by a rotation key in effect and chain back to the DID, \
+                 and the DID document agrees with where they end up",
+                self.operations
+            )
+        } else {
+            write!(f, "{}", self.problems.join("\n"))
+        }
+    }
+}
+
+// the DAG-CBOR encoding of an operation, with or without its signature
+fn encode_operation(operation: &PlcOperation, signed: bool) -> Result<Vec<u8>, String> {
+    let json = serde_json::to_value(operation).map_err(|err| err.to_string())?;
+    let mut ipld = ipld_from_json(json)?;
+    if let (Ipld::Map(map), false) = (&mut ipld, signed) {
+        map.remove("sig");
+    }
+    serde_ipld_dagcbor::to_vec(&ipld).map_err(|err| err.to_string())
+}
+
+fn operation_sig(operation: &PlcOperation) -> &str {
+    match operation {
+        PlcOperation::Operation { sig, .. }
+        | PlcOperation::Tombstone { sig, .. }
+        | PlcOperation::LegacyCreate { sig, .. } => sig,
+    }
+}
+
+fn check_operation_sig(operation: &PlcOperation, rotation_keys: &[String]) -> Result<(), String> {
+    let unsigned = encode_operation(operation, false)?;
+    let sig = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(operation_sig(operation).trim_end_matches('='))
+        .map_err(|err| format!("the signature isn't valid base64: {}", err))?;
+    let signed_by_any = rotation_keys.iter().any(|did_key| {
+        PublicKey::from_did_key(did_key)
+            .and_then(|key| key.verify(&unsigned, &sig))
+            .is_ok()
+    });
+    if signed_by_any {
+        Ok(())
+    } else {
+        Err(String::from(
+            "isn't signed by any of the rotation keys in effect",
+        ))
+    }
+}
+
+// did:plc: and the first 24 characters of the base32 sha256 of the signed genesis operation
+fn plc_did_for(genesis: &[u8]) -> String {
+    let digest = Sha256::digest(genesis);
+    let encoded = multibase::Base::Base32Lower.encode(digest);
+    format!("did:plc:{}", &encoded[..24])
+}
+
+pub fn check_plc_log(did: &str, log: &[PlcLogEntry], did_doc: &DidDocument) -> PlcLogCheck {
+    let mut problems = vec![];
+    // the state each operation left behind, by CID
+    let mut states: HashMap<&str, Option<PlcData>> = HashMap::new();
+    // the last operation that wasn't nullified
+    let mut head: Option<&str> = None;
+
+    for (index, entry) in log.iter().enumerate() {
+        let cid = entry.cid.as_str();
+        let mut problem = |reason: String| problems.push(format!("Operation {}: {}", cid, reason));
+        if entry.did != did {
+            problem(format!("is for {}", entry.did));
+        }
+        match encode_operation(&entry.operation, true) {
+            Err(err) => problem(format!("couldn't be encoded: {}", err)),
+            Ok(signed) => {
+                let actual = cid_for(&signed).to_string();
+                if actual != cid {
+                    problem(format!("hashes to {}", actual));
+                }
+                let derived = plc_did_for(&signed);
+                if entry.operation.prev().is_none() && index == 0 && derived != did {
+                    problem(format!(
+                        "is the genesis operation of {}, not {}",
+                        derived, did
+                    ));
+                }
+            }
+        }
+
+        let rotation_keys = match entry.operation.prev() {
+            None if index == 0 => entry
+                .operation
+                .data(did)
+                .map(|data| data.rotation_keys)
+                .unwrap_or_default(),
+            None => {
+                problem(String::from("claims to be a second genesis operation"));
+                vec![]
+            }
+            Some(prev) => match states.get(prev) {
+                Some(Some(state)) => state.rotation_keys.clone(),
+                Some(None) => {
+                    problem(String::from("follows a tombstone"));
+                    vec![]
+                }
+                None => {
+                    problem(format!("follows {}, which doesn't come before it", prev));
+                    vec![]
+                }
+            },
+        };
+        if let Err(err) = check_operation_sig(&entry.operation, &rotation_keys) {
+            problem(err);
+        }
+
+        if !entry.nullified {
+            if entry.operation.prev() != head {
+                problem(format!(
+                    "follows {} instead of the operation in effect before it, {}",
+                    entry.operation.prev().unwrap_or("nothing"),
+                    head.unwrap_or("nothing")
+                ));
+            }
+            head = Some(cid);
+        }
+        states.insert(cid, entry.operation.data(did));
+    }
+
+    match head.and_then(|head| states.get(head)) {
+        None => problems.push(String::from("The log has no operations in effect")),
+        Some(None) => problems.push(String::from(
+            "The identity was tombstoned, but plc.directory still resolves a DID document for it",
+        )),
+        Some(Some(state)) => problems.extend(compare_did_doc(state, did_doc)),
+    }
+    PlcLogCheck {
+        operations: log.len(),
+        problems,
+    }
+}
+
+fn compare_did_doc(state: &PlcData, did_doc: &DidDocument) -> Vec<String> {
+    let mut problems = vec![];
+    if did_doc.id != state.did {
+        problems.push(format!("The DID document is for {}", did_doc.id));
+    }
+    let also_known_as = did_doc.also_known_as.clone().unwrap_or_default();
+    if also_known_as != state.also_known_as {
+        problems.push(format!(
+            "The DID document says the identity is also known as {}, the log says {}",
+            also_known_as.join(", "),
+            state.also_known_as.join(", ")
+        ));
+    }
+    /* keys are compared decoded, since the document may write them in the legacy form */
+    let document_key = PublicKey::atproto_signing_key(did_doc).map(|key| key.did_key());
+    let log_key = state
+        .verification_methods
+        .get("atproto")
+        .ok_or_else(|| String::from("no #atproto key"))
+        .and_then(|did_key| PublicKey::from_did_key(did_key))
+        .map(|key| key.did_key());
+    if document_key != log_key {
+        problems.push(format!(
+            "The DID document's #atproto key is {}, the log says {}",
+            document_key.unwrap_or_else(|err| err),
+            log_key.unwrap_or_else(|err| err)
+        ));
+    }
+    let document_pds = did_doc.get_pds_endpoint();
+    let log_pds = state
+        .services
+        .get("atproto_pds")
+        .map(|service| service.endpoint.clone());
+    if document_pds != log_pds {
+        problems.push(format!(
+            "The DID document's PDS is {}, the log says {}",
+            document_pds.as_deref().unwrap_or("(none)"),
+            log_pds.as_deref().unwrap_or("(none)")
+        ));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, did_doc, public, sign, signed_commit, Repo};
+    use serde_json::json;
+
+    fn post(text: &str) -> Unknown {
+        serde_json::from_value(json!({
+            "$type": "app.bsky.feed.post",
+            "text": text,
+            "createdAt": "2024-01-01T00:00:00.000Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn block_matches_its_cid() {
+        let block = serde_ipld_dagcbor::to_vec(&"hello").unwrap();
+        assert!(matches!(
+            check_block(&cid_for(&block), &block),
+            CidCheck::Verified
+        ));
+    }
+
+    #[test]
+    fn tampered_block_reports_what_it_hashes_to() {
+        let block = serde_ipld_dagcbor::to_vec(&"hello").unwrap();
+        let tampered = serde_ipld_dagcbor::to_vec(&"hellO").unwrap();
+        match check_block(&cid_for(&block), &tampered) {
+            CidCheck::Mismatch(actual) => assert_eq!(actual, cid_for(&tampered)),
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn raw_cid_for_the_same_bytes_verifies() {
+        let block = b"not DAG-CBOR at all";
+        let raw = Cid::new_v1(0x55, *cid_for(block).hash());
+        assert!(matches!(check_block(&raw, block), CidCheck::Verified));
+    }
+
+    #[test]
+    fn unknown_hash_function_is_unverifiable() {
+        let sha512 = Cid::new_v1(DAG_CBOR, Multihash::wrap(0x13, &[0; 64]).unwrap());
+        assert!(matches!(
+            check_block(&sha512, b"anything"),
+            CidCheck::Unverifiable(_)
+        ));
+    }
+
+    #[test]
+    fn record_from_json_rehashes_to_its_cid() {
+        let cid = cid_for(&encode_record(&post("hello")).unwrap());
+        let claimed = atrium_api::types::string::Cid::new(cid);
+        assert!(matches!(
+            check_record(Some(&claimed), &post("hello")),
+            CidCheck::Verified
+        ));
+        assert!(matches!(
+            check_record(Some(&claimed), &post("goodbye")),
+            CidCheck::Mismatch(_)
+        ));
+        assert!(matches!(
+            check_record(None, &post("hello")),
+            CidCheck::Unverifiable(_)
+        ));
+    }
+
+    #[test]
+    fn links_and_bytes_come_back_from_json() {
+        let link = cid_for(b"linked");
+        let ipld = ipld_from_json(json!({
+            "link": { "$link": link.to_string() },
+            "bytes": { "$bytes": "aGk" },
+            "count": 3,
+        }))
+        .unwrap();
+        assert_eq!(
+            ipld,
+            Ipld::Map(BTreeMap::from([
+                (String::from("bytes"), Ipld::Bytes(b"hi".to_vec())),
+                (String::from("count"), Ipld::Integer(3)),
+                (String::from("link"), Ipld::Link(link)),
+            ]))
+        );
+        assert!(ipld_from_json(json!({ "ratio": 0.5 })).is_err());
+    }
+
+    fn unsigned(commit: &Commit) -> Vec<u8> {
+        serde_ipld_dagcbor::to_vec(&UnsignedCommit {
+            did: &commit.did,
+            rev: &commit.rev,
+            data: &commit.data,
+            prev: &commit.prev,
+            version: commit.version,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn unsigned_commit_keys_are_in_dag_cbor_order() {
+        let commit = signed_commit(&fixtures::key(1), "did:plc:alice", cid_for(b"root"));
+        let bytes = unsigned(&commit);
+        /* a map of five entries, with no room for a sig */
+        assert_eq!(bytes[0], 0xa5);
+        let position = |key: &str| {
+            let mut needle = vec![0x60 + key.len() as u8];
+            needle.extend_from_slice(key.as_bytes());
+            bytes
+                .windows(needle.len())
+                .position(|window| window == needle)
+                .unwrap()
+        };
+        let positions: Vec<usize> = ["did", "rev", "data", "prev", "version"]
+            .into_iter()
+            .map(position)
+            .collect();
+        assert_eq!(positions[0], 1);
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn commit_signed_by_the_atproto_key_is_valid() {
+        let key = fixtures::key(1);
+        let commit = signed_commit(&key, "did:plc:alice", cid_for(b"root"));
+        let check = check_commit(cid_for(b"commit"), &commit, &did_doc("did:plc:alice", &key));
+        assert!(matches!(
+            check.signature,
+            SignatureCheck::Valid("secp256k1")
+        ));
+    }
+
+    #[test]
+    fn tampered_or_misattributed_commits_are_invalid() {
+        let key = fixtures::key(1);
+        let doc = did_doc("did:plc:alice", &key);
+        let mut tampered = signed_commit(&key, "did:plc:alice", cid_for(b"root"));
+        tampered.data = cid_for(b"another root");
+        assert!(matches!(
+            check_commit(cid_for(b"commit"), &tampered, &doc).signature,
+            SignatureCheck::Invalid(_)
+        ));
+
+        let someone_else = signed_commit(&key, "did:plc:mallory", cid_for(b"root"));
+        assert!(matches!(
+            check_commit(cid_for(b"commit"), &someone_else, &doc).signature,
+            SignatureCheck::Invalid(_)
+        ));
+
+        let other_key = fixtures::key(2);
+        let forged = signed_commit(&other_key, "did:plc:alice", cid_for(b"root"));
+        assert!(matches!(
+            check_commit(cid_for(b"commit"), &forged, &doc).signature,
+            SignatureCheck::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn commit_without_a_signing_key_is_unverifiable() {
+        let commit = signed_commit(&fixtures::key(1), "did:plc:alice", cid_for(b"root"));
+        let doc: DidDocument = serde_json::from_value(json!({ "id": "did:plc:alice" })).unwrap();
+        assert!(matches!(
+            check_commit(cid_for(b"commit"), &commit, &doc).signature,
+            SignatureCheck::Unverifiable(_)
+        ));
+    }
+
+    #[test]
+    fn record_in_a_signed_tree_is_proven() {
+        let key = fixtures::key(1);
+        let mut repo = Repo::default();
+        let (data, records) = repo.leaf(&["app.bsky.feed.post/a", "app.bsky.feed.post/b"]);
+        let car = repo.commit(&key, data);
+        let doc = did_doc("did:plc:alice", &key);
+        match check_inclusion(&car, &doc, "app.bsky.feed.post/b") {
+            InclusionCheck::Proven { record, depth, .. } => {
+                assert_eq!((record, depth), (records[1], 1))
+            }
+            other => panic!("expected a proof, got {:?}", other),
+        }
+        assert!(matches!(
+            check_inclusion(&car, &doc, "app.bsky.feed.post/c"),
+            InclusionCheck::Absent { .. }
+        ));
+    }
+
+    #[test]
+    fn proof_fails_without_the_signer_or_the_blocks() {
+        let key = fixtures::key(1);
+        let mut repo = Repo::default();
+        let (data, records) = repo.leaf(&["app.bsky.feed.post/a"]);
+        let mut car = repo.commit(&key, data);
+        let stranger = did_doc("did:plc:alice", &fixtures::key(2));
+        assert!(matches!(
+            check_inclusion(&car, &stranger, "app.bsky.feed.post/a"),
+            InclusionCheck::Failed(_)
+        ));
+
+        let doc = did_doc("did:plc:alice", &key);
+        car.blocks.insert(records[0], b"something else".to_vec());
+        assert!(matches!(
+            check_inclusion(&car, &doc, "app.bsky.feed.post/a"),
+            InclusionCheck::Failed(_)
+        ));
+        car.blocks.remove(&records[0]);
+        assert!(matches!(
+            check_inclusion(&car, &doc, "app.bsky.feed.post/a"),
+            InclusionCheck::Failed(_)
+        ));
+    }
+
+    fn rotation_key() -> k256::ecdsa::SigningKey {
+        fixtures::key(3)
+    }
+
+    // an operation moving the identity to `handle`, signed by `signer`
+    fn operation(
+        prev: Option<&str>,
+        handle: &str,
+        signer: &k256::ecdsa::SigningKey,
+    ) -> PlcOperation {
+        let mut operation = PlcOperation::Operation {
+            rotation_keys: vec![public(&rotation_key()).did_key()],
+            verification_methods: BTreeMap::from([(
+                String::from("atproto"),
+                public(&fixtures::key(1)).did_key(),
+            )]),
+            also_known_as: vec![format!("at://{}", handle)],
+            services: BTreeMap::from([(
+                String::from("atproto_pds"),
+                crate::plc::PlcService {
+                    r#type: String::from("AtprotoPersonalDataServer"),
+                    endpoint: String::from("https://pds.test"),
+                },
+            )]),
+            prev: prev.map(str::to_string),
+            sig: String::new(),
+        };
+        let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(sign(signer, &encode_operation(&operation, false).unwrap()));
+        if let PlcOperation::Operation { sig, .. } = &mut operation {
+            *sig = signature;
+        }
+        operation
+    }
+
+    fn log_entry(did: &str, operation: PlcOperation, nullified: bool) -> PlcLogEntry {
+        PlcLogEntry {
+            did: did.to_string(),
+            cid: cid_for(&encode_operation(&operation, true).unwrap()).to_string(),
+            operation,
+            nullified,
+            created_at: String::from("2024-01-01T00:00:00.000Z"),
+        }
+    }
+
+    // a genesis operation, the DID it derives, and a handle change after it
+    fn plc_log() -> (String, Vec<PlcLogEntry>) {
+        let genesis = operation(None, "alice.old", &rotation_key());
+        let did = plc_did_for(&encode_operation(&genesis, true).unwrap());
+        let genesis = log_entry(&did, genesis, false);
+        let update = operation(Some(&genesis.cid), "alice.test", &rotation_key());
+        let update = log_entry(&did, update, false);
+        (did, vec![genesis, update])
+    }
+
+    #[test]
+    fn genesis_operation_derives_the_did() {
+        let (did, log) = plc_log();
+        assert!(did.starts_with("did:plc:"));
+        assert_eq!(did.len(), "did:plc:".len() + 24);
+        let check = check_plc_log(&did, &log, &did_doc(&did, &fixtures::key(1)));
+        assert!(check.problems.is_empty(), "{:?}", check.problems);
+
+        let (_, mut log) = plc_log();
+        let impostor = "did:plc:aaaaaaaaaaaaaaaaaaaaaaaa";
+        for entry in &mut log {
+            entry.did = impostor.to_string();
+        }
+        let check = check_plc_log(impostor, &log, &did_doc(impostor, &fixtures::key(1)));
+        assert!(check
+            .problems
+            .iter()
+            .any(|problem| problem.contains("is the genesis operation of")));
+    }
+
+    #[test]
+    fn chain_has_to_follow_prev_and_the_rotation_keys() {
+        let (did, mut log) = plc_log();
+        let doc = did_doc(&did, &fixtures::key(1));
+
+        // an operation naming something outside the log as its prev
+        let mut broken = log.clone();
+        broken[1] = log_entry(
+            &did,
+            operation(
+                Some(&cid_for(b"elsewhere").to_string()),
+                "alice.test",
+                &rotation_key(),
+            ),
+            false,
+        );
+        let check = check_plc_log(&did, &broken, &doc);
+        assert!(check
+            .problems
+            .iter()
+            .any(|problem| problem.contains("which doesn't come before it")));
+
+        // the signing key isn't a rotation key, so it can't change the identity
+        log[1] = log_entry(
+            &did,
+            operation(Some(&log[0].cid), "alice.test", &fixtures::key(1)),
+            false,
+        );
+        let check = check_plc_log(&did, &log, &doc);
+        assert!(check
+            .problems
+            .iter()
+            .any(|problem| problem.contains("isn't signed by any of the rotation keys")));
+    }
+
+    #[test]
+    fn nullified_operations_are_left_out_of_the_chain() {
+        let (did, mut log) = plc_log();
+        let overridden = operation(Some(&log[0].cid), "alice.nope", &rotation_key());
+        log.insert(1, log_entry(&did, overridden, true));
+        let check = check_plc_log(&did, &log, &did_doc(&did, &fixtures::key(1)));
+        assert!(check.problems.is_empty(), "{:?}", check.problems);
+        assert_eq!(check.operations, 3);
+    }
+}